@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+use crate::channel::Channel;
+use crate::speed_test::{self, ErrorKind, SpeedTestResult, SpeedTester};
+
+// 单个频道的批量测试结果
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub channel: Channel,
+    pub result: SpeedTestResult,
+}
+
+pub struct BatchRunner {
+    tester: Arc<SpeedTester>,
+    concurrency: usize,
+    verbose: bool,
+}
+
+impl BatchRunner {
+    pub fn new(tester: SpeedTester, concurrency: usize, verbose: bool) -> Self {
+        Self {
+            tester: Arc::new(tester),
+            concurrency: concurrency.max(1),
+            verbose,
+        }
+    }
+
+    // 并发测试所有频道，所有 worker 共享同一个 SpeedTester（及其 reqwest::Client）
+    // 返回结果的顺序与输入频道顺序一致
+    pub async fn run(&self, channels: Vec<Channel>) -> Vec<BatchResult> {
+        let total = channels.len();
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let completed = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::with_capacity(total);
+
+        for channel in channels {
+            let task_channel = channel.clone();
+            let tester = Arc::clone(&self.tester);
+            let semaphore = Arc::clone(&semaphore);
+            let completed = Arc::clone(&completed);
+            let verbose = self.verbose;
+
+            let handle = tokio::spawn(async move {
+                let channel = task_channel;
+                // 信号量不会被关闭，acquire 只会在关闭时失败
+                let _permit = semaphore.acquire_owned().await.expect("semaphore closed");
+
+                // 频道声明了 DRM 许可证时不用测试，直接归类为 DRM 加密
+                let result = match channel.drm_system() {
+                    Some(system) => SpeedTestResult::drm_protected(
+                        &channel.url,
+                        system,
+                        format!("频道声明了 {} 许可证 (KODIPROP license_type)", system),
+                    ),
+                    None => match tester.test_url(&channel.url).await {
+                        Ok(result) => result,
+                        Err(e) => SpeedTestResult::failure(
+                            &channel.url,
+                            "未知",
+                            speed_test::classify_error(&e),
+                            format!("测试失败: {}", e),
+                        ),
+                    },
+                };
+
+                let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                if !verbose {
+                    eprint!("\r  测试进度: {}/{}", done, total);
+                }
+
+                result
+            });
+            handles.push((channel, handle));
+        }
+
+        let results = collect_results(handles).await;
+
+        if !self.verbose && total > 0 {
+            eprintln!();
+        }
+
+        results
+    }
+}
+
+// 按输入顺序等待所有任务；任务 panic 或被取消时该频道记为失败，不从结果中丢掉
+async fn collect_results(handles: Vec<(Channel, JoinHandle<SpeedTestResult>)>) -> Vec<BatchResult> {
+    let mut results = Vec::with_capacity(handles.len());
+    for (channel, handle) in handles {
+        let result = match handle.await {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("\n测试任务异常退出 ({}): {}", channel.url, e);
+                SpeedTestResult::failure(&channel.url, "未知", ErrorKind::Other, format!("测试任务异常退出: {}", e))
+            }
+        };
+        results.push(BatchResult { channel, result });
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn spawn(url: &str, delay_ms: u64, panics: bool) -> (Channel, JoinHandle<SpeedTestResult>) {
+        let channel = Channel::new(url, url);
+        let url = url.to_string();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            if panics {
+                panic!("测试 panic");
+            }
+            SpeedTestResult { url, success: true, ..Default::default() }
+        });
+        (channel, handle)
+    }
+
+    #[tokio::test]
+    async fn test_collect_results() {
+        // 完成顺序与输入顺序相反，中间的任务 panic
+        let handles = vec![spawn("http://a/1", 30, false), spawn("http://a/2", 10, true), spawn("http://a/3", 0, false)];
+        let results = collect_results(handles).await;
+
+        let urls: Vec<&str> = results.iter().map(|batch| batch.channel.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://a/1", "http://a/2", "http://a/3"]);
+        assert!(results[0].result.success && results[2].result.success);
+
+        let failed = &results[1].result;
+        assert!(!failed.success);
+        assert_eq!(failed.url, "http://a/2");
+        assert_eq!(failed.error_kind, Some(ErrorKind::Other));
+        assert!(failed.details.as_deref().unwrap().starts_with("测试任务异常退出"));
+    }
+}
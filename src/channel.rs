@@ -0,0 +1,330 @@
+use anyhow::{Result, anyhow};
+use std::path::Path;
+
+use crate::drm::DrmSystem;
+use crate::m3u_parser;
+
+// 频道列表中的一个条目
+// 除频道名和URL外，其余字段来自 M3U 的 #EXTINF 属性及附加行，TXT 列表中为空
+#[derive(Debug, Clone, Default)]
+pub struct Channel {
+    pub name: String,
+    pub url: String,
+    pub tvg_id: Option<String>,
+    pub tvg_name: Option<String>,
+    pub tvg_logo: Option<String>,
+    pub group_title: Option<String>,
+    pub catchup: Option<String>,
+    pub catchup_source: Option<String>,
+    pub catchup_days: Option<String>,
+    pub extra_attributes: Vec<(String, String)>, // 其他未识别的 #EXTINF 属性，保持原顺序
+    pub vlc_options: Vec<String>,                // #EXTVLCOPT 行（不含前缀）
+    pub kodi_props: Vec<String>,                 // #KODIPROP 行（不含前缀）
+}
+
+impl Channel {
+    pub fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // 频道通过 #KODIPROP:inputstream.adaptive.license_type 声明了 DRM 许可证类型
+    pub fn drm_system(&self) -> Option<DrmSystem> {
+        self.kodi_props.iter().find_map(|prop| {
+            let (key, value) = prop.split_once('=')?;
+            (key.trim() == "inputstream.adaptive.license_type").then(|| DrmSystem::from_license_type(value))
+        })
+    }
+}
+
+// 频道列表格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelListFormat {
+    M3u, // #EXTM3U / #EXTINF 格式
+    Txt, // "频道名,URL" 格式
+}
+
+impl std::fmt::Display for ChannelListFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelListFormat::M3u => write!(f, "M3U"),
+            ChannelListFormat::Txt => write!(f, "TXT"),
+        }
+    }
+}
+
+// 根据内容判断频道列表格式，不依赖文件扩展名
+pub fn detect_format(content: &str) -> ChannelListFormat {
+    let is_m3u = content
+        .lines()
+        .map(|line| line.trim())
+        .any(|line| line.starts_with("#EXTM3U") || line.starts_with("#EXTINF"));
+
+    if is_m3u {
+        ChannelListFormat::M3u
+    } else {
+        ChannelListFormat::Txt
+    }
+}
+
+// 自动识别格式并解析频道列表
+pub fn parse_channel_list(content: &str) -> (ChannelListFormat, Vec<Channel>) {
+    // 去掉可能存在的UTF-8 BOM
+    let content = content.trim_start_matches('\u{feff}');
+    let format = detect_format(content);
+
+    let channels = match format {
+        ChannelListFormat::M3u => m3u_parser::parse_m3u_channels(content),
+        ChannelListFormat::Txt => parse_txt_channels(content),
+    };
+
+    (format, channels)
+}
+
+// 读取频道列表文件（M3U 或 TXT）
+pub fn load_channel_file(path: &Path) -> Result<Vec<Channel>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("无法读取频道列表文件 {}: {}", path.display(), e))?;
+
+    Ok(parse_channel_list(&content).1)
+}
+
+// 保存频道列表，扩展名为 .m3u/.m3u8 时写为 M3U（保留 tvg-* 等属性），否则写为 TXT
+pub fn save_channel_file(path: &Path, channels: &[Channel]) -> Result<()> {
+    let is_m3u = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+        .unwrap_or(false);
+
+    let content = if is_m3u {
+        m3u_parser::to_m3u(channels)
+    } else {
+        to_txt(channels)
+    };
+
+    std::fs::write(path, content)
+        .map_err(|e| anyhow!("无法写入频道列表文件 {}: {}", path.display(), e))
+}
+
+// 按分组归类：返回未分组的频道，以及按首次出现的顺序排列的分组，组内保持原顺序
+pub fn group_by_title(channels: &[Channel]) -> (Vec<&Channel>, Vec<(&str, Vec<&Channel>)>) {
+    let mut ungrouped = Vec::new();
+    let mut groups: Vec<(&str, Vec<&Channel>)> = Vec::new();
+
+    for channel in channels {
+        match channel.group_title.as_deref() {
+            Some(group) => match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, members)) => members.push(channel),
+                None => groups.push((group, vec![channel])),
+            },
+            None => ungrouped.push(channel),
+        }
+    }
+
+    (ungrouped, groups)
+}
+
+// 将频道列表写为 "频道名,URL" 文本
+// 有分组的频道按分组首次出现的顺序归类，写在对应的 "分组名,#genre#" 行之后；
+// 没有分组的频道写在最前面
+pub fn to_txt(channels: &[Channel]) -> String {
+    let mut output = String::new();
+    let (ungrouped, groups) = group_by_title(channels);
+
+    for channel in ungrouped {
+        output.push_str(&format!("{},{}\n", channel.name, channel.url));
+    }
+    for (group, members) in groups {
+        output.push_str(&format!("{},#genre#\n", group));
+        for channel in members {
+            output.push_str(&format!("{},{}\n", channel.name, channel.url));
+        }
+    }
+
+    output
+}
+
+// 解析 "频道名,URL" 格式的文本列表（1-download-filter.sh 的输出格式）
+// 也兼容每行只有一个URL的列表，此时频道名即为URL
+//
+// 支持 genre 格式：
+// 央视频道,#genre#
+// CCTV1,http://a.com/1.m3u8#http://b.com/1.m3u8
+// "分组名,#genre#" 行开启一个分组，之后的频道都属于该分组；
+// 一行中用 # 分隔的多个URL拆分为同名同分组的多个备用源
+pub fn parse_txt_channels(content: &str) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    let mut current_group: Option<String> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        // 跳过空行和注释
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(group) = parse_genre_header(trimmed) {
+            current_group = Some(group.to_string()).filter(|group| !group.is_empty());
+            continue;
+        }
+
+        if let Some((name, urls)) = split_name_url(trimmed) {
+            for url in split_alternate_urls(urls) {
+                let mut channel = Channel::new(name, url);
+                channel.group_title = current_group.clone();
+                channels.push(channel);
+            }
+        }
+    }
+
+    channels
+}
+
+// 识别 "分组名,#genre#" 分组行，返回分组名
+fn parse_genre_header(line: &str) -> Option<&str> {
+    let (name, marker) = line.rsplit_once(',')?;
+
+    if marker.trim().eq_ignore_ascii_case("#genre#") {
+        Some(name.trim())
+    } else {
+        None
+    }
+}
+
+// 拆分用 # 连接的多个URL。只有 # 之后紧跟 "scheme://" 时才视为分隔符，
+// 以免把URL自身的锚点部分误拆开
+fn split_alternate_urls(urls: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+
+    for (pos, _) in urls.match_indices('#') {
+        if pos < start {
+            continue;
+        }
+        let rest = &urls[pos + 1..];
+        let is_url_start = rest
+            .find("://")
+            .map(|scheme_end| {
+                scheme_end > 0
+                    && rest[..scheme_end]
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+            })
+            .unwrap_or(false);
+
+        if is_url_start {
+            result.push(urls[start..pos].trim());
+            start = pos + 1;
+        }
+    }
+    result.push(urls[start..].trim());
+
+    result.into_iter().filter(|url| !url.is_empty()).collect()
+}
+
+// 拆分 "频道名,URL"，频道名本身可能包含逗号，所以取URL协议头之前的最后一个逗号
+fn split_name_url(line: &str) -> Option<(&str, &str)> {
+    let scheme_pos = line.find("://")?;
+
+    match line[..scheme_pos].rfind(',') {
+        Some(comma) => {
+            let name = line[..comma].trim();
+            let url = line[comma + 1..].trim();
+            if name.is_empty() {
+                Some((url, url))
+            } else {
+                Some((name, url))
+            }
+        }
+        None => Some((line, line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_channel_list_detects_m3u() {
+        let content = "#EXTM3U\n#EXTINF:-1 tvg-id=\"cctv1\" group-title=\"央视\",CCTV-1 综合\nhttp://example.com/1.m3u8\nhttp://example.com/2.m3u8\n";
+        let (format, channels) = parse_channel_list(content);
+
+        assert_eq!(format, ChannelListFormat::M3u);
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "CCTV-1 综合");
+        assert_eq!(channels[1].name, "频道1");
+    }
+
+    #[test]
+    fn test_channel_drm_system() {
+        let mut channel = Channel::new("CCTV-5", "http://example.com/manifest.mpd");
+        channel.kodi_props = vec!["inputstream.adaptive.manifest_type=mpd".to_string()];
+        assert_eq!(channel.drm_system(), None);
+
+        channel.kodi_props.push("inputstream.adaptive.license_type=com.widevine.alpha".to_string());
+        assert_eq!(channel.drm_system(), Some(DrmSystem::Widevine));
+    }
+
+    #[test]
+    fn test_parse_txt_channels() {
+        let content = "# 注释\n\nCCTV1,http://example.com/cctv1.m3u8\n广东卫视, http://example.com/gdws\n";
+        let channels = parse_txt_channels(content);
+
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "CCTV1");
+        assert_eq!(channels[0].url, "http://example.com/cctv1.m3u8");
+        assert_eq!(channels[1].name, "广东卫视");
+        assert_eq!(channels[1].url, "http://example.com/gdws");
+    }
+
+    #[test]
+    fn test_parse_txt_channels_edge_cases() {
+        let content = "Sports, HD,http://example.com/a?x=1,2\nhttp://example.com/plain.ts\n";
+        let channels = parse_txt_channels(content);
+
+        assert_eq!(channels.len(), 2);
+        // 频道名中的逗号保留，URL中的逗号也保留
+        assert_eq!(channels[0].name, "Sports, HD");
+        assert_eq!(channels[0].url, "http://example.com/a?x=1,2");
+        // 纯URL行
+        assert_eq!(channels[1].name, "http://example.com/plain.ts");
+        assert_eq!(channels[1].url, "http://example.com/plain.ts");
+    }
+
+    #[test]
+    fn test_parse_genre_txt() {
+        let content = "更新时间,#genre#\n央视频道,#genre#\nCCTV1,http://a.com/1.m3u8#http://b.com/1.m3u8#rtmp://c.com/live\nCCTV2,http://a.com/2.m3u8#anchor\n卫视频道,#GENRE#\n广东卫视,http://a.com/gd.m3u8\n";
+        let channels = parse_txt_channels(content);
+
+        assert_eq!(channels.len(), 5);
+        assert_eq!(channels[0].name, "CCTV1");
+        assert_eq!(channels[0].url, "http://a.com/1.m3u8");
+        assert_eq!(channels[1].url, "http://b.com/1.m3u8");
+        assert_eq!(channels[2].url, "rtmp://c.com/live");
+        assert!(channels[..3].iter().all(|c| c.name == "CCTV1" && c.group_title.as_deref() == Some("央视频道")));
+
+        // URL 自身的锚点不拆分
+        assert_eq!(channels[3].url, "http://a.com/2.m3u8#anchor");
+
+        assert_eq!(channels[4].name, "广东卫视");
+        assert_eq!(channels[4].group_title.as_deref(), Some("卫视频道"));
+    }
+
+    #[test]
+    fn test_to_txt_keeps_groups() {
+        let content = "plain,http://a.com/0\n央视,#genre#\nCCTV1,http://a.com/1\n卫视,#genre#\n广东卫视,http://a.com/2\n";
+        let channels = parse_txt_channels(content);
+        let mut shuffled = vec![channels[2].clone(), channels[1].clone(), channels[0].clone()];
+        shuffled.push(Channel { group_title: Some("央视".to_string()), ..Channel::new("CCTV2", "http://a.com/3") });
+
+        assert_eq!(
+            to_txt(&shuffled),
+            "plain,http://a.com/0\n卫视,#genre#\n广东卫视,http://a.com/2\n央视,#genre#\nCCTV1,http://a.com/1\nCCTV2,http://a.com/3\n"
+        );
+    }
+}
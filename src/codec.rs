@@ -0,0 +1,606 @@
+// 从码流本身识别编码格式、分辨率和帧率，不依赖 FFmpeg：
+// 编码格式来自 PMT 的 stream_type 和描述符，分辨率和帧率来自 H.264 / H.265 的 SPS 或 MPEG-2 的序列头
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    H264,
+    Hevc,
+    Mpeg2, // MPEG-1 / MPEG-2 视频
+    Mpeg4, // MPEG-4 Part 2
+    Avs,   // AVS / AVS+
+    Avs2,
+    Vc1,
+    Mp2,   // MPEG-1 / MPEG-2 音频（Layer I / II / III）
+    Aac,
+    Ac3,
+    Eac3,
+    Dts,
+}
+
+impl Codec {
+    // PMT 中的 stream_type（ISO/IEC 13818-1 表 2-34 及常见的私有类型），
+    // 私有流（0x06）和用户私有类型需要结合描述符判断
+    pub fn from_stream_type(stream_type: u8, descriptors: &[u8]) -> Option<Self> {
+        match stream_type {
+            0x01 | 0x02 => Some(Codec::Mpeg2),
+            0x10 => Some(Codec::Mpeg4),
+            0x1B => Some(Codec::H264),
+            0x24 => Some(Codec::Hevc),
+            0x42 => Some(Codec::Avs),
+            0xD2 => Some(Codec::Avs2),
+            0xEA => Some(Codec::Vc1),
+            0x03 | 0x04 => Some(Codec::Mp2),
+            0x0F | 0x11 | 0x1C => Some(Codec::Aac),
+            0x81 => Some(Codec::Ac3),
+            0x87 => Some(Codec::Eac3),
+            0x82 => Some(Codec::Dts),
+            _ => Self::from_descriptors(descriptors),
+        }
+    }
+
+    // DVB 的 AC-3 / E-AC-3 / AAC 描述符，以及 registration_descriptor 的 format_identifier
+    fn from_descriptors(mut descriptors: &[u8]) -> Option<Self> {
+        while descriptors.len() >= 2 {
+            let (tag, length) = (descriptors[0], descriptors[1] as usize);
+            let body = descriptors.get(2..2 + length)?;
+            let codec = match tag {
+                0x6A => Some(Codec::Ac3),
+                0x7A => Some(Codec::Eac3),
+                0x7C => Some(Codec::Aac),
+                0x7B => Some(Codec::Dts),
+                0x05 => match body.get(..4) {
+                    Some(b"AC-3") => Some(Codec::Ac3),
+                    Some(b"EAC3") => Some(Codec::Eac3),
+                    Some(b"HEVC") => Some(Codec::Hevc),
+                    Some(b"VC-1") => Some(Codec::Vc1),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if codec.is_some() {
+                return codec;
+            }
+            descriptors = &descriptors[2 + length..];
+        }
+        None
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(
+            self,
+            Codec::H264 | Codec::Hevc | Codec::Mpeg2 | Codec::Mpeg4 | Codec::Avs | Codec::Avs2 | Codec::Vc1
+        )
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Codec::H264 => "H.264",
+            Codec::Hevc => "HEVC",
+            Codec::Mpeg2 => "MPEG-2",
+            Codec::Mpeg4 => "MPEG-4",
+            Codec::Avs => "AVS",
+            Codec::Avs2 => "AVS2",
+            Codec::Vc1 => "VC-1",
+            Codec::Mp2 => "MP2",
+            Codec::Aac => "AAC",
+            Codec::Ac3 => "AC-3",
+            Codec::Eac3 => "E-AC-3",
+            Codec::Dts => "DTS",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// 码流的编码信息，TS 中没有视频或没解析到 SPS 时对应字段为空
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MediaInfo {
+    pub video_codec: Option<Codec>,
+    pub audio_codecs: Vec<Codec>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+}
+
+impl MediaInfo {
+    pub fn resolution(&self) -> Option<String> {
+        Some(format!("{}x{}", self.width?, self.height?))
+    }
+
+    // 用于标注频道的简短描述，如 "1080p HEVC"、"576p MPEG-2"，没有视频时为音频格式
+    pub fn label(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(height) = self.height {
+            parts.push(format!("{}p", height));
+        }
+        match self.video_codec {
+            Some(codec) => parts.push(codec.to_string()),
+            None => parts.extend(self.audio_codecs.iter().map(|codec| codec.to_string())),
+        }
+        parts.join(" ")
+    }
+}
+
+// 从 SPS / 序列头得到的视频参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoParameters {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: Option<f64>, // SPS 的 VUI 或序列头中声明的帧率
+}
+
+// 按位读取，支持 Exp-Golomb 编码
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bits(&mut self, count: usize) -> Option<u64> {
+        let mut value = 0u64;
+        for _ in 0..count {
+            let byte = self.data.get(self.position / 8)?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u64;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        self.bits(1).map(|bit| bit == 1)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.position += count;
+        (self.position <= self.data.len() * 8).then_some(())
+    }
+
+    fn ue(&mut self) -> Option<u64> {
+        let mut leading_zeros = 0;
+        while !self.flag()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u64 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i64> {
+        let value = self.ue()? as i64;
+        Some(if value % 2 == 1 { (value + 1) / 2 } else { -(value / 2) })
+    }
+}
+
+// 去掉 NAL 中的防竞争字节（00 00 03 中的 03）
+fn remove_emulation_prevention(nal: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        output.push(byte);
+    }
+    output
+}
+
+// 按 00 00 01 起始码切分 NAL 单元（Annex B）
+pub fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = Vec::new();
+    let mut index = 0;
+    while index + 3 <= data.len() {
+        if data[index] == 0 && data[index + 1] == 0 && data[index + 2] == 1 {
+            starts.push(index + 3);
+            index += 3;
+        } else {
+            index += 1;
+        }
+    }
+
+    let ends: Vec<usize> = starts.iter().skip(1).map(|start| start - 3).chain(std::iter::once(data.len())).collect();
+    starts.into_iter().zip(ends).map(move |(start, end)| {
+        // 去掉下一个四字节起始码多出的 0
+        let mut end = end;
+        while end > start && data[end - 1] == 0 {
+            end -= 1;
+        }
+        &data[start..end]
+    })
+}
+
+// 在基本流数据中查找并解析第一个 SPS（MPEG-2 为序列头）
+pub fn parse_video_parameters(codec: Codec, data: &[u8]) -> Option<VideoParameters> {
+    match codec {
+        Codec::H264 => nal_units(data).find(|nal| nal.first().is_some_and(|header| header & 0x1F == 7)).and_then(parse_h264_sps),
+        Codec::Hevc => nal_units(data).find(|nal| nal.first().is_some_and(|header| (header >> 1) & 0x3F == 33)).and_then(parse_hevc_sps),
+        Codec::Mpeg2 => {
+            let start = data.windows(4).position(|window| window == [0x00, 0x00, 0x01, 0xB3])?;
+            parse_mpeg2_sequence_header(&data[start + 4..])
+        }
+        _ => None,
+    }
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale = 8i64;
+    let mut next_scale = 8i64;
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + reader.se()? + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+// H.264 SPS（ITU-T H.264 7.3.2.1.1），nal 包含一个字节的 NAL 头
+pub fn parse_h264_sps(nal: &[u8]) -> Option<VideoParameters> {
+    let rbsp = remove_emulation_prevention(nal.get(1..)?);
+    let mut reader = BitReader::new(&rbsp);
+
+    let profile_idc = reader.bits(8)?;
+    reader.skip(16)?; // constraint_set 标志和 level_idc
+    reader.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    if matches!(profile_idc, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+        chroma_format_idc = reader.ue()?;
+        if chroma_format_idc == 3 {
+            reader.skip(1)?; // separate_colour_plane_flag
+        }
+        reader.ue()?; // bit_depth_luma_minus8
+        reader.ue()?; // bit_depth_chroma_minus8
+        reader.skip(1)?; // qpprime_y_zero_transform_bypass_flag
+        if reader.flag()? {
+            let count = if chroma_format_idc == 3 { 12 } else { 8 };
+            for index in 0..count {
+                if reader.flag()? {
+                    skip_scaling_list(&mut reader, if index < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    reader.ue()?; // log2_max_frame_num_minus4
+    match reader.ue()? {
+        0 => {
+            reader.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.skip(1)?;
+            reader.se()?;
+            reader.se()?;
+            for _ in 0..reader.ue()? {
+                reader.se()?;
+            }
+        }
+        _ => {}
+    }
+    reader.ue()?; // max_num_ref_frames
+    reader.skip(1)?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = reader.ue()? + 1;
+    let height_in_map_units = reader.ue()? + 1;
+    let frame_mbs_only = reader.flag()?;
+    if !frame_mbs_only {
+        reader.skip(1)?; // mb_adaptive_frame_field_flag
+    }
+    reader.skip(1)?; // direct_8x8_inference_flag
+
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let mut width = width_in_mbs * 16;
+    let mut height = field_factor * height_in_map_units * 16;
+    if reader.flag()? {
+        // 裁剪单位取决于色度采样格式
+        let (crop_x, crop_y) = match chroma_format_idc {
+            0 => (1, field_factor),
+            1 => (2, 2 * field_factor),
+            2 => (2, field_factor),
+            _ => (1, field_factor),
+        };
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        width = width.checked_sub((left + right) * crop_x)?;
+        height = height.checked_sub((top + bottom) * crop_y)?;
+    }
+
+    Some(VideoParameters {
+        width: width as u32,
+        height: height as u32,
+        frame_rate: parse_h264_vui_frame_rate(&mut reader),
+    })
+}
+
+// VUI 中的 timing_info，帧率 = time_scale / (2 * num_units_in_tick)
+fn parse_h264_vui_frame_rate(reader: &mut BitReader) -> Option<f64> {
+    if !reader.flag()? {
+        return None;
+    }
+    if reader.flag()? {
+        // aspect_ratio_info
+        if reader.bits(8)? == 255 {
+            reader.skip(32)?;
+        }
+    }
+    if reader.flag()? {
+        reader.skip(1)?; // overscan_appropriate_flag
+    }
+    if reader.flag()? {
+        reader.skip(4)?; // video_format, video_full_range_flag
+        if reader.flag()? {
+            reader.skip(24)?; // colour_description
+        }
+    }
+    if reader.flag()? {
+        reader.ue()?;
+        reader.ue()?;
+    }
+    if !reader.flag()? {
+        return None;
+    }
+    let num_units_in_tick = reader.bits(32)?;
+    let time_scale = reader.bits(32)?;
+    (num_units_in_tick > 0 && time_scale > 0).then(|| round_frame_rate(time_scale as f64 / (2.0 * num_units_in_tick as f64)))
+}
+
+// H.265 SPS（ITU-T H.265 7.3.2.2）到 conformance_window 为止，帧率由 PTS 推算
+pub fn parse_hevc_sps(nal: &[u8]) -> Option<VideoParameters> {
+    let rbsp = remove_emulation_prevention(nal.get(2..)?);
+    let mut reader = BitReader::new(&rbsp);
+
+    reader.skip(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.bits(3)? as usize;
+    reader.skip(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level：general 部分 88 位 + general_level_idc 8 位
+    reader.skip(96)?;
+    let mut sub_layer_flags = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layer_flags.push((reader.flag()?, reader.flag()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present {
+            reader.skip(88)?;
+        }
+        if level_present {
+            reader.skip(8)?;
+        }
+    }
+
+    reader.ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = reader.ue()?;
+    if chroma_format_idc == 3 {
+        reader.skip(1)?; // separate_colour_plane_flag
+    }
+    let mut width = reader.ue()?;
+    let mut height = reader.ue()?;
+    if reader.flag()? {
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        width = width.checked_sub((left + right) * sub_width)?;
+        height = height.checked_sub((top + bottom) * sub_height)?;
+    }
+
+    Some(VideoParameters {
+        width: width as u32,
+        height: height as u32,
+        frame_rate: None,
+    })
+}
+
+// MPEG-1 / MPEG-2 序列头（00 00 01 B3 之后）：12 位宽、12 位高、4 位宽高比、4 位帧率代码
+fn parse_mpeg2_sequence_header(data: &[u8]) -> Option<VideoParameters> {
+    let mut reader = BitReader::new(data);
+    let width = reader.bits(12)? as u32;
+    let height = reader.bits(12)? as u32;
+    reader.skip(4)?;
+    let frame_rate = match reader.bits(4)? {
+        1 => Some(23.976),
+        2 => Some(24.0),
+        3 => Some(25.0),
+        4 => Some(29.97),
+        5 => Some(30.0),
+        6 => Some(50.0),
+        7 => Some(59.94),
+        8 => Some(60.0),
+        _ => None,
+    };
+    (width > 0 && height > 0).then_some(VideoParameters { width, height, frame_rate })
+}
+
+// 保留两位小数，29.97、59.94 等不会被取整
+pub fn round_frame_rate(frame_rate: f64) -> f64 {
+    (frame_rate * 100.0).round() / 100.0
+}
+
+// 由视频 PES 的 PTS（90 kHz）推算帧率：排序去重后取相邻差值的中位数，兼容 B 帧造成的乱序
+pub fn frame_rate_from_pts(pts: &[u64]) -> Option<f64> {
+    let mut sorted = pts.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut deltas: Vec<u64> = sorted
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        // 跨过 33 位回绕或不连续时差值异常大
+        .filter(|delta| *delta > 0 && *delta < 90_000)
+        .collect();
+    if deltas.len() < 2 {
+        return None;
+    }
+    deltas.sort_unstable();
+    let median = deltas[deltas.len() / 2];
+    Some(round_frame_rate(90_000.0 / median as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试用的位写入，按 Exp-Golomb 编码写 SPS
+    #[derive(Default)]
+    struct BitWriter {
+        bits: Vec<bool>,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u64, count: usize) {
+            for index in (0..count).rev() {
+                self.bits.push((value >> index) & 1 == 1);
+            }
+        }
+
+        fn ue(&mut self, value: u64) {
+            let length = 64 - (value + 1).leading_zeros() as usize;
+            self.bits(0, length - 1);
+            self.bits(value + 1, length);
+        }
+
+        fn bytes(mut self) -> Vec<u8> {
+            // rbsp_stop_one_bit 和对齐
+            self.bits.push(true);
+            while !self.bits.len().is_multiple_of(8) {
+                self.bits.push(false);
+            }
+            // 和编码器一样插入防竞争字节
+            let mut output = Vec::new();
+            let mut zeros = 0;
+            for byte in self.bits.chunks(8).map(|byte| byte.iter().fold(0u8, |value, bit| (value << 1) | *bit as u8)) {
+                if zeros >= 2 && byte <= 0x03 {
+                    output.push(0x03);
+                    zeros = 0;
+                }
+                zeros = if byte == 0 { zeros + 1 } else { 0 };
+                output.push(byte);
+            }
+            output
+        }
+    }
+
+    // 1920x1088 裁剪为 1080，High profile，VUI 声明 25 帧
+    fn h264_sps() -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer.bits(100, 8); // profile_idc
+        writer.bits(0, 8);
+        writer.bits(40, 8); // level_idc
+        writer.ue(0); // sps_id
+        writer.ue(1); // chroma_format_idc
+        writer.ue(0);
+        writer.ue(0);
+        writer.bits(0, 1);
+        writer.bits(0, 1); // seq_scaling_matrix_present_flag
+        writer.ue(0); // log2_max_frame_num_minus4
+        writer.ue(0); // pic_order_cnt_type
+        writer.ue(0);
+        writer.ue(4); // max_num_ref_frames
+        writer.bits(0, 1);
+        writer.ue(119); // 120 个宏块宽
+        writer.ue(67); // 68 个宏块高
+        writer.bits(1, 1); // frame_mbs_only_flag
+        writer.bits(1, 1);
+        writer.bits(1, 1); // frame_cropping_flag
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(4); // 底部裁剪 8 行
+        writer.bits(1, 1); // vui_parameters_present_flag
+        writer.bits(0, 4); // 没有 aspect_ratio / overscan / video_signal / chroma_loc
+        writer.bits(1, 1); // timing_info_present_flag
+        writer.bits(1, 32);
+        writer.bits(50, 32);
+        writer.bits(1, 1);
+
+        let mut nal = vec![0x67];
+        nal.extend(writer.bytes());
+        nal
+    }
+
+    #[test]
+    fn test_h264_sps() {
+        let parameters = parse_h264_sps(&h264_sps()).unwrap();
+        assert_eq!(parameters, VideoParameters { width: 1920, height: 1080, frame_rate: Some(25.0) });
+
+        // 在 Annex B 码流中查找 SPS（前面有 AUD，后面有 PPS）
+        let mut stream = vec![0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1];
+        stream.extend(h264_sps());
+        stream.extend([0, 0, 1, 0x68, 0xEE, 0x3C, 0x80]);
+        assert_eq!(parse_video_parameters(Codec::H264, &stream), Some(parameters));
+    }
+
+    #[test]
+    fn test_hevc_sps() {
+        let mut writer = BitWriter::default();
+        writer.bits(0, 4); // sps_video_parameter_set_id
+        writer.bits(0, 3); // max_sub_layers_minus1
+        writer.bits(1, 1);
+        writer.bits(0x01, 8); // general_profile_space / tier / profile_idc (Main)
+        writer.bits(0x6000_0000, 32);
+        writer.bits(0, 48);
+        writer.bits(120, 8); // general_level_idc
+        writer.ue(0); // sps_seq_parameter_set_id
+        writer.ue(1); // chroma_format_idc
+        writer.ue(3840);
+        writer.ue(2176);
+        writer.bits(1, 1); // conformance_window_flag
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(8); // 底部裁剪 16 行
+
+        let mut nal = vec![0x42, 0x01];
+        nal.extend(writer.bytes());
+        let parameters = parse_hevc_sps(&nal).unwrap();
+        assert_eq!((parameters.width, parameters.height), (3840, 2160));
+    }
+
+    #[test]
+    fn test_mpeg2_sequence_header_and_pts() {
+        let data = [0x00, 0x00, 0x01, 0xB3, 0x2D, 0x02, 0x40, 0x23, 0xFF, 0xFF];
+        let parameters = parse_video_parameters(Codec::Mpeg2, &data).unwrap();
+        assert_eq!(parameters, VideoParameters { width: 720, height: 576, frame_rate: Some(25.0) });
+
+        // B 帧导致 PTS 乱序，29.97 帧的间隔为 3003
+        let pts: Vec<u64> = [0, 3, 1, 2, 6, 4, 5, 7].iter().map(|index| 900_000 + index * 3003).collect();
+        assert_eq!(frame_rate_from_pts(&pts), Some(29.97));
+        assert_eq!(frame_rate_from_pts(&[0]), None);
+    }
+
+    #[test]
+    fn test_codec_and_label() {
+        assert_eq!(Codec::from_stream_type(0x24, &[]), Some(Codec::Hevc));
+        assert_eq!(Codec::from_stream_type(0x06, &[0x0A, 0x04, b'c', b'h', b'i', 0x00, 0x6A, 0x01, 0x00]), Some(Codec::Ac3));
+        assert_eq!(Codec::from_stream_type(0x06, &[0x05, 0x04, b'E', b'A', b'C', b'3']), Some(Codec::Eac3));
+        assert_eq!(Codec::from_stream_type(0x06, &[0x59, 0x00]), None);
+
+        let info = MediaInfo {
+            video_codec: Some(Codec::Hevc),
+            audio_codecs: vec![Codec::Aac],
+            width: Some(1920),
+            height: Some(1080),
+            frame_rate: Some(25.0),
+        };
+        assert_eq!(info.label(), "1080p HEVC");
+        assert_eq!(info.resolution().as_deref(), Some("1920x1080"));
+        let audio_only = MediaInfo { audio_codecs: vec![Codec::Mp2], ..Default::default() };
+        assert_eq!(audio_only.label(), "MP2");
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::filter::FilterConfig;
+use crate::policy::PolicyConfig;
+use crate::speed_test::TestConfig;
+
+// 配置文件，支持 TOML 和 YAML 格式（按扩展名区分）
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub filter: FilterConfig,
+    pub policy: PolicyConfig,
+    pub test: TestConfig,
+}
+
+impl AppConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("无法读取配置文件 {}: {}", path.display(), e))?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match extension.as_str() {
+            "yaml" | "yml" => serde_yaml::from_str(&content)
+                .map_err(|e| anyhow!("配置文件 {} 格式错误: {}", path.display(), e)),
+            _ => toml::from_str(&content)
+                .map_err(|e| anyhow!("配置文件 {} 格式错误: {}", path.display(), e)),
+        }
+    }
+}
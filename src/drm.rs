@@ -0,0 +1,77 @@
+use serde::Serialize;
+
+// 需要许可证才能播放的 DRM 系统，这类流无法被普通播放器直接播放
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrmSystem {
+    FairPlay,
+    Widevine,
+    PlayReady,
+    #[serde(rename = "sample_aes")]
+    SampleAes, // METHOD=SAMPLE-AES 但没有声明已知的 KEYFORMAT
+    Other,     // 其他 DRM，如未知的 KEYFORMAT 或 license_type
+}
+
+// 各 DRM 系统在 CENC / DASH 中的 SystemID
+const WIDEVINE_UUID: &str = "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed";
+const PLAYREADY_UUID: &str = "urn:uuid:9a04f079-9840-4286-ab92-e65be0885f95";
+
+impl DrmSystem {
+    // EXT-X-KEY 的 KEYFORMAT，identity（默认值）不是 DRM
+    pub fn from_keyformat(keyformat: &str) -> Option<Self> {
+        let keyformat = keyformat.trim().to_lowercase();
+        match keyformat.as_str() {
+            "" | "identity" => None,
+            "com.apple.streamingkeydelivery" => Some(DrmSystem::FairPlay),
+            WIDEVINE_UUID | "com.widevine" | "com.widevine.alpha" => Some(DrmSystem::Widevine),
+            PLAYREADY_UUID | "com.microsoft.playready" => Some(DrmSystem::PlayReady),
+            _ => Some(DrmSystem::Other),
+        }
+    }
+
+    // #KODIPROP:inputstream.adaptive.license_type 的值
+    pub fn from_license_type(license_type: &str) -> Self {
+        let license_type = license_type.trim().to_lowercase();
+        if license_type.contains("widevine") || license_type == WIDEVINE_UUID {
+            DrmSystem::Widevine
+        } else if license_type.contains("playready") || license_type == PLAYREADY_UUID {
+            DrmSystem::PlayReady
+        } else if license_type.starts_with("com.apple.fps") {
+            DrmSystem::FairPlay
+        } else {
+            DrmSystem::Other
+        }
+    }
+}
+
+impl std::fmt::Display for DrmSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DrmSystem::FairPlay => write!(f, "FairPlay"),
+            DrmSystem::Widevine => write!(f, "Widevine"),
+            DrmSystem::PlayReady => write!(f, "PlayReady"),
+            DrmSystem::SampleAes => write!(f, "SAMPLE-AES"),
+            DrmSystem::Other => write!(f, "未知DRM"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drm_system_detection() {
+        assert_eq!(DrmSystem::from_keyformat("identity"), None);
+        assert_eq!(DrmSystem::from_keyformat("com.apple.streamingkeydelivery"), Some(DrmSystem::FairPlay));
+        assert_eq!(
+            DrmSystem::from_keyformat("urn:uuid:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED"),
+            Some(DrmSystem::Widevine)
+        );
+        assert_eq!(DrmSystem::from_keyformat("com.microsoft.playready"), Some(DrmSystem::PlayReady));
+
+        assert_eq!(DrmSystem::from_license_type("com.widevine.alpha"), DrmSystem::Widevine);
+        assert_eq!(DrmSystem::from_license_type("com.microsoft.playready.recommendation"), DrmSystem::PlayReady);
+        assert_eq!(DrmSystem::from_license_type("org.w3.clearkey"), DrmSystem::Other);
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::batch::BatchResult;
+use crate::channel::{self, Channel};
+
+// 导出播放列表时的排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    Host,    // 按 host 出现次数倒序（同 3-sort-by-host.sh）
+    Speed,   // 按速度从快到慢
+    Latency, // 按延迟从低到高
+    Name,    // 按频道名
+}
+
+// 选出测试通过的频道，排序并按URL去重（保留排序靠前的一个）
+pub fn select_channels(results: &[BatchResult], sort: SortOrder) -> Vec<Channel> {
+    let mut passed: Vec<&BatchResult> = results.iter().filter(|item| item.result.passed()).collect();
+
+    match sort {
+        SortOrder::Host => {
+            let mut host_count: HashMap<String, usize> = HashMap::new();
+            for item in &passed {
+                *host_count.entry(url_host(&item.channel.url)).or_default() += 1;
+            }
+            passed.sort_by(|a, b| {
+                let (host_a, host_b) = (url_host(&a.channel.url), url_host(&b.channel.url));
+                host_count[&host_b]
+                    .cmp(&host_count[&host_a])
+                    .then_with(|| host_a.cmp(&host_b))
+                    .then_with(|| a.channel.name.cmp(&b.channel.name))
+            });
+        }
+        SortOrder::Speed => {
+            passed.sort_by(|a, b| b.result.speed_kbps.total_cmp(&a.result.speed_kbps));
+        }
+        SortOrder::Latency => {
+            // 延迟未知（-1）的排在最后
+            passed.sort_by(|a, b| {
+                let delay_a = if a.result.delay_ms < 0.0 { f64::MAX } else { a.result.delay_ms };
+                let delay_b = if b.result.delay_ms < 0.0 { f64::MAX } else { b.result.delay_ms };
+                delay_a.total_cmp(&delay_b)
+            });
+        }
+        SortOrder::Name => {
+            passed.sort_by(|a, b| a.channel.name.cmp(&b.channel.name));
+        }
+    }
+
+    let mut seen_urls = HashSet::new();
+    passed
+        .into_iter()
+        .filter(|item| seen_urls.insert(item.channel.url.clone()))
+        .map(|item| item.channel.clone())
+        .collect()
+}
+
+// 按分组归类，分组顺序为首次出现的顺序，组内保持原顺序，未分组的频道排在最前
+pub fn group_channels(channels: Vec<Channel>) -> Vec<Channel> {
+    let (ungrouped, groups) = channel::group_by_title(&channels);
+    ungrouped
+        .into_iter()
+        .chain(groups.into_iter().flat_map(|(_, members)| members))
+        .cloned()
+        .collect()
+}
+
+// 把逐个测试过子播放列表的频道地址换成实测能承载的最高码率子播放列表
+pub fn pin_best_variants(results: &[BatchResult]) -> Vec<BatchResult> {
+    results
+        .iter()
+        .map(|item| {
+            let mut item = item.clone();
+            if let Some(url) = &item.result.selected_variant {
+                item.channel.url = url.clone();
+            }
+            item
+        })
+        .collect()
+}
+
+// 导出播放列表，扩展名为 .m3u/.m3u8 时写为 M3U（保留 tvg-* 和 group-title），否则写为 genre TXT
+// genre TXT 中同一分组的频道必须写在一起，所以分组优先于排序：分组按其中排序最靠前的频道排列，
+// 排序只在分组内生效
+pub fn export_playlist(path: &Path, results: &[BatchResult], sort: SortOrder, best_variant: bool) -> Result<usize> {
+    let channels = if best_variant {
+        select_channels(&pin_best_variants(results), sort)
+    } else {
+        select_channels(results, sort)
+    };
+    let channels = group_channels(channels);
+    channel::save_channel_file(path, &channels)?;
+    Ok(channels.len())
+}
+
+fn url_host(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.host_str().map(|host| match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            })
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speed_test::{ErrorKind, SpeedTestResult};
+
+    fn item(name: &str, url: &str, group: Option<&str>, speed_kbps: f64, delay_ms: f64) -> BatchResult {
+        let result = if speed_kbps > 0.0 {
+            SpeedTestResult {
+                url: url.to_string(),
+                success: true,
+                delay_ms,
+                speed_kbps,
+                protocol_type: "HTTP直连".to_string(),
+                ..Default::default()
+            }
+        } else {
+            SpeedTestResult::failure(url, "HTTP直连", ErrorKind::Other, "下载失败".to_string())
+        };
+
+        BatchResult {
+            channel: Channel {
+                group_title: group.map(|group| group.to_string()),
+                ..Channel::new(name, url)
+            },
+            result,
+        }
+    }
+
+    fn names(channels: &[Channel]) -> Vec<&str> {
+        channels.iter().map(|channel| channel.name.as_str()).collect()
+    }
+
+    fn results() -> Vec<BatchResult> {
+        vec![
+            item("B台", "http://b.com/1", None, 3000.0, 50.0),
+            item("A台", "http://a.com/1", None, 1000.0, -1.0),
+            item("C台", "http://a.com/2", None, 5000.0, 20.0),
+            item("失败", "http://c.com/1", None, 0.0, -1.0),
+            item("A台备用", "http://a.com/1", None, 2000.0, 10.0),
+        ]
+    }
+
+    #[test]
+    fn test_select_channels_sorting() {
+        let results = results();
+
+        assert_eq!(names(&select_channels(&results, SortOrder::Host)), vec!["A台", "C台", "B台"]);
+        assert_eq!(names(&select_channels(&results, SortOrder::Speed)), vec!["C台", "B台", "A台备用"]);
+        assert_eq!(names(&select_channels(&results, SortOrder::Latency)), vec!["A台备用", "C台", "B台"]);
+        assert_eq!(names(&select_channels(&results, SortOrder::Name)), vec!["A台", "B台", "C台"]);
+    }
+
+    #[test]
+    fn test_group_channels() {
+        let results = vec![
+            item("广东卫视", "http://a.com/1", Some("卫视"), 1000.0, 1.0),
+            item("CCTV1", "http://a.com/2", Some("央视"), 1000.0, 1.0),
+            item("其他", "http://a.com/3", None, 1000.0, 1.0),
+            item("湖南卫视", "http://a.com/4", Some("卫视"), 1000.0, 1.0),
+        ];
+        let grouped = group_channels(select_channels(&results, SortOrder::Latency));
+
+        assert_eq!(names(&grouped), vec!["其他", "广东卫视", "湖南卫视", "CCTV1"]);
+    }
+
+    #[test]
+    fn test_sort_within_groups() {
+        let results = vec![
+            item("广东卫视", "http://a.com/1", Some("卫视"), 1000.0, 1.0),
+            item("CCTV1", "http://a.com/2", Some("央视"), 4000.0, 1.0),
+            item("湖南卫视", "http://a.com/3", Some("卫视"), 3000.0, 1.0),
+            item("CCTV2", "http://a.com/4", Some("央视"), 2000.0, 1.0),
+        ];
+        let grouped = group_channels(select_channels(&results, SortOrder::Speed));
+
+        // 央视分组有最快的频道，排在前面；各分组内按速度排序
+        assert_eq!(names(&grouped), vec!["CCTV1", "CCTV2", "湖南卫视", "广东卫视"]);
+    }
+}
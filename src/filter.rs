@@ -0,0 +1,312 @@
+use anyhow::{Result, anyhow};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use url::Url;
+
+use crate::channel::Channel;
+
+// 规则匹配的频道字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchField {
+    Name,   // 频道名
+    Group,  // 分组（group-title / #genre#）
+    Host,   // URL 主机名（含端口）
+    Scheme, // URL 协议，如 http、rtmp
+    Path,   // URL 路径，如 /rtp/239.1.1.1:1234
+    Url,    // 完整 URL
+}
+
+impl MatchField {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MatchField::Name => "name",
+            MatchField::Group => "group",
+            MatchField::Host => "host",
+            MatchField::Scheme => "scheme",
+            MatchField::Path => "path",
+            MatchField::Url => "url",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Include, // 只保留匹配的频道（白名单）
+    Exclude, // 去掉匹配的频道（黑名单）
+}
+
+// 一条过滤规则：任一字段匹配任一模式即视为匹配
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub action: RuleAction,
+    pub fields: Vec<MatchField>,
+    pub patterns: Vec<String>,
+    // 模式为正则表达式，否则按关键词子串匹配
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default = "default_ignore_case")]
+    pub ignore_case: bool,
+}
+
+fn default_ignore_case() -> bool {
+    true
+}
+
+// 配置文件中的 [filter] 部分，规则按顺序依次应用
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub rules: Vec<FilterRule>,
+}
+
+// 每条规则去掉的频道数
+#[derive(Debug, Clone)]
+pub struct RuleReport {
+    pub rule: String,
+    pub removed: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterReport {
+    pub input: usize,
+    pub output: usize,
+    pub rules: Vec<RuleReport>,
+}
+
+enum Matcher {
+    Keywords(Vec<String>),
+    Regex(Vec<Regex>),
+}
+
+struct CompiledRule {
+    label: String,
+    action: RuleAction,
+    fields: Vec<MatchField>,
+    ignore_case: bool,
+    matcher: Matcher,
+}
+
+impl CompiledRule {
+    fn compile(rule: &FilterRule) -> Result<Self> {
+        let label = rule.name.clone().unwrap_or_else(|| {
+            let action = match rule.action {
+                RuleAction::Include => "include",
+                RuleAction::Exclude => "exclude",
+            };
+            let fields: Vec<&str> = rule.fields.iter().map(|field| field.as_str()).collect();
+            format!("{} {}: {}", action, fields.join(","), rule.patterns.join("|"))
+        });
+
+        if rule.fields.is_empty() {
+            return Err(anyhow!("过滤规则 \"{}\" 未指定匹配字段", label));
+        }
+
+        let matcher = if rule.regex {
+            let regexes = rule
+                .patterns
+                .iter()
+                .map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(rule.ignore_case)
+                        .build()
+                        .map_err(|e| anyhow!("过滤规则 \"{}\" 的正则表达式无效: {}", label, e))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Matcher::Regex(regexes)
+        } else if rule.ignore_case {
+            Matcher::Keywords(rule.patterns.iter().map(|pattern| pattern.to_lowercase()).collect())
+        } else {
+            Matcher::Keywords(rule.patterns.clone())
+        };
+
+        Ok(Self {
+            label,
+            action: rule.action,
+            fields: rule.fields.clone(),
+            ignore_case: rule.ignore_case,
+            matcher,
+        })
+    }
+
+    fn matches(&self, channel: &Channel) -> bool {
+        let parsed_url = Url::parse(&channel.url).ok();
+
+        self.fields.iter().any(|field| {
+            let value = field_value(channel, parsed_url.as_ref(), *field);
+            match &self.matcher {
+                Matcher::Regex(regexes) => regexes.iter().any(|regex| regex.is_match(&value)),
+                Matcher::Keywords(keywords) => {
+                    let value = if self.ignore_case { value.to_lowercase() } else { value };
+                    keywords.iter().any(|keyword| value.contains(keyword.as_str()))
+                }
+            }
+        })
+    }
+}
+
+fn field_value(channel: &Channel, parsed_url: Option<&Url>, field: MatchField) -> String {
+    match field {
+        MatchField::Name => channel.name.clone(),
+        MatchField::Group => channel.group_title.clone().unwrap_or_default(),
+        MatchField::Url => channel.url.clone(),
+        MatchField::Host => parsed_url
+            .and_then(|url| {
+                url.host_str().map(|host| match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                })
+            })
+            .unwrap_or_default(),
+        MatchField::Scheme => match parsed_url {
+            Some(url) => url.scheme().to_string(),
+            None => channel.url.split("://").next().unwrap_or("").to_string(),
+        },
+        MatchField::Path => parsed_url.map(|url| url.path().to_string()).unwrap_or_default(),
+    }
+}
+
+pub struct FilterEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl FilterEngine {
+    pub fn new(config: &FilterConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(CompiledRule::compile)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // 按顺序应用所有规则，每个频道只计入第一条去掉它的规则
+    pub fn apply(&self, channels: Vec<Channel>) -> (Vec<Channel>, FilterReport) {
+        let input = channels.len();
+        let mut removed = vec![0usize; self.rules.len()];
+
+        let kept: Vec<Channel> = channels
+            .into_iter()
+            .filter(|channel| {
+                for (index, rule) in self.rules.iter().enumerate() {
+                    let matched = rule.matches(channel);
+                    let keep = match rule.action {
+                        RuleAction::Include => matched,
+                        RuleAction::Exclude => !matched,
+                    };
+                    if !keep {
+                        removed[index] += 1;
+                        return false;
+                    }
+                }
+                true
+            })
+            .collect();
+
+        let report = FilterReport {
+            input,
+            output: kept.len(),
+            rules: self
+                .rules
+                .iter()
+                .zip(removed)
+                .map(|(rule, removed)| RuleReport {
+                    rule: rule.label.clone(),
+                    removed,
+                })
+                .collect(),
+        };
+
+        (kept, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str, url: &str, group: Option<&str>) -> Channel {
+        Channel {
+            group_title: group.map(|group| group.to_string()),
+            ..Channel::new(name, url)
+        }
+    }
+
+    #[test]
+    fn test_filter_rules_and_report() {
+        let config: FilterConfig = toml::from_str(
+            r#"
+            [[rules]]
+            name = "blacklist"
+            action = "exclude"
+            fields = ["name", "url"]
+            patterns = ["swf", "drm", "audio", "伴音"]
+
+            [[rules]]
+            name = "http only"
+            action = "include"
+            fields = ["scheme"]
+            patterns = ["^https?$"]
+            regex = true
+
+            [[rules]]
+            name = "whitelist"
+            action = "include"
+            fields = ["name", "group", "path"]
+            patterns = ["广东", "CCTV5", "/rtp/"]
+            "#,
+        )
+        .unwrap();
+
+        let engine = FilterEngine::new(&config).unwrap();
+        let channels = vec![
+            channel("广东卫视", "http://a.com/gd.m3u8", None),
+            channel("cctv5 体育", "https://a.com/5.m3u8", None),
+            channel("CCTV5 伴音", "http://a.com/5a.m3u8", None),
+            channel("Some DRM", "http://a.com/DRM/x.mpd", None),
+            channel("广东体育", "rtmp://a.com/live", None),
+            channel("翡翠台", "http://b.com:8800/rtp/239.1.1.1:1234", None),
+            channel("新闻", "http://a.com/news.m3u8", Some("广东频道")),
+            channel("CCTV1", "http://a.com/1.m3u8", None),
+        ];
+
+        let (kept, report) = engine.apply(channels);
+        let names: Vec<&str> = kept.iter().map(|c| c.name.as_str()).collect();
+
+        assert_eq!(names, vec!["广东卫视", "cctv5 体育", "翡翠台", "新闻"]);
+        assert_eq!(report.input, 8);
+        assert_eq!(report.output, 4);
+        let removed: Vec<usize> = report.rules.iter().map(|r| r.removed).collect();
+        assert_eq!(removed, vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn test_case_sensitive_and_invalid_regex() {
+        let rule = FilterRule {
+            name: None,
+            action: RuleAction::Exclude,
+            fields: vec![MatchField::Host],
+            patterns: vec!["Example".to_string()],
+            regex: false,
+            ignore_case: false,
+        };
+        let engine = FilterEngine::new(&FilterConfig { rules: vec![rule.clone()] }).unwrap();
+        let (kept, _) = engine.apply(vec![channel("a", "http://example.com/a", None)]);
+        assert_eq!(kept.len(), 1);
+
+        let invalid = FilterRule {
+            regex: true,
+            patterns: vec!["(".to_string()],
+            ..rule
+        };
+        assert!(FilterEngine::new(&FilterConfig { rules: vec![invalid] }).is_err());
+    }
+}
@@ -1,198 +1,768 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use reqwest::Client;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use url::Url;
 
+use crate::drm::DrmSystem;
+use crate::speed_test::TestError;
+
 pub struct M3u8Parser {
     verbose: bool,
 }
 
+// EXT-X-BYTERANGE / EXT-X-MAP 中的字节范围，offset 缺省时紧接上一个片段
+// （媒体片段的 offset 在解析时已经补全，EXT-X-MAP 缺省时从 0 开始）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub length: u64,
+    pub offset: Option<u64>,
+}
+
+impl ByteRange {
+    pub fn start(&self) -> u64 {
+        self.offset.unwrap_or(0)
+    }
+
+    // HTTP Range 请求头的值，结束位置包含在内
+    pub fn header_value(&self) -> String {
+        format!("bytes={}-{}", self.start(), self.start() + self.length.saturating_sub(1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMethod {
+    None,
+    Aes128,
+    SampleAes,
+    Other(String),
+}
+
+// EXT-X-KEY / EXT-X-SESSION-KEY
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    pub method: KeyMethod,
+    pub uri: Option<String>,
+    // 显式指定的 128 位 IV，缺省时 AES-128 以片段的媒体序号作为 IV
+    pub iv: Option<[u8; 16]>,
+    pub keyformat: Option<String>,
+    pub keyformatversions: Option<String>,
+}
+
+impl Key {
+    // SAMPLE-AES 或 KEYFORMAT 为 FairPlay / Widevine / PlayReady 等的密钥需要 DRM 许可证，
+    // 只有 METHOD=AES-128 且 KEYFORMAT 为 identity（或省略）的密钥可以直接获取
+    pub fn drm_system(&self) -> Option<DrmSystem> {
+        if self.method == KeyMethod::None {
+            return None;
+        }
+        if let Some(system) = self.keyformat.as_deref().and_then(DrmSystem::from_keyformat) {
+            return Some(system);
+        }
+        match self.method {
+            KeyMethod::SampleAes => Some(DrmSystem::SampleAes),
+            KeyMethod::Other(_) => Some(DrmSystem::Other),
+            _ => None,
+        }
+    }
+}
+
+// EXT-X-MAP，fMP4 等格式的初始化片段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Map {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MediaSegment {
+    pub uri: String,
+    pub duration: f64,
+    pub title: Option<String>,
+    pub byte_range: Option<ByteRange>,
+    pub discontinuity: bool,
+    // 对该片段生效的密钥和初始化片段（EXT-X-KEY / EXT-X-MAP 会一直作用到下一次出现）
+    pub key: Option<Key>,
+    pub map: Option<Map>,
+    pub program_date_time: Option<String>,
+    // LL-HLS：组成该片段的部分片段（EXT-X-PART 出现在所属片段的 URI 之前）
+    pub parts: Vec<PartialSegment>,
+}
+
+impl MediaSegment {
+    // 用于日志的简要描述，如 "5.005 秒, 第一段, 2024-01-01T00:00:00+00:00, 不连续"
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("{} 秒", self.duration)];
+        parts.extend(self.title.clone());
+        parts.extend(self.program_date_time.clone());
+        if self.discontinuity {
+            parts.push("不连续".to_string());
+        }
+        parts.join(", ")
+    }
+}
+
+// LL-HLS 的 EXT-X-PART 部分片段
+#[derive(Debug, Clone, Default)]
+pub struct PartialSegment {
+    pub uri: String,
+    pub duration: f64,
+    pub byte_range: Option<ByteRange>,
+    pub gap: bool,                      // GAP=YES 表示该部分片段不可用
+}
+
+// LL-HLS 的 EXT-X-SERVER-CONTROL
+#[derive(Debug, Clone, Default)]
+pub struct ServerControl {
+    pub can_block_reload: bool,         // 支持 _HLS_msn / _HLS_part 阻塞刷新
+    pub hold_back: Option<f64>,
+    pub part_hold_back: Option<f64>,
+}
+
+// LL-HLS 的 EXT-X-PRELOAD-HINT，服务器即将发布的部分片段或初始化片段
+#[derive(Debug, Clone)]
+pub struct PreloadHint {
+    pub hint_type: String, // PART 或 MAP
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistType {
+    Event,
+    Vod,
+}
+
+// 媒体播放列表
+#[derive(Debug, Clone, Default)]
+pub struct MediaPlaylist {
+    pub version: Option<u32>,
+    pub target_duration: f64,
+    pub media_sequence: u64,
+    pub discontinuity_sequence: u64,
+    pub playlist_type: Option<PlaylistType>,
+    pub end_list: bool,
+    pub independent_segments: bool,
+    pub segments: Vec<MediaSegment>,
+    // LL-HLS：EXT-X-PART-INF 的 PART-TARGET、服务器控制、
+    // 最后一个还没有完成的片段已经发布的部分片段，以及预加载提示
+    pub part_target: Option<f64>,
+    pub server_control: Option<ServerControl>,
+    pub trailing_parts: Vec<PartialSegment>,
+    pub preload_hints: Vec<PreloadHint>,
+}
+
+impl MediaPlaylist {
+    // 没有 EXT-X-ENDLIST 且不是 VOD 的播放列表为直播
+    pub fn is_live(&self) -> bool {
+        !self.end_list && self.playlist_type != Some(PlaylistType::Vod)
+    }
+
+    pub fn total_duration(&self) -> f64 {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+
+    // 声明了 PART-TARGET 的播放列表为低延迟 HLS
+    pub fn is_low_latency(&self) -> bool {
+        self.part_target.is_some()
+    }
+
+    // 下一个要发布的部分片段 (片段序号, 部分片段序号)，用于 _HLS_msn / _HLS_part 阻塞刷新
+    pub fn next_part(&self) -> (u64, usize) {
+        (self.media_sequence + self.segments.len() as u64, self.trailing_parts.len())
+    }
+
+    pub fn drm_system(&self) -> Option<DrmSystem> {
+        self.segments.iter().filter_map(|segment| segment.key.as_ref()).find_map(Key::drm_system)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u64,
+    pub height: u64,
+}
+
+// EXT-X-STREAM-INF 描述的子播放列表
+#[derive(Debug, Clone, Default)]
+pub struct VariantStream {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub codecs: Option<String>,
+    pub resolution: Option<Resolution>,
+    pub frame_rate: Option<f64>,
+    pub audio: Option<String>,
+    pub video: Option<String>,
+    pub subtitles: Option<String>,
+    pub closed_captions: Option<String>,
+}
+
+impl VariantStream {
+    // 用于日志和结果详情的简要描述，如 "4000000 bps, 1920x1080, 25fps, avc1.640028"
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("{} bps", self.bandwidth)];
+        if let Some(average) = self.average_bandwidth {
+            parts.push(format!("平均 {} bps", average));
+        }
+        if let Some(resolution) = self.resolution {
+            parts.push(format!("{}x{}", resolution.width, resolution.height));
+        }
+        if let Some(frame_rate) = self.frame_rate {
+            parts.push(format!("{}fps", frame_rate));
+        }
+        if let Some(codecs) = &self.codecs {
+            parts.push(codecs.clone());
+        }
+        let groups = [
+            ("音频组", &self.audio),
+            ("视频组", &self.video),
+            ("字幕组", &self.subtitles),
+            ("CC组", &self.closed_captions),
+        ];
+        for (label, group) in groups {
+            if let Some(group) = group {
+                parts.push(format!("{} {}", label, group));
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaType {
+    Audio,
+    Video,
+    Subtitles,
+    ClosedCaptions,
+}
+
+// EXT-X-MEDIA 描述的备选媒体（单独的音轨、字幕等），没有 URI 时表示已包含在子播放列表中
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    pub media_type: MediaType,
+    pub group_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    pub uri: Option<String>,
+    pub default: bool,
+    pub autoselect: bool,
+    pub forced: bool,
+    pub channels: Option<String>,
+}
+
+// 主播放列表
+#[derive(Debug, Clone, Default)]
+pub struct MasterPlaylist {
+    pub version: Option<u32>,
+    pub independent_segments: bool,
+    pub variants: Vec<VariantStream>,
+    pub i_frame_variants: Vec<VariantStream>,
+    pub renditions: Vec<Rendition>,
+    pub session_keys: Vec<Key>,
+}
+
+impl MasterPlaylist {
+    // 播放器会为该分组选择的备选媒体：优先 DEFAULT=YES，其次 AUTOSELECT=YES，都没有时取分组中的第一个
+    pub fn rendition_for(&self, media_type: MediaType, group_id: &str) -> Option<&Rendition> {
+        let group: Vec<&Rendition> = self
+            .renditions
+            .iter()
+            .filter(|rendition| rendition.media_type == media_type && rendition.group_id == group_id)
+            .collect();
+
+        group
+            .iter()
+            .find(|rendition| rendition.default)
+            .or_else(|| group.iter().find(|rendition| rendition.autoselect))
+            .or_else(|| group.first())
+            .copied()
+    }
+
+    // 声明码率最高的子播放列表，码率相同时取先出现的
+    pub fn best_variant(&self) -> Option<&VariantStream> {
+        self.variants.iter().fold(None, |best: Option<&VariantStream>, variant| match best {
+            Some(best) if best.bandwidth >= variant.bandwidth => Some(best),
+            _ => Some(variant),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+// 递归解析的结果
+pub struct ParsedM3u8 {
+    pub url: String,                     // 最终媒体播放列表的 URL
+    pub media: MediaPlaylist,
+    pub variant: Option<VariantStream>,  // 从主播放列表中选中的子播放列表
+    pub master: Option<MasterPlaylist>,  // 最外层的主播放列表
+}
+
+impl ParsedM3u8 {
+    // 主播放列表的 EXT-X-SESSION-KEY 或媒体播放列表中片段的密钥需要 DRM 时返回对应的 DRM 系统
+    pub fn drm_system(&self) -> Option<DrmSystem> {
+        self.master
+            .iter()
+            .flat_map(|master| master.session_keys.iter())
+            .find_map(Key::drm_system)
+            .or_else(|| self.media.drm_system())
+    }
+}
+
 impl M3u8Parser {
     pub fn new(verbose: bool) -> Self {
         Self { verbose }
     }
 
-    pub async fn parse_m3u8(&self, m3u8_url: &str, client: &Client) -> Result<Vec<String>> {
-        use std::collections::VecDeque;
+    // 从给定URL开始解析，遇到主播放列表时跟随码率最高的子播放列表，直到得到媒体播放列表
+    pub async fn parse_m3u8(&self, m3u8_url: &str, client: &Client) -> Result<ParsedM3u8> {
+        let mut current_url = m3u8_url.to_string();
+        let mut variant = None;
+        let mut master_playlist = None;
+        let mut visited_urls = HashSet::new();
 
-        let mut playlist_queue = VecDeque::new();
-        playlist_queue.push_back(m3u8_url.to_string());
-        let mut all_segments = Vec::new();
-        let mut visited_urls = std::collections::HashSet::new();
-
-        while let Some(current_url) = playlist_queue.pop_front() {
+        loop {
             // 避免循环访问同一个URL
-            if visited_urls.contains(&current_url) {
-                continue;
+            if !visited_urls.insert(current_url.clone()) {
+                return Err(anyhow!("播放列表循环引用: {}", current_url));
             }
-            visited_urls.insert(current_url.clone());
 
             if self.verbose {
                 println!("解析播放列表: {}", current_url);
             }
 
-            // 获取M3U8文件内容
-            let response = client
-                .get(&current_url)
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                if self.verbose {
-                    println!("无法获取M3U8文件 {}: {}", current_url, response.status());
-                }
-                continue;
-            }
-
-            let content = response.text().await?;
+            let content = self.fetch_playlist(&current_url, client).await?;
 
             if self.verbose && current_url == m3u8_url {
-                println!("M3U8文件内容预览:\n{}", &content[..content.len().min(500)]);
+                println!("M3U8文件内容预览:\n{}", preview(&content, 500));
             }
 
-            // 解析M3U8内容
-            let (segments, is_master, best_playlist_url) = self.parse_m3u8_content(&content, &current_url)?;
+            match parse_playlist(&content, &current_url)? {
+                Playlist::Master(master) => {
+                    let best = match master.best_variant() {
+                        Some(best) => best.clone(),
+                        None => {
+                            if self.verbose {
+                                println!("主播放列表中未找到有效的播放流");
+                            }
+                            return Err(TestError::NoSegments.into());
+                        }
+                    };
 
-            if is_master {
-                // 主播放列表：将最佳子播放列表加入队列
-                if let Some(playlist_url) = best_playlist_url {
                     if self.verbose {
-                        println!("发现主播放列表，添加子播放列表到队列: {}", playlist_url);
+                        println!(
+                            "发现主播放列表{}，共 {} 个子播放列表{}",
+                            version_label(master.version),
+                            master.variants.len(),
+                            if master.independent_segments { "，片段可独立解码" } else { "" }
+                        );
+                        for variant in &master.variants {
+                            println!("  {} ({})", variant.uri, variant.describe());
+                        }
+                        println!("选择码率最高的子播放列表: {}", best.uri);
                     }
-                    playlist_queue.push_back(playlist_url);
-                } else {
+                    current_url = best.uri.clone();
+                    variant = Some(best);
+                    master_playlist.get_or_insert(master);
+                }
+                Playlist::Media(media) => {
                     if self.verbose {
-                        println!("主播放列表中未找到有效的播放流");
+                        let discontinuities = media.segments.iter().filter(|segment| segment.discontinuity).count();
+                        println!(
+                            "发现媒体播放列表{}，{} 个媒体片段，目标时长 {} 秒，起始序号 {}，{}{}{}",
+                            version_label(media.version),
+                            media.segments.len(),
+                            media.target_duration,
+                            media.media_sequence,
+                            if media.is_live() { "直播" } else { "点播" },
+                            if discontinuities > 0 || media.discontinuity_sequence > 0 {
+                                format!("，{} 处不连续（不连续序号 {}）", discontinuities, media.discontinuity_sequence)
+                            } else {
+                                String::new()
+                            },
+                            if media.independent_segments { "，片段可独立解码" } else { "" }
+                        );
                     }
+                    return Ok(ParsedM3u8 {
+                        url: current_url,
+                        media,
+                        variant,
+                        master: master_playlist,
+                    });
                 }
-            } else {
-                // 媒体播放列表：添加片段到结果
-                if self.verbose {
-                    println!("发现媒体播放列表，{} 个媒体片段", segments.len());
-                }
-                all_segments.extend(segments);
             }
         }
+    }
 
-        if self.verbose {
-            println!("总共解析得到 {} 个媒体片段", all_segments.len());
+    // 重新获取媒体播放列表，用于直播刷新
+    pub async fn fetch_media_playlist(&self, url: &str, client: &Client) -> Result<MediaPlaylist> {
+        let content = self.fetch_playlist(url, client).await?;
+        match parse_playlist(&content, url)? {
+            Playlist::Media(media) => Ok(media),
+            Playlist::Master(_) => Err(anyhow!("期望媒体播放列表，但得到主播放列表: {}", url)),
+        }
+    }
+
+    async fn fetch_playlist(&self, url: &str, client: &Client) -> Result<String> {
+        let response = client.get(url).send().await?;
+
+        if !response.status().is_success() {
+            if self.verbose {
+                println!("无法获取M3U8文件 {}: {}", url, response.status());
+            }
+            return Err(TestError::HttpStatus(response.status()).into());
         }
 
-        Ok(all_segments)
+        let content = response.text().await?;
+        if self.verbose && !has_header(&content) {
+            println!("播放列表开头不是 #EXTM3U，按其中的标签继续解析: {}", url);
+        }
+        Ok(content)
     }
+}
 
-    fn parse_m3u8_content(&self, content: &str, base_url: &str) -> Result<(Vec<String>, bool, Option<String>)> {
-        let lines: Vec<&str> = content.lines().collect();
-        let mut segments = Vec::new();
-        let mut is_master_playlist = false;
-        let mut best_bandwidth = 0u64;
-        let mut best_playlist_url: Option<String> = None;
+fn preview(content: &str, max_len: usize) -> &str {
+    let mut end = content.len().min(max_len);
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    &content[..end]
+}
 
-        let base_url_obj = Url::parse(base_url)?;
-        let _base_path = format!(
-            "{}://{}{}",
-            base_url_obj.scheme(),
-            base_url_obj.host_str().unwrap_or(""),
-            base_url_obj.path().rsplit('/').nth(1).unwrap_or("")
-        );
+fn version_label(version: Option<u32>) -> String {
+    version.map(|version| format!("（版本 {}）", version)).unwrap_or_default()
+}
 
-        for (i, line) in lines.iter().enumerate() {
-            let trimmed = line.trim();
+// 第一个非空行是否为 #EXTM3U
+fn has_header(content: &str) -> bool {
+    content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| line.starts_with("#EXTM3U"))
+}
 
-            // 跳过空行和注释
-            if trimmed.is_empty() || trimmed.starts_with('#') {
-                // 检查是否为主播放列表
-                if trimmed.contains("EXT-X-STREAM-INF") {
-                    is_master_playlist = true;
+// 解析 M3U8 文本，所有 URI 都会按 base_url 解析为完整 URL
+// 有些 IPTV 服务器在 #EXTM3U 之前输出注释或其他内容，没有 #EXTM3U 开头但有 #EXTINF / #EXT-X-STREAM-INF 标签时也按播放列表解析
+pub fn parse_playlist(content: &str, base_url: &str) -> Result<Playlist> {
+    let content = content.trim_start_matches('\u{feff}');
+    if !has_header(content) && !content.contains("#EXTINF") && !content.contains("#EXT-X-STREAM-INF") {
+        return Err(anyhow!("不是有效的M3U8播放列表（缺少 #EXTM3U）"));
+    }
 
-                    // 提取码率信息
-                    if let Some(bandwidth) = self.extract_bandwidth(trimmed) {
-                        if self.verbose {
-                            println!("发现播放流，码率: {}", bandwidth);
-                        }
+    // m3u8-rs 要求内容以 #EXTM3U 开头：#EXTM3U（没有时为第一个标签）之前的内容不当作 URI，
+    // 去掉空行和每行首尾的空白后重新补上 #EXTM3U
+    let start = if content.lines().any(|line| line.trim().starts_with("#EXTM3U")) { "#EXTM3U" } else { "#" };
+    let mut normalized = String::from("#EXTM3U\n");
+    for line in content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .skip_while(|line| !line.starts_with(start))
+        .filter(|line| !line.starts_with("#EXTM3U"))
+    {
+        normalized.push_str(line);
+        normalized.push('\n');
+    }
 
-                        // 查找下一行的URL
-                        if let Some(next_line) = lines.get(i + 1) {
-                            let next_trimmed = next_line.trim();
-                            if !next_trimmed.is_empty() && !next_trimmed.starts_with('#') {
-                                let playlist_url = self.resolve_url(next_trimmed, base_url);
-
-                                
-                                if bandwidth > best_bandwidth {
-                                    best_bandwidth = bandwidth;
-                                    best_playlist_url = Some(playlist_url);
-                                }
-                            }
-                        }
-                    }
-                }
-                continue;
-            }
+    if m3u8_rs::is_master_playlist(normalized.as_bytes()) {
+        let master = m3u8_rs::parse_master_playlist_res(normalized.as_bytes())
+            .map_err(|_| anyhow!("无法解析M3U8主播放列表"))?;
+        Ok(Playlist::Master(convert_master(master, base_url)))
+    } else {
+        // m3u8-rs 会丢弃最后一个 URI 之后的标签，而 LL-HLS 正在生成的片段的 EXT-X-PART 和
+        // EXT-X-PRELOAD-HINT 就在那里，所以在末尾补一个占位 URI，让这些标签落在占位片段上
+        normalized.push_str(TRAILING_PLACEHOLDER);
+        normalized.push('\n');
+        let media = m3u8_rs::parse_media_playlist_res(normalized.as_bytes())
+            .map_err(|_| anyhow!("无法解析M3U8媒体播放列表"))?;
+        Ok(Playlist::Media(convert_media(media, base_url)))
+    }
+}
 
-            // 媒体片段URL
-            if !is_master_playlist {
-                let segment_url = self.resolve_url(trimmed, base_url);
-                segments.push(segment_url);
-            }
+const TRAILING_PLACEHOLDER: &str = "iptv-speed-test-trailing-tags";
+
+fn convert_master(playlist: m3u8_rs::MasterPlaylist, base_url: &str) -> MasterPlaylist {
+    let mut master = MasterPlaylist {
+        version: playlist.version.map(|version| version as u32),
+        independent_segments: playlist.independent_segments,
+        session_keys: playlist.session_key.into_iter().map(|session_key| convert_key(session_key.0, base_url)).collect(),
+        ..Default::default()
+    };
+
+    // 没有紧跟 URI 行的 EXT-X-STREAM-INF 忽略
+    for variant in playlist.variants.into_iter().filter(|variant| !variant.uri.is_empty()) {
+        let is_i_frame = variant.is_i_frame;
+        let variant = VariantStream {
+            uri: resolve_url(&variant.uri, base_url),
+            bandwidth: variant.bandwidth,
+            average_bandwidth: variant.average_bandwidth,
+            codecs: variant.codecs,
+            resolution: variant.resolution.map(|resolution| Resolution {
+                width: resolution.width,
+                height: resolution.height,
+            }),
+            frame_rate: variant.frame_rate,
+            audio: variant.audio,
+            video: variant.video,
+            subtitles: variant.subtitles,
+            closed_captions: match variant.closed_captions {
+                Some(m3u8_rs::ClosedCaptionGroupId::GroupId(group) | m3u8_rs::ClosedCaptionGroupId::Other(group)) => Some(group),
+                Some(m3u8_rs::ClosedCaptionGroupId::None) | None => None,
+            },
+        };
+        if is_i_frame {
+            master.i_frame_variants.push(variant);
+        } else {
+            master.variants.push(variant);
+        }
+    }
+
+    master.renditions = playlist
+        .alternatives
+        .into_iter()
+        .filter_map(|media| {
+            let media_type = match media.media_type {
+                m3u8_rs::AlternativeMediaType::Audio => MediaType::Audio,
+                m3u8_rs::AlternativeMediaType::Video => MediaType::Video,
+                m3u8_rs::AlternativeMediaType::Subtitles => MediaType::Subtitles,
+                m3u8_rs::AlternativeMediaType::ClosedCaptions => MediaType::ClosedCaptions,
+                m3u8_rs::AlternativeMediaType::Other(_) => return None,
+            };
+            Some(Rendition {
+                media_type,
+                group_id: media.group_id,
+                name: media.name,
+                language: media.language,
+                uri: media.uri.map(|uri| resolve_url(&uri, base_url)),
+                default: media.default,
+                autoselect: media.autoselect,
+                forced: media.forced,
+                channels: media.channels,
+            })
+        })
+        .collect();
+
+    master
+}
+
+fn convert_media(playlist: m3u8_rs::MediaPlaylist, base_url: &str) -> MediaPlaylist {
+    let mut media = MediaPlaylist {
+        version: playlist.version.map(|version| version as u32),
+        target_duration: playlist.target_duration as f64,
+        media_sequence: playlist.media_sequence,
+        discontinuity_sequence: playlist.discontinuity_sequence,
+        playlist_type: match playlist.playlist_type {
+            Some(m3u8_rs::MediaPlaylistType::Event) => Some(PlaylistType::Event),
+            Some(m3u8_rs::MediaPlaylistType::Vod) => Some(PlaylistType::Vod),
+            _ => None,
+        },
+        end_list: playlist.end_list,
+        independent_segments: playlist.independent_segments,
+        ..Default::default()
+    };
+
+    // m3u8-rs 只把 EXT-X-KEY / EXT-X-MAP 放在紧跟其后的片段上，这里让它们一直作用到下一次出现
+    let mut current_key: Option<Key> = None;
+    let mut current_map: Option<Map> = None;
+    // 上一个片段的 URI 和字节范围结束位置，用于补全省略的 offset
+    let mut previous_range_end: Option<(String, u64)> = None;
+
+    for segment in playlist.segments {
+        let parts = apply_extension_tags(&mut media, &segment.unknown_tags, &mut current_key, base_url);
+        if segment.uri == TRAILING_PLACEHOLDER {
+            // 播放列表末尾没有 URI 的部分片段属于正在生成的片段
+            media.trailing_parts = parts;
+            break;
+        }
+
+        if let Some(key) = segment.key {
+            current_key = (key.method != m3u8_rs::KeyMethod::None).then(|| convert_key(key, base_url));
+        }
+        if let Some(map) = segment.map {
+            current_map = Some(Map {
+                uri: resolve_url(&map.uri, base_url),
+                byte_range: map.byte_range.map(convert_byte_range),
+            });
         }
 
-        Ok((segments, is_master_playlist, best_playlist_url))
+        let uri = resolve_url(&segment.uri, base_url);
+        let byte_range = segment.byte_range.map(|range| ByteRange {
+            length: range.length,
+            offset: range.offset.or_else(|| match &previous_range_end {
+                Some((previous_uri, end)) if *previous_uri == uri => Some(*end),
+                _ => Some(0),
+            }),
+        });
+        previous_range_end = byte_range.map(|range| (uri.clone(), range.start() + range.length));
+
+        media.segments.push(MediaSegment {
+            uri,
+            duration: seconds(segment.duration),
+            title: segment.title.map(|title| title.trim().to_string()).filter(|title| !title.is_empty()),
+            byte_range,
+            discontinuity: segment.discontinuity,
+            key: current_key.clone(),
+            map: current_map.clone(),
+            program_date_time: segment.program_date_time.map(|time| time.to_rfc3339()),
+            parts,
+        });
     }
 
-    fn extract_bandwidth(&self, line: &str) -> Option<u64> {
-        // 查找 BANDWIDTH 参数
-        let bandwidth_regex = regex::Regex::new(r"BANDWIDTH=(\d+)").ok()?;
+    media
+}
 
-        if let Some(captures) = bandwidth_regex.captures(line) {
-            if let Some(bandwidth_str) = captures.get(1) {
-                return bandwidth_str.as_str().parse().ok();
+// 处理 m3u8-rs 不认识、原样保留在片段上的标签，返回其中的 LL-HLS 部分片段
+fn apply_extension_tags(
+    media: &mut MediaPlaylist,
+    tags: &[m3u8_rs::ExtTag],
+    current_key: &mut Option<Key>,
+    base_url: &str,
+) -> Vec<PartialSegment> {
+    let mut parts = Vec::new();
+
+    for tag in tags {
+        let value = tag.rest.as_deref().unwrap_or("");
+        match tag.tag.as_str() {
+            // m3u8-rs 只接受整数目标时长
+            "X-TARGETDURATION" => media.target_duration = value.trim().parse().unwrap_or(media.target_duration),
+            // m3u8-rs 6 把不带 IV 的 METHOD=NONE 当作无法解析的标签
+            "X-KEY" if parse_attributes(value).get("METHOD").is_some_and(|method| method == "NONE") => *current_key = None,
+            "X-PART-INF" => {
+                media.part_target = parse_attributes(value).get("PART-TARGET").and_then(|target| target.parse().ok());
+            }
+            "X-SERVER-CONTROL" => {
+                let attributes = parse_attributes(value);
+                let seconds = |name: &str| attributes.get(name).and_then(|value| value.parse().ok());
+                media.server_control = Some(ServerControl {
+                    can_block_reload: attributes.get("CAN-BLOCK-RELOAD").is_some_and(|value| value == "YES"),
+                    hold_back: seconds("HOLD-BACK"),
+                    part_hold_back: seconds("PART-HOLD-BACK"),
+                });
             }
+            "X-PART" => {
+                let attributes = parse_attributes(value);
+                if let Some(uri) = attributes.get("URI") {
+                    parts.push(PartialSegment {
+                        uri: resolve_url(uri, base_url),
+                        duration: attributes.get("DURATION").and_then(|duration| duration.parse().ok()).unwrap_or(0.0),
+                        byte_range: attributes.get("BYTERANGE").and_then(|range| parse_byte_range(range)),
+                        gap: attributes.get("GAP").is_some_and(|value| value == "YES"),
+                    });
+                }
+            }
+            "X-PRELOAD-HINT" => {
+                let attributes = parse_attributes(value);
+                if let (Some(hint_type), Some(uri)) = (attributes.get("TYPE"), attributes.get("URI")) {
+                    media.preload_hints.push(PreloadHint {
+                        hint_type: hint_type.clone(),
+                        uri: resolve_url(uri, base_url),
+                    });
+                }
+            }
+            _ => {}
         }
+    }
+
+    parts
+}
 
-        None
+fn convert_key(key: m3u8_rs::Key, base_url: &str) -> Key {
+    Key {
+        method: match key.method {
+            m3u8_rs::KeyMethod::None => KeyMethod::None,
+            m3u8_rs::KeyMethod::AES128 => KeyMethod::Aes128,
+            m3u8_rs::KeyMethod::SampleAES => KeyMethod::SampleAes,
+            m3u8_rs::KeyMethod::Other(other) => KeyMethod::Other(other),
+        },
+        uri: key.uri.map(|uri| resolve_url(&uri, base_url)),
+        iv: key.iv.as_deref().and_then(parse_iv),
+        keyformat: key.keyformat,
+        keyformatversions: key.keyformatversions,
     }
+}
 
-    fn resolve_url(&self, url: &str, base_url: &str) -> String {
-        // 如果是完整的URL，直接返回
-        if url.starts_with("http://") || url.starts_with("https://") {
-            return url.to_string();
-        }
+fn convert_byte_range(range: m3u8_rs::ByteRange) -> ByteRange {
+    ByteRange {
+        length: range.length,
+        offset: range.offset,
+    }
+}
+
+// m3u8-rs 以 f32 保存 EXTINF 时长，按十进制文本转换，避免 5.005 变成 5.005000114
+fn seconds(duration: f32) -> f64 {
+    duration.to_string().parse().unwrap_or(duration as f64)
+}
 
-        // 解析基础URL
-        let base_url_obj = match Url::parse(base_url) {
-            Ok(url) => url,
-            Err(_) => return url.to_string(), // 如果解析失败，返回原始URL
+// 解析 m3u8-rs 不认识的 LL-HLS 标签的属性列表，如 DURATION=1.0,URI="part.mp4",INDEPENDENT=YES
+// 属性名统一为大写，带引号的值去掉引号
+fn parse_attributes(text: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() {
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
         };
+        let name = name.trim().to_uppercase();
+        let after = after.trim_start();
 
-        // 处理绝对相对路径（以/开头）
-        if url.starts_with('/') {
-            format!(
-                "{}://{}{}",
-                base_url_obj.scheme(),
-                base_url_obj.host_str().unwrap_or("localhost"),
-                url
-            )
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            }
         } else {
-            // 相对路径：使用基础URL的目录路径
-            let base_path = base_url_obj
-                .path()
-                .rsplit('/')
-                .skip(1) // 跳过文件名，获取目录路径
-                .collect::<Vec<&str>>()
-                .iter()
-                .rev()
-                .copied()
-                .collect::<Vec<&str>>()
-                .join("/");
-
-            // 构建完整的基础路径（scheme://host + base_path）
-            let full_base_path = if base_path.is_empty() || base_path == "/" {
-                format!("{}://{}", base_url_obj.scheme(), base_url_obj.host_str().unwrap_or("localhost"))
-            } else {
-                format!("{}://{}/{}", base_url_obj.scheme(), base_url_obj.host_str().unwrap_or("localhost"), base_path.trim_start_matches('/'))
-            };
+            match after.find(',') {
+                Some(end) => (after[..end].trim(), &after[end..]),
+                None => (after.trim(), ""),
+            }
+        };
 
-            format!("{}/{}", full_base_path.trim_end_matches('/'), url.trim_start_matches('/'))
-        }
+        attributes.insert(name, value.to_string());
+        rest = remaining.trim_start().trim_start_matches(',').trim_start();
+    }
+
+    attributes
+}
+
+// 格式为 0x 开头的 32 位十六进制数，格式不对时视为没有指定
+fn parse_iv(value: &str) -> Option<[u8; 16]> {
+    let hex = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X"))?;
+    if hex.len() != 32 {
+        return None;
+    }
+
+    let mut iv = [0u8; 16];
+    for (index, byte) in iv.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    Some(iv)
+}
+
+// EXT-X-PART 的 BYTERANGE 属性，格式为 <长度>[@<偏移>]
+fn parse_byte_range(value: &str) -> Option<ByteRange> {
+    let (length, offset) = match value.trim().split_once('@') {
+        Some((length, offset)) => (length, Some(offset.parse().ok()?)),
+        None => (value.trim(), None),
+    };
+
+    Some(ByteRange {
+        length: length.parse().ok()?,
+        offset,
+    })
+}
+
+// 按 base_url 解析相对地址（保留端口），解析失败时返回原始地址
+pub fn resolve_url(url: &str, base_url: &str) -> String {
+    match Url::parse(base_url).and_then(|base| base.join(url)) {
+        Ok(resolved) => resolved.to_string(),
+        Err(_) => url.to_string(),
     }
 }
 
@@ -201,45 +771,236 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_extract_bandwidth() {
-        let parser = M3u8Parser::new(false);
+    fn test_parse_low_latency_playlist() {
+        let content = r#"#EXTM3U
+#EXT-X-VERSION:9
+#EXT-X-TARGETDURATION:4
+#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=3.0,CAN-SKIP-UNTIL=24
+#EXT-X-PART-INF:PART-TARGET=1.0
+#EXT-X-MEDIA-SEQUENCE:266
+#EXTINF:4.0,
+fileSequence266.mp4
+#EXT-X-PART:DURATION=1.0,URI="filePart267.0.mp4",INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI="filePart267.1.mp4"
+#EXT-X-PART:DURATION=1.0,URI="filePart267.2.mp4"
+#EXT-X-PART:DURATION=1.0,URI="filePart267.3.mp4"
+#EXTINF:4.0,
+fileSequence267.mp4
+#EXT-X-PART:DURATION=1.0,URI="filePart268.0.mp4",INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI="filePart268.1.mp4",BYTERANGE="1000@0"
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI="filePart268.2.mp4"
+"#;
+        let Playlist::Media(media) = parse_playlist(content, "http://example.com/ll/index.m3u8").unwrap() else {
+            panic!("应解析为媒体播放列表");
+        };
 
-        assert_eq!(
-            parser.extract_bandwidth("EXT-X-STREAM-INF:BANDWIDTH=1280000"),
-            Some(1280000)
-        );
+        assert!(media.is_low_latency());
+        assert_eq!(media.part_target, Some(1.0));
+        let server_control = media.server_control.as_ref().unwrap();
+        assert!(server_control.can_block_reload);
+        assert_eq!(server_control.part_hold_back, Some(3.0));
+
+        assert_eq!(media.segments.len(), 2);
+        assert!(media.segments[0].parts.is_empty());
+        assert_eq!(media.segments[1].parts.len(), 4);
+        assert!(!media.segments[1].parts[0].gap);
+        assert_eq!(media.trailing_parts.len(), 2);
+        assert_eq!(media.trailing_parts[1].byte_range, Some(ByteRange { length: 1000, offset: Some(0) }));
+        assert_eq!(media.next_part(), (268, 2));
+        assert_eq!(media.preload_hints[0].uri, "http://example.com/ll/filePart268.2.mp4");
+    }
 
-        assert_eq!(
-            parser.extract_bandwidth("EXT-X-STREAM-INF:BANDWIDTH=2560000,RESOLUTION=1920x1080"),
-            Some(2560000)
-        );
+    #[test]
+    fn test_drm_keys() {
+        let content = r#"#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXT-X-KEY:METHOD=SAMPLE-AES,URI="skd://key1",KEYFORMAT="com.apple.streamingkeydelivery",KEYFORMATVERSIONS="1"
+#EXTINF:6,
+seg1.ts
+"#;
+        let Playlist::Media(media) = parse_playlist(content, "http://example.com/index.m3u8").unwrap() else {
+            panic!("应解析为媒体播放列表");
+        };
+        assert_eq!(media.drm_system(), Some(DrmSystem::FairPlay));
 
-        assert_eq!(
-            parser.extract_bandwidth("EXTINF:10.0,"),
-            None
-        );
+        let sample_aes = content.replace(r#",KEYFORMAT="com.apple.streamingkeydelivery",KEYFORMATVERSIONS="1""#, "");
+        let Playlist::Media(media) = parse_playlist(&sample_aes, "http://example.com/index.m3u8").unwrap() else {
+            panic!("应解析为媒体播放列表");
+        };
+        assert_eq!(media.drm_system(), Some(DrmSystem::SampleAes));
     }
 
     #[test]
-    fn test_resolve_url() {
-        let parser = M3u8Parser::new(false);
+    fn test_parse_iv() {
+        let iv = parse_iv("0x000102030405060708090A0B0C0D0E0F").unwrap();
+        assert_eq!(iv, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+        assert!(parse_iv("000102030405060708090A0B0C0D0E0F").is_none());
+        assert!(parse_iv("0x0A").is_none());
+        assert!(parse_iv("0x000102030405060708090A0B0C0D0EZZ").is_none());
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let attributes = parse_attributes("BANDWIDTH=1280000");
+        assert_eq!(attributes.get("BANDWIDTH").map(String::as_str), Some("1280000"));
+
+        let attributes = parse_attributes(r#"BANDWIDTH=2560000,CODECS="avc1.640028,mp4a.40.2",RESOLUTION=1920x1080"#);
+        assert_eq!(attributes.get("BANDWIDTH").map(String::as_str), Some("2560000"));
+        assert_eq!(attributes.get("CODECS").map(String::as_str), Some("avc1.640028,mp4a.40.2"));
+        assert_eq!(attributes.get("RESOLUTION").map(String::as_str), Some("1920x1080"));
+
+        assert!(!parse_attributes("10.0,").contains_key("BANDWIDTH"));
+    }
 
+    #[test]
+    fn test_resolve_url() {
         // 完整URL
         assert_eq!(
-            parser.resolve_url("https://example.com/segment.ts", "https://base.com/playlist.m3u8"),
+            resolve_url("https://example.com/segment.ts", "https://base.com/playlist.m3u8"),
             "https://example.com/segment.ts"
         );
 
         // 相对URL
         assert_eq!(
-            parser.resolve_url("segment.ts", "https://example.com/path/playlist.m3u8"),
+            resolve_url("segment.ts", "https://example.com/path/playlist.m3u8"),
             "https://example.com/path/segment.ts"
         );
 
         // 绝对相对URL
         assert_eq!(
-            parser.resolve_url("/segment.ts", "https://example.com/path/playlist.m3u8"),
+            resolve_url("/segment.ts", "https://example.com/path/playlist.m3u8"),
             "https://example.com/segment.ts"
         );
+
+        // 保留端口和上级目录
+        assert_eq!(
+            resolve_url("../hd/index.m3u8?token=1", "http://example.com:8080/live/sd/index.m3u8"),
+            "http://example.com:8080/live/hd/index.m3u8?token=1"
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_master_playlist() {
+        let content = r#"#EXTM3U
+#EXT-X-VERSION:4
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",URI="audio/en.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="中文",LANGUAGE="zh",DEFAULT=YES,AUTOSELECT=YES,CHANNELS="2",URI="audio/zh.m3u8"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID="subs",NAME="中文字幕",FORCED=YES,URI="subs/zh.m3u8"
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID="cc",NAME="CC1",INSTREAM-ID="CC1"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS="avc1.4d401e,mp4a.40.2",AUDIO="aac"
+sd/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4000000,AVERAGE-BANDWIDTH=3500000,RESOLUTION=1920x1080,FRAME-RATE=25.000,CODECS="avc1.640028,mp4a.40.2",AUDIO="aac"
+hd/index.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=200000,URI="iframe.m3u8"
+"#;
+        let Playlist::Master(master) = parse_playlist(content, "http://example.com:8080/live/master.m3u8").unwrap() else {
+            panic!("应解析为主播放列表");
+        };
+
+        assert_eq!(master.version, Some(4));
+        assert_eq!(master.variants.len(), 2);
+        assert_eq!(master.i_frame_variants.len(), 1);
+
+        let best = master.best_variant().unwrap();
+        assert_eq!(best.uri, "http://example.com:8080/live/hd/index.m3u8");
+        assert_eq!(best.bandwidth, 4000000);
+        assert_eq!(best.average_bandwidth, Some(3500000));
+        assert_eq!(best.resolution, Some(Resolution { width: 1920, height: 1080 }));
+        assert_eq!(best.frame_rate, Some(25.0));
+        assert_eq!(best.codecs.as_deref(), Some("avc1.640028,mp4a.40.2"));
+        assert_eq!(best.audio.as_deref(), Some("aac"));
+
+        assert_eq!(master.renditions.len(), 4);
+        let audio = master.rendition_for(MediaType::Audio, "aac").unwrap();
+        assert_eq!(audio.name, "中文");
+        assert_eq!(audio.uri.as_deref(), Some("http://example.com:8080/live/audio/zh.m3u8"));
+        assert_eq!(audio.channels.as_deref(), Some("2"));
+        let subtitles = master.rendition_for(MediaType::Subtitles, "subs").unwrap();
+        assert!(subtitles.forced);
+        assert!(master.rendition_for(MediaType::ClosedCaptions, "cc").unwrap().uri.is_none());
+        assert!(master.rendition_for(MediaType::Audio, "ac3").is_none());
+    }
+
+    #[test]
+    fn test_parse_media_playlist() {
+        let content = r#"#EXTM3U
+#EXT-X-VERSION:6
+#EXT-X-TARGETDURATION:6
+#EXT-X-MEDIA-SEQUENCE:1024
+#EXT-X-MAP:URI="init.mp4",BYTERANGE="720@0"
+#EXT-X-KEY:METHOD=AES-128,URI="https://keys.example.com/k1",IV=0x0000000000000000000000000000000A
+#EXTINF:5.005,第一段
+#EXT-X-BYTERANGE:1000@720
+main.mp4
+#EXTINF:6.0,
+#EXT-X-BYTERANGE:2000
+main.mp4
+#EXT-X-DISCONTINUITY
+#EXT-X-KEY:METHOD=NONE
+#EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:00Z
+#EXTINF:4,
+/ad/seg.ts
+#EXT-X-ENDLIST
+"#;
+        let Playlist::Media(media) = parse_playlist(content, "http://example.com/vod/index.m3u8").unwrap() else {
+            panic!("应解析为媒体播放列表");
+        };
+
+        assert_eq!(media.target_duration, 6.0);
+        assert_eq!(media.media_sequence, 1024);
+        assert!(media.end_list);
+        assert!(!media.is_live());
+        assert_eq!(media.segments.len(), 3);
+        assert!((media.total_duration() - 15.005).abs() < 1e-9);
+
+        let first = &media.segments[0];
+        assert_eq!(first.uri, "http://example.com/vod/main.mp4");
+        assert_eq!(first.title.as_deref(), Some("第一段"));
+        assert_eq!(first.byte_range, Some(ByteRange { length: 1000, offset: Some(720) }));
+        let key = first.key.as_ref().unwrap();
+        assert_eq!(key.method, KeyMethod::Aes128);
+        assert_eq!(key.uri.as_deref(), Some("https://keys.example.com/k1"));
+        assert_eq!(key.iv.map(|iv| iv[15]), Some(0x0A));
+        assert_eq!(key.drm_system(), None);
+        assert_eq!(media.drm_system(), None);
+        assert_eq!(
+            first.map,
+            Some(Map {
+                uri: "http://example.com/vod/init.mp4".to_string(),
+                byte_range: Some(ByteRange { length: 720, offset: Some(0) }),
+            })
+        );
+
+        // 省略 offset 时紧接上一个片段
+        let second_range = media.segments[1].byte_range.unwrap();
+        assert_eq!(second_range, ByteRange { length: 2000, offset: Some(1720) });
+        assert_eq!(second_range.header_value(), "bytes=1720-3719");
+        assert!(media.segments[1].key.is_some());
+
+        let last = &media.segments[2];
+        assert_eq!(last.uri, "http://example.com/ad/seg.ts");
+        assert!(last.discontinuity);
+        assert!(last.key.is_none());
+        assert_eq!(last.program_date_time.as_deref(), Some("2024-01-01T00:00:00+00:00"));
+
+        assert!(parse_playlist("<html></html>", "http://example.com/").is_err());
+    }
+
+    #[test]
+    fn test_missing_header() {
+        // #EXTM3U 之前有注释和空行，或者完全没有 #EXTM3U
+        let content = "\n# generated by iptv-server\nOK\n#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\nseg0.ts\n";
+        assert!(!has_header(content));
+        let Playlist::Media(media) = parse_playlist(content, "http://example.com/live/index.m3u8").unwrap() else {
+            panic!("应解析为媒体播放列表");
+        };
+        assert_eq!(media.segments.len(), 1);
+        assert_eq!(media.segments[0].uri, "http://example.com/live/seg0.ts");
+
+        let content = "OK\n#EXT-X-STREAM-INF:BANDWIDTH=800000\nlow.m3u8\n";
+        assert!(matches!(parse_playlist(content, "http://example.com/master.m3u8").unwrap(), Playlist::Master(master) if master.variants.len() == 1));
+
+        assert!(has_header("\u{feff}#EXTM3U\n"));
+        assert!(parse_playlist("# 只有注释\nseg0.ts\n", "http://example.com/").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use crate::channel::Channel;
+
+// IPTV 频道列表式 M3U 解析（与 HLS 的 M3u8Parser 不同，这里每个条目是一个频道）
+//
+// #EXTM3U x-tvg-url="..."
+// #EXTINF:-1 tvg-id="cctv1" tvg-name="CCTV1" tvg-logo="..." group-title="央视",CCTV-1 综合
+// #EXTVLCOPT:http-user-agent=...
+// #KODIPROP:inputstream.adaptive.manifest_type=hls
+// http://example.com/cctv1.m3u8
+pub fn parse_m3u_channels(content: &str) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    let mut pending = Channel::default();
+    let mut has_extinf = false;
+    let mut pending_group: Option<String> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some(body) = trimmed.strip_prefix("#EXTINF:") {
+            let (attributes, name) = parse_extinf(body);
+            // 新的 #EXTINF 之前收集到的附加行属于这个频道，保留它们
+            let vlc_options = std::mem::take(&mut pending.vlc_options);
+            let kodi_props = std::mem::take(&mut pending.kodi_props);
+            pending = Channel {
+                name,
+                vlc_options,
+                kodi_props,
+                ..Default::default()
+            };
+            for (key, value) in attributes {
+                apply_attribute(&mut pending, key, value);
+            }
+            has_extinf = true;
+        } else if let Some(option) = trimmed.strip_prefix("#EXTVLCOPT:") {
+            pending.vlc_options.push(option.trim().to_string());
+        } else if let Some(prop) = trimmed.strip_prefix("#KODIPROP:") {
+            pending.kodi_props.push(prop.trim().to_string());
+        } else if let Some(group) = trimmed.strip_prefix("#EXTGRP:") {
+            pending_group = Some(group.trim().to_string()).filter(|group| !group.is_empty());
+        } else if trimmed.starts_with('#') {
+            // #EXTM3U 头及其他未识别的标签
+            continue;
+        } else if trimmed.contains("://") {
+            let mut channel = std::mem::take(&mut pending);
+            channel.url = trimmed.to_string();
+
+            // 没有 #EXTINF 或没有频道名时生成默认名称
+            if !has_extinf || channel.name.is_empty() {
+                channel.name = format!("频道{}", channels.len());
+            }
+            if channel.group_title.is_none() {
+                channel.group_title = pending_group.take();
+            }
+
+            channels.push(channel);
+            has_extinf = false;
+            pending_group = None;
+        }
+    }
+
+    channels
+}
+
+// 解析 #EXTINF: 之后的内容，返回属性列表和频道名
+// 频道名为第一个不在引号内的逗号之后的全部内容，因此频道名本身可以包含逗号
+fn parse_extinf(body: &str) -> (Vec<(String, String)>, String) {
+    let chars: Vec<char> = body.chars().collect();
+    let mut attributes = Vec::new();
+    let mut i = 0;
+
+    // 跳过时长（通常为 -1）
+    while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ',' {
+        i += 1;
+    }
+
+    loop {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        if i >= chars.len() {
+            return (attributes, String::new());
+        }
+
+        if chars[i] == ',' {
+            let name: String = chars[i + 1..].iter().collect();
+            return (attributes, name.trim().to_string());
+        }
+
+        let key_start = i;
+        while i < chars.len() && chars[i] != '=' && chars[i] != ',' && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        let key: String = chars[key_start..i].iter().collect();
+
+        // 没有值的裸属性，忽略
+        if i >= chars.len() || chars[i] != '=' {
+            continue;
+        }
+        i += 1;
+
+        let value = if i < chars.len() && (chars[i] == '"' || chars[i] == '\'') {
+            let quote = chars[i];
+            i += 1;
+            let value_start = i;
+            while i < chars.len() && chars[i] != quote {
+                i += 1;
+            }
+            let value: String = chars[value_start..i].iter().collect();
+            i += 1; // 跳过结束引号
+            value
+        } else {
+            let value_start = i;
+            while i < chars.len() && chars[i] != ',' && !chars[i].is_whitespace() {
+                i += 1;
+            }
+            chars[value_start..i].iter().collect()
+        };
+
+        attributes.push((key, value));
+    }
+}
+
+fn apply_attribute(channel: &mut Channel, key: String, value: String) {
+    match key.to_lowercase().as_str() {
+        "tvg-id" => channel.tvg_id = Some(value),
+        "tvg-name" => channel.tvg_name = Some(value),
+        "tvg-logo" => channel.tvg_logo = Some(value),
+        "group-title" => channel.group_title = Some(value),
+        "catchup" => channel.catchup = Some(value),
+        "catchup-source" => channel.catchup_source = Some(value),
+        "catchup-days" => channel.catchup_days = Some(value),
+        _ => channel.extra_attributes.push((key, value)),
+    }
+}
+
+// 生成频道的 #EXTINF 行（不含换行），保留解析时得到的全部属性
+pub fn format_extinf(channel: &Channel) -> String {
+    let mut line = String::from("#EXTINF:-1");
+
+    let known = [
+        ("tvg-id", &channel.tvg_id),
+        ("tvg-name", &channel.tvg_name),
+        ("tvg-logo", &channel.tvg_logo),
+        ("group-title", &channel.group_title),
+        ("catchup", &channel.catchup),
+        ("catchup-source", &channel.catchup_source),
+        ("catchup-days", &channel.catchup_days),
+    ];
+
+    for (key, value) in known {
+        if let Some(value) = value {
+            line.push_str(&format!(" {}=\"{}\"", key, attribute_value(value)));
+        }
+    }
+
+    for (key, value) in &channel.extra_attributes {
+        line.push_str(&format!(" {}=\"{}\"", key, attribute_value(value)));
+    }
+
+    line.push(',');
+    line.push_str(&channel.name);
+    line
+}
+
+// M3U 属性值没有通用的转义方式：双引号会提前结束属性值，不少播放器还会在第一个逗号处截断，
+// 所以双引号换成单引号、逗号换成全角逗号、换行换成空格
+fn attribute_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '"' => '\'',
+            ',' => '，',
+            '\r' | '\n' => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+// 将频道列表写为 M3U 文本
+pub fn to_m3u(channels: &[Channel]) -> String {
+    let mut output = String::from("#EXTM3U\n");
+
+    for channel in channels {
+        output.push_str(&format_extinf(channel));
+        output.push('\n');
+        for prop in &channel.kodi_props {
+            output.push_str(&format!("#KODIPROP:{}\n", prop));
+        }
+        for option in &channel.vlc_options {
+            output.push_str(&format!("#EXTVLCOPT:{}\n", option));
+        }
+        output.push_str(&channel.url);
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_extinf_attributes() {
+        let content = r#"#EXTM3U x-tvg-url="http://epg.example.com/e.xml"
+#EXTINF:-1 tvg-id="cctv1" tvg-name="CCTV1" tvg-logo="http://logo/cctv1.png" group-title="央视, 高清" catchup="append" catchup-source="?playseek=${(b)yyyyMMddHHmmss}" catchup-days="7" tvg-chno="1",CCTV-1 综合, HD
+#EXTVLCOPT:http-user-agent=Mozilla/5.0
+#KODIPROP:inputstream.adaptive.manifest_type=hls
+http://example.com/cctv1.m3u8
+"#;
+        let channels = parse_m3u_channels(content);
+
+        assert_eq!(channels.len(), 1);
+        let channel = &channels[0];
+        assert_eq!(channel.name, "CCTV-1 综合, HD");
+        assert_eq!(channel.url, "http://example.com/cctv1.m3u8");
+        assert_eq!(channel.tvg_id.as_deref(), Some("cctv1"));
+        assert_eq!(channel.tvg_name.as_deref(), Some("CCTV1"));
+        assert_eq!(channel.tvg_logo.as_deref(), Some("http://logo/cctv1.png"));
+        assert_eq!(channel.group_title.as_deref(), Some("央视, 高清"));
+        assert_eq!(channel.catchup.as_deref(), Some("append"));
+        assert_eq!(channel.catchup_source.as_deref(), Some("?playseek=${(b)yyyyMMddHHmmss}"));
+        assert_eq!(channel.catchup_days.as_deref(), Some("7"));
+        assert_eq!(channel.extra_attributes, vec![("tvg-chno".to_string(), "1".to_string())]);
+        assert_eq!(channel.vlc_options, vec!["http-user-agent=Mozilla/5.0"]);
+        assert_eq!(channel.kodi_props, vec!["inputstream.adaptive.manifest_type=hls"]);
+    }
+
+    #[test]
+    fn test_parse_without_extinf_and_extgrp() {
+        let content = "#EXTM3U\n#EXTINF:-1,\n#EXTGRP:体育\nhttp://example.com/a.ts\nhttp://example.com/b.ts\n";
+        let channels = parse_m3u_channels(content);
+
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "频道0");
+        assert_eq!(channels[0].group_title.as_deref(), Some("体育"));
+        assert_eq!(channels[1].name, "频道1");
+        assert_eq!(channels[1].group_title, None);
+    }
+
+    #[test]
+    fn test_m3u_round_trip() {
+        let content = "#EXTM3U\n#EXTINF:-1 tvg-id=\"gd\" group-title=\"广东\" tvg-country=\"CN\",广东卫视\n#KODIPROP:inputstream=inputstream.adaptive\nhttp://example.com/gd.m3u8\n";
+        let channels = parse_m3u_channels(content);
+        let output = to_m3u(&channels);
+
+        assert_eq!(output, content);
+        assert_eq!(parse_m3u_channels(&output)[0].tvg_id.as_deref(), Some("gd"));
+    }
+
+    #[test]
+    fn test_attribute_quotes_and_commas_round_trip() {
+        let channel = Channel {
+            tvg_name: Some("CCTV\"5\"".to_string()),
+            group_title: Some("央视, 高清".to_string()),
+            extra_attributes: vec![("tvg-country".to_string(), "CN,\nHK".to_string())],
+            ..Channel::new("CCTV-5, 体育", "http://example.com/cctv5.m3u8")
+        };
+        let output = to_m3u(&[channel]);
+
+        assert_eq!(
+            output.lines().nth(1),
+            Some("#EXTINF:-1 tvg-name=\"CCTV'5'\" group-title=\"央视， 高清\" tvg-country=\"CN， HK\",CCTV-5, 体育")
+        );
+
+        let parsed = &parse_m3u_channels(&output)[0];
+        assert_eq!(parsed.name, "CCTV-5, 体育");
+        assert_eq!(parsed.url, "http://example.com/cctv5.m3u8");
+        assert_eq!(parsed.tvg_name.as_deref(), Some("CCTV'5'"));
+        assert_eq!(parsed.group_title.as_deref(), Some("央视， 高清"));
+        assert_eq!(parsed.extra_attributes, vec![("tvg-country".to_string(), "CN， HK".to_string())]);
+        assert_eq!(to_m3u(std::slice::from_ref(parsed)), output);
+    }
+}
@@ -1,61 +1,301 @@
-use clap::Parser;
-use anyhow::Result;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
 
 mod speed_test;
 mod m3u8_parser;
+mod channel;
+mod m3u_parser;
+mod batch;
+mod subscription;
+mod config;
+mod filter;
+mod output;
+mod report;
+mod export;
+mod policy;
+mod drm;
+mod mpd_parser;
+mod mpeg_ts;
+mod codec;
 
-use speed_test::SpeedTester;
+use speed_test::{HlsMode, SpeedTestResult, SpeedTester, TestConfig};
+use batch::BatchRunner;
+use subscription::SubscriptionFetcher;
+use config::AppConfig;
+use filter::FilterEngine;
+use channel::Channel;
+use output::OutputFormat;
+use report::Column;
+use export::SortOrder;
+use policy::{Policy, VerdictStatus};
 
 #[derive(Parser)]
 #[command(name = "iptv-speed-test")]
 #[command(about = "IPTV流媒体测速工具", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// 要测试的HTTP URL
-    url: String,
+    url: Option<String>,
 
     /// 详细输出
-    #[arg(short = 'v', long)]
+    #[arg(short = 'v', long, global = true)]
     verbose: bool,
+
+    /// 配置文件（.toml 或 .yaml），包含频道过滤规则等
+    #[arg(short = 'c', long, global = true)]
+    config: Option<PathBuf>,
+
+    /// 结果输出格式
+    #[arg(short = 'o', long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// 判定通过的最低速度（kbps），覆盖配置文件中的 policy.min_speed_kbps
+    #[arg(long, global = true)]
+    min_speed: Option<f64>,
+
+    /// 判定通过的最低余量（实测速度 / 码流码率），覆盖配置文件中的 policy.min_headroom
+    #[arg(long, global = true, value_name = "RATIO")]
+    min_headroom: Option<f64>,
+
+    #[command(flatten)]
+    test: TestArgs,
+}
+
+// 测速超时和预算参数，覆盖配置文件中的 [test] 部分
+#[derive(Args)]
+struct TestArgs {
+    /// HTTP客户端整体超时（秒）
+    #[arg(long, global = true, value_name = "SECS")]
+    client_timeout: Option<f64>,
+
+    /// 连接建立超时（秒）
+    #[arg(long, global = true, value_name = "SECS")]
+    connect_timeout: Option<f64>,
+
+    /// 直连下载的整体请求超时（秒）
+    #[arg(long, global = true, value_name = "SECS")]
+    request_timeout: Option<f64>,
+
+    /// 连接成功后的流式读取时长（秒）
+    #[arg(long, global = true, value_name = "SECS")]
+    read_window: Option<f64>,
+
+    /// 单个URL测试的兜底超时（秒）
+    #[arg(long, global = true, value_name = "SECS")]
+    test_timeout: Option<f64>,
+
+    /// HLS 测试下载的片段数
+    #[arg(long, global = true, value_name = "N")]
+    hls_segments: Option<usize>,
+
+    /// HLS 测试方式: burst 并发下载前几个片段，live 持续刷新直播播放列表，low_latency 阻塞刷新跟随 LL-HLS 部分片段，player 按顺序下载并模拟播放缓冲
+    #[arg(long, global = true, value_enum, value_name = "MODE")]
+    hls_mode: Option<HlsMode>,
+
+    /// live 模式的观察时长（秒）
+    #[arg(long, global = true, value_name = "SECS")]
+    live_window: Option<f64>,
+
+    /// 逐个测试主播放列表中的所有子播放列表，而不只是码率最高的一个
+    #[arg(long, global = true)]
+    all_variants: bool,
+
+    /// 逐个测试子播放列表时，只测试分辨率高度不超过该值的子播放列表
+    #[arg(long, global = true, value_name = "PX")]
+    variant_max_height: Option<u64>,
+
+    /// 逐个测试子播放列表时，只测试声明码率不低于该值（bps）的子播放列表
+    #[arg(long, global = true, value_name = "BPS")]
+    variant_min_bandwidth: Option<u64>,
+
+    /// 逐个测试子播放列表时，只测试声明码率不超过该值（bps）的子播放列表
+    #[arg(long, global = true, value_name = "BPS")]
+    variant_max_bandwidth: Option<u64>,
+
+    /// AES-128 加密的 HLS 流除了检查密钥，还下载并解密第一个加密片段，确认能解出有效的 MPEG-TS
+    #[arg(long, global = true)]
+    decrypt_check: bool,
+
+    /// 直连下载时统计 TS 连续计数器错误、PCR 不连续和抖动等传输流健康指标（TR 101 290 第一优先级）
+    #[arg(long, global = true)]
+    ts_health: bool,
+}
+
+impl TestArgs {
+    fn apply(&self, config: &mut TestConfig) {
+        if let Some(value) = self.client_timeout {
+            config.client_timeout_secs = value;
+        }
+        if let Some(value) = self.connect_timeout {
+            config.connect_timeout_secs = value;
+        }
+        if let Some(value) = self.request_timeout {
+            config.request_timeout_secs = value;
+        }
+        if let Some(value) = self.read_window {
+            config.read_window_secs = value;
+        }
+        if let Some(value) = self.test_timeout {
+            config.test_timeout_secs = value;
+        }
+        if let Some(value) = self.hls_segments {
+            config.hls_segments = value;
+        }
+        if let Some(value) = self.hls_mode {
+            config.hls_mode = value;
+        }
+        if let Some(value) = self.live_window {
+            config.live_window_secs = value;
+        }
+        if self.all_variants {
+            config.probe_variants = true;
+        }
+        if let Some(value) = self.variant_max_height {
+            config.variant_max_height = Some(value);
+        }
+        if let Some(value) = self.variant_min_bandwidth {
+            config.variant_min_bandwidth = Some(value);
+        }
+        if let Some(value) = self.variant_max_bandwidth {
+            config.variant_max_bandwidth = Some(value);
+        }
+        if self.decrypt_check {
+            config.decrypt_check = true;
+        }
+        if self.ts_health {
+            config.ts_health = true;
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// 批量测试频道列表文件（每行 "频道名,URL"）
+    Batch {
+        /// 频道列表文件
+        file: PathBuf,
+
+        /// 最大并发测试数
+        #[arg(short = 'j', long, default_value_t = 20)]
+        concurrency: usize,
+
+        /// 将结果保存到文件（默认输出到标准输出）
+        #[arg(short = 's', long)]
+        save: Option<PathBuf>,
+
+        /// CSV/TSV 输出的列，逗号分隔（默认输出全部列）
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
+
+        /// 将测试通过的频道导出为播放列表，.m3u/.m3u8 为 M3U，其他为 genre TXT（可重复指定）
+        #[arg(short = 'e', long)]
+        export: Vec<PathBuf>,
+
+        /// 导出播放列表的排序方式，有分组时先按分组归类，排序只在分组内生效
+        #[arg(long, value_enum, default_value_t = SortOrder::Host)]
+        sort: SortOrder,
+
+        /// 导出时使用实测能承载的最高码率子播放列表地址代替主播放列表地址（需配合 --all-variants）
+        #[arg(long, requires = "export")]
+        export_best_variant: bool,
+    },
+
+    /// 下载订阅源并合并为一个 "频道名,URL" 列表
+    Fetch {
+        /// 订阅源列表文件（每行一个地址）
+        #[arg(default_value = "subscribe.txt")]
+        file: PathBuf,
+
+        /// 将频道列表保存到文件，.m3u/.m3u8 扩展名保存为 M3U 并保留频道属性（默认以TXT输出到标准输出）
+        #[arg(short = 's', long)]
+        save: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let mut config = match &cli.config {
+        Some(path) => AppConfig::load(path)?,
+        None => AppConfig::default(),
+    };
+    if let Some(min_speed) = cli.min_speed {
+        config.policy.min_speed_kbps = min_speed;
+    }
+    if let Some(min_headroom) = cli.min_headroom {
+        config.policy.min_headroom = Some(min_headroom);
+    }
+    cli.test.apply(&mut config.test);
+    config.test.validate()?;
+
+    // -v 的日志也写到标准输出，会打乱写到标准输出的机器可读结果
+    let writes_stdout = !matches!(&cli.command, Some(Commands::Batch { save: Some(_), .. }) | Some(Commands::Fetch { .. }));
+    if cli.verbose && cli.output != OutputFormat::Text && writes_stdout {
+        let format = cli.output.to_possible_value().map_or(String::new(), |value| value.get_name().to_string());
+        return Err(anyhow!("-v 不能与 -o {} 一起输出到标准输出，请去掉 -v 或用 batch -s 将结果保存到文件", format));
+    }
+
+    // 没有逐个测试子播放列表时结果中没有 selected_variant，--export-best-variant 不会生效
+    if matches!(&cli.command, Some(Commands::Batch { export_best_variant: true, .. })) && !config.test.probe_variants {
+        return Err(anyhow!("--export-best-variant 需要配合 --all-variants（或配置文件中的 probe_variants = true）"));
+    }
+
+    match cli.command {
+        Some(Commands::Batch { file, concurrency, save, columns, export, sort, export_best_variant }) => {
+            let options = BatchOptions {
+                concurrency,
+                save,
+                columns,
+                export,
+                sort,
+                export_best_variant,
+                format: cli.output,
+            };
+            run_batch(&file, &options, &config, cli.verbose).await
+        }
+        Some(Commands::Fetch { file, save }) => {
+            run_fetch(&file, save.as_deref(), &config, cli.verbose).await
+        }
+        None => match cli.url {
+            Some(url) => run_single(&url, &config, cli.output, cli.verbose).await,
+            None => Err(anyhow!("请提供要测试的URL或子命令")),
+        },
+    }
+}
+
+async fn run_single(url: &str, config: &AppConfig, format: OutputFormat, verbose: bool) -> Result<()> {
     let tester = SpeedTester::new(
-        cli.verbose,
+        verbose,
+        config.test.clone(),
     );
 
-    if cli.verbose {
-        println!("开始测试 URL: {}", cli.url);
-    }
-
-    match tester.test_url(&cli.url).await {
-        Ok(result) => {
-            if cli.verbose {
-                println!("\n=== 测试结果 ===");
-                println!("URL: {}", result.url);
-                println!("状态: {}", if result.success { "成功" } else { "失败" });
-                println!("延迟: {:.2} ms", result.delay_ms);
-                println!("下载速度: {:.0} kbps", result.speed_kbps);
-                println!("下载大小: {:.2} MB", result.size_mb);
-                println!("测试时长: {:.2} 秒", result.duration_secs);
-                println!("协议类型: {}", result.protocol_type);
-
-                if let Some(details) = result.details {
-                    println!("详细信息: {}", details);
-                }
-            } else {
-                // 非verbose模式，只输出速率（kbps）
-                if result.success {
-                    println!("{:.0} kbps", result.speed_kbps);
-                } else {
-                    println!("0 kbps");
-                }
-            }
+    if verbose {
+        println!("开始测试 URL: {}", url);
+    }
+
+    let policy = Policy::new(config.policy.clone());
+
+    match tester.test_url(url).await {
+        Ok(mut result) => {
+            policy.apply(&mut result);
+            output::print_single(&result, format, verbose)?
         }
         Err(e) => {
-            if cli.verbose {
+            if format != OutputFormat::Text {
+                let mut result = SpeedTestResult::failure(
+                    url,
+                    "未知",
+                    speed_test::classify_error(&e),
+                    format!("测试失败: {}", e),
+                );
+                policy.apply(&mut result);
+                output::print_single(&result, format, verbose)?;
+            } else if verbose {
                 eprintln!("测试失败: {}", e);
             } else {
                 println!("0");
@@ -64,4 +304,117 @@ async fn main() -> Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+struct BatchOptions {
+    concurrency: usize,
+    save: Option<PathBuf>,
+    columns: Vec<Column>,
+    export: Vec<PathBuf>,
+    sort: SortOrder,
+    export_best_variant: bool,
+    format: OutputFormat,
+}
+
+async fn run_batch(file: &Path, options: &BatchOptions, config: &AppConfig, verbose: bool) -> Result<()> {
+    let channels = apply_filter(config, channel::load_channel_file(file)?)?;
+
+    if verbose {
+        println!("读取到 {} 个频道，并发数: {}", channels.len(), options.concurrency);
+    }
+
+    let runner = BatchRunner::new(SpeedTester::new(verbose, config.test.clone()), options.concurrency, verbose);
+    let mut results = runner.run(channels).await;
+
+    let policy = Policy::new(config.policy.clone());
+    for item in &mut results {
+        policy.apply(&mut item.result);
+    }
+
+    // 所有测试完成后一次性写出结果
+    match &options.save {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .map_err(|e| anyhow!("无法创建结果文件 {}: {}", path.display(), e))?;
+            output::write_batch(std::io::BufWriter::new(file), &results, options.format, &options.columns)?;
+            eprintln!("测试结果已保存至: {}", path.display());
+        }
+        None => output::write_batch(std::io::stdout().lock(), &results, options.format, &options.columns)?,
+    }
+
+    for path in &options.export {
+        let count = export::export_playlist(path, &results, options.sort, options.export_best_variant)?;
+        eprintln!("已导出 {} 个频道至: {}", count, path.display());
+    }
+
+    if verbose && options.format == OutputFormat::Text {
+        let succeeded = results.iter().filter(|item| item.result.success).count();
+        let passed = results.iter().filter(|item| item.result.passed()).count();
+        let degraded = results
+            .iter()
+            .filter(|item| item.result.verdict.as_ref().is_some_and(|verdict| verdict.status == VerdictStatus::Degraded))
+            .count();
+        println!(
+            "\n批量测试完成: 共 {} 个, 成功 {} 个, 判定通过 {} 个, 降级 {} 个",
+            results.len(),
+            succeeded,
+            passed,
+            degraded
+        );
+    }
+
+    Ok(())
+}
+
+async fn run_fetch(file: &Path, save: Option<&Path>, config: &AppConfig, verbose: bool) -> Result<()> {
+    let sources = subscription::load_subscription_file(file)?;
+
+    let fetcher = SubscriptionFetcher::new(speed_test::build_client(&config.test), verbose);
+    let (channels, reports) = fetcher.fetch_all(&sources).await;
+
+    // 下载报告写到 stderr，避免和输出到 stdout 的频道列表混在一起
+    for report in &reports {
+        if let Some(format) = report.format {
+            eprintln!("✓ 下载成功: {} ({}, {} 个频道)", report.url, format, report.channel_count);
+        } else {
+            eprintln!("✗ 下载失败: {}: {}", report.url, report.error.as_deref().unwrap_or("未知错误"));
+        }
+    }
+
+    let succeeded = reports.iter().filter(|report| report.success).count();
+    eprintln!(
+        "订阅源处理完成: 成功 {} 个, 失败 {} 个, 共 {} 个频道",
+        succeeded,
+        reports.len() - succeeded,
+        channels.len()
+    );
+
+    let channels = apply_filter(config, channels)?;
+
+    match save {
+        Some(path) => {
+            channel::save_channel_file(path, &channels)?;
+            eprintln!("频道列表已保存至: {}", path.display());
+        }
+        None => print!("{}", channel::to_txt(&channels)),
+    }
+
+    Ok(())
+}
+
+// 应用配置文件中的过滤规则，过滤报告写到 stderr
+fn apply_filter(config: &AppConfig, channels: Vec<Channel>) -> Result<Vec<Channel>> {
+    let engine = FilterEngine::new(&config.filter)?;
+    if engine.is_empty() {
+        return Ok(channels);
+    }
+
+    let (channels, report) = engine.apply(channels);
+
+    eprintln!("频道过滤: {} 个 -> {} 个", report.input, report.output);
+    for rule in &report.rules {
+        eprintln!("  规则 [{}] 去掉 {} 个", rule.rule, rule.removed);
+    }
+
+    Ok(channels)
+}
@@ -0,0 +1,723 @@
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use roxmltree::{Document, Node};
+
+use crate::drm::DrmSystem;
+use crate::m3u8_parser::{resolve_url, ByteRange, Map, MediaSegment};
+use crate::speed_test::TestError;
+
+pub struct MpdParser {
+    verbose: bool,
+}
+
+// SegmentTimeline 中的一个 <S> 元素，r 为重复次数（-1 表示一直重复到下一个 S 或周期结束）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub t: Option<u64>,
+    pub d: u64,
+    pub r: i64,
+}
+
+// <SegmentTemplate>，属性可以从 AdaptationSet / Period 继承
+#[derive(Debug, Clone, Default)]
+pub struct SegmentTemplate {
+    pub media: String,
+    pub initialization: Option<String>,
+    pub start_number: u64,
+    pub timescale: u64,
+    pub duration: Option<u64>,
+    pub presentation_time_offset: u64,
+    pub timeline: Vec<TimelineEntry>,
+}
+
+// 媒体片段的寻址方式
+#[derive(Debug, Clone)]
+pub enum SegmentAddressing {
+    Template(SegmentTemplate),
+    // <SegmentList>：逐个列出的片段地址
+    List {
+        initialization: Option<Map>,
+        segments: Vec<MediaSegment>,
+    },
+    // <SegmentBase>：整个表示就是一个文件，可带初始化部分的字节范围
+    Base {
+        initialization: Option<ByteRange>,
+    },
+}
+
+impl SegmentAddressing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SegmentAddressing::Template(template) if !template.timeline.is_empty() => "SegmentTimeline",
+            SegmentAddressing::Template(_) => "SegmentTemplate",
+            SegmentAddressing::List { .. } => "SegmentList",
+            SegmentAddressing::Base { .. } => "SegmentBase",
+        }
+    }
+}
+
+// <Representation>，已合并所属 AdaptationSet / Period 的信息
+#[derive(Debug, Clone)]
+pub struct Representation {
+    pub id: String,
+    pub bandwidth: u64,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub codecs: Option<String>,
+    pub mime_type: Option<String>,
+    pub content_type: Option<String>, // video / audio / text
+    pub base_url: String,             // 已解析的 BaseURL，模板中的相对地址以它为基准
+    pub addressing: SegmentAddressing,
+    pub drm: Option<DrmSystem>,       // ContentProtection 声明的 DRM 系统
+    pub period_start: f64,
+    pub period_duration: Option<f64>,
+}
+
+impl Representation {
+    pub fn is_video(&self) -> bool {
+        self.content_type.as_deref() == Some("video")
+            || self.mime_type.as_deref().is_some_and(|mime_type| mime_type.starts_with("video/"))
+            || self.width.is_some()
+    }
+
+    // 用于日志和结果详情的简要描述，如 "video, 1920x1080, 4000000 bps, avc1.640028, SegmentTemplate"
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(content_type) = self.content_type.as_deref().or(self.mime_type.as_deref()) {
+            parts.push(content_type.to_string());
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            parts.push(format!("{}x{}", width, height));
+        }
+        parts.push(format!("{} bps", self.bandwidth));
+        if let Some(codecs) = &self.codecs {
+            parts.push(codecs.clone());
+        }
+        parts.push(self.addressing.name().to_string());
+        parts.join(", ")
+    }
+
+    // 初始化片段
+    pub fn init_segment(&self) -> Option<Map> {
+        match &self.addressing {
+            SegmentAddressing::Template(template) => template.initialization.as_ref().map(|initialization| Map {
+                uri: resolve_url(&expand_template(initialization, &self.id, self.bandwidth, 0, 0), &self.base_url),
+                byte_range: None,
+            }),
+            SegmentAddressing::List { initialization, .. } => initialization.clone(),
+            SegmentAddressing::Base { initialization } => initialization.map(|range| Map {
+                uri: self.base_url.clone(),
+                byte_range: Some(range),
+            }),
+        }
+    }
+
+    // 要测试的媒体片段：点播取开头的 count 个，直播取直播边缘最新的 count 个
+    // now 为当前 Unix 时间（秒），availability_start 为 MPD@availabilityStartTime
+    pub fn media_segments(&self, count: usize, live: bool, availability_start: Option<f64>, now: f64) -> Vec<MediaSegment> {
+        let segments: Vec<MediaSegment> = match &self.addressing {
+            SegmentAddressing::Template(template) => {
+                let numbers = self.template_numbers(template, live, availability_start, now);
+                let numbers = if live {
+                    numbers[numbers.len().saturating_sub(count)..].to_vec()
+                } else {
+                    numbers.into_iter().take(count).collect()
+                };
+                numbers
+                    .into_iter()
+                    .map(|(number, time, duration)| MediaSegment {
+                        uri: resolve_url(&expand_template(&template.media, &self.id, self.bandwidth, number, time), &self.base_url),
+                        duration,
+                        ..Default::default()
+                    })
+                    .collect()
+            }
+            SegmentAddressing::List { segments, .. } if live => segments[segments.len().saturating_sub(count)..].to_vec(),
+            SegmentAddressing::List { segments, .. } => segments.iter().take(count).cloned().collect(),
+            // 整个文件作为一个片段，下载受读取时长限制
+            SegmentAddressing::Base { .. } => vec![MediaSegment {
+                uri: self.base_url.clone(),
+                ..Default::default()
+            }],
+        };
+        segments
+    }
+
+    // 模板片段的 (序号, 开始时间, 时长秒)
+    fn template_numbers(&self, template: &SegmentTemplate, live: bool, availability_start: Option<f64>, now: f64) -> Vec<(u64, u64, f64)> {
+        let timescale = template.timescale.max(1);
+        // 直播时只到当前时间，点播到周期结束，都不知道时最多展开这么多个片段
+        const MAX_SEGMENTS: usize = 100_000;
+        let elapsed = availability_start.map(|start| now - start - self.period_start).filter(|_| live);
+        let period_end = elapsed.or(self.period_duration);
+
+        if !template.timeline.is_empty() {
+            // t / d / r 来自远程 MPD，时间或序号溢出时停止展开
+            let mut numbers = Vec::new();
+            let mut number = template.start_number;
+            let mut time = 0u64;
+            'timeline: for (index, entry) in template.timeline.iter().enumerate() {
+                time = entry.t.unwrap_or(time);
+                let repeat_until = if entry.r >= 0 {
+                    match entry.d.checked_mul(entry.r as u64 + 1).and_then(|span| time.checked_add(span)) {
+                        Some(end) => Some(end),
+                        None => break,
+                    }
+                } else {
+                    // 重复到下一个 S 的开始时间或周期结束
+                    template.timeline.get(index + 1).and_then(|next| next.t).or_else(|| {
+                        period_end.and_then(|end| template.presentation_time_offset.checked_add((end.max(0.0) * timescale as f64) as u64))
+                    })
+                };
+
+                let mut repeats = 0;
+                loop {
+                    let Some(end_time) = time.checked_add(entry.d) else {
+                        break 'timeline;
+                    };
+                    let within = match repeat_until {
+                        Some(end) => end_time <= end,
+                        None => repeats == 0,
+                    };
+                    if !within || entry.d == 0 || numbers.len() >= MAX_SEGMENTS {
+                        break;
+                    }
+                    numbers.push((number, time, entry.d as f64 / timescale as f64));
+                    let Some(next_number) = number.checked_add(1) else {
+                        break 'timeline;
+                    };
+                    number = next_number;
+                    time = end_time;
+                    repeats += 1;
+                }
+            }
+            return numbers;
+        }
+
+        let Some(duration) = template.duration.filter(|duration| *duration > 0) else {
+            return Vec::new();
+        };
+        let segment_secs = duration as f64 / timescale as f64;
+
+        let (first, last) = match (live, elapsed) {
+            // 直播：最后一个已经完整发布的片段
+            (true, Some(elapsed)) => {
+                let published = (elapsed / segment_secs).floor() as i64 - 1;
+                if published < 0 {
+                    return Vec::new();
+                }
+                let Some(last) = template.start_number.checked_add(published as u64) else {
+                    return Vec::new();
+                };
+                (last.saturating_sub(MAX_SEGMENTS as u64).max(template.start_number), last)
+            }
+            _ => {
+                let total = period_end.map_or(MAX_SEGMENTS as u64, |end| (end / segment_secs).ceil() as u64);
+                (template.start_number, template.start_number.saturating_add(total.saturating_sub(1)))
+            }
+        };
+
+        (first..=last)
+            .take(MAX_SEGMENTS)
+            .map_while(|number| {
+                let time = (number - template.start_number)
+                    .checked_mul(duration)
+                    .and_then(|offset| offset.checked_add(template.presentation_time_offset))?;
+                Some((number, time, segment_secs))
+            })
+            .collect()
+    }
+}
+
+// 解析后的 MPD，只保留测速需要的信息（只使用第一个周期）
+#[derive(Debug, Clone)]
+pub struct Mpd {
+    pub is_live: bool,                     // type="dynamic"
+    pub duration: Option<f64>,             // mediaPresentationDuration
+    pub availability_start: Option<f64>,   // availabilityStartTime，Unix 时间（秒）
+    pub representations: Vec<Representation>,
+}
+
+impl Mpd {
+    // 优先选择视频中带宽最高的表示，没有视频时在所有表示中选择
+    pub fn best_representation(&self) -> Option<&Representation> {
+        let video = self.representations.iter().filter(|representation| representation.is_video());
+        video
+            .max_by_key(|representation| representation.bandwidth)
+            .or_else(|| self.representations.iter().max_by_key(|representation| representation.bandwidth))
+    }
+}
+
+impl MpdParser {
+    pub fn new(verbose: bool) -> Self {
+        Self { verbose }
+    }
+
+    pub async fn parse_mpd(&self, url: &str, client: &Client) -> Result<Mpd> {
+        if self.verbose {
+            println!("解析MPD: {}", url);
+        }
+
+        let response = client.get(url).send().await?;
+        if !response.status().is_success() {
+            if self.verbose {
+                println!("无法获取MPD文件 {}: {}", url, response.status());
+            }
+            return Err(TestError::HttpStatus(response.status()).into());
+        }
+
+        // 重定向后以最终地址作为相对地址的基准
+        let base_url = response.url().to_string();
+        let content = response.text().await?;
+        parse_mpd_content(&content, &base_url)
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+fn parse_attribute<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    node.attribute(name).and_then(|value| value.trim().parse().ok())
+}
+
+// 元素下的 <BaseURL> 相对于上一级地址解析
+fn resolve_base_url(node: Node, parent: &str) -> String {
+    match child(node, "BaseURL").and_then(|base| base.text()).map(str::trim) {
+        Some(base) if !base.is_empty() => resolve_url(base, parent),
+        _ => parent.to_string(),
+    }
+}
+
+// 从内到外（Representation → AdaptationSet → Period）取第一个有该属性的 SegmentTemplate
+fn template_attribute<T: std::str::FromStr>(templates: &[Node], name: &str) -> Option<T> {
+    templates.iter().find_map(|template| parse_attribute(*template, name))
+}
+
+fn parse_segment_template(templates: &[Node]) -> Option<SegmentTemplate> {
+    if templates.is_empty() {
+        return None;
+    }
+
+    let timeline = templates
+        .iter()
+        .find_map(|template| child(*template, "SegmentTimeline"))
+        .map(|timeline| {
+            children(timeline, "S")
+                .map(|entry| TimelineEntry {
+                    t: parse_attribute(entry, "t"),
+                    d: parse_attribute(entry, "d").unwrap_or(0),
+                    r: parse_attribute(entry, "r").unwrap_or(0),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some(SegmentTemplate {
+        media: template_attribute(templates, "media")?,
+        initialization: template_attribute(templates, "initialization"),
+        start_number: template_attribute(templates, "startNumber").unwrap_or(1),
+        timescale: template_attribute(templates, "timescale").unwrap_or(1),
+        duration: template_attribute(templates, "duration"),
+        presentation_time_offset: template_attribute(templates, "presentationTimeOffset").unwrap_or(0),
+        timeline,
+    })
+}
+
+// mediaRange / range 格式为 "首字节-末字节"
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let (start, end) = value.trim().split_once('-')?;
+    let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+    (end >= start).then_some(ByteRange {
+        length: end - start + 1,
+        offset: Some(start),
+    })
+}
+
+fn parse_segment_list(list: Node, base_url: &str) -> SegmentAddressing {
+    let initialization = child(list, "Initialization").map(|initialization| Map {
+        uri: initialization
+            .attribute("sourceURL")
+            .map_or_else(|| base_url.to_string(), |source| resolve_url(source, base_url)),
+        byte_range: initialization.attribute("range").and_then(parse_range),
+    });
+    let timescale = parse_attribute(list, "timescale").unwrap_or(1u64).max(1);
+    let duration = parse_attribute(list, "duration").unwrap_or(0u64) as f64 / timescale as f64;
+
+    let segments = children(list, "SegmentURL")
+        .map(|segment| MediaSegment {
+            uri: segment
+                .attribute("media")
+                .map_or_else(|| base_url.to_string(), |media| resolve_url(media, base_url)),
+            duration,
+            byte_range: segment.attribute("mediaRange").and_then(parse_range),
+            ..Default::default()
+        })
+        .collect();
+
+    SegmentAddressing::List { initialization, segments }
+}
+
+// ContentProtection 的 schemeIdUri，mp4protection 只表示 CENC 加密，具体 DRM 系统由其他元素声明
+fn content_protection(nodes: &[Node]) -> Option<DrmSystem> {
+    let systems: Vec<DrmSystem> = nodes
+        .iter()
+        .flat_map(|node| children(*node, "ContentProtection"))
+        .filter_map(|protection| protection.attribute("schemeIdUri"))
+        .filter_map(DrmSystem::from_keyformat)
+        .collect();
+
+    systems
+        .iter()
+        .find(|system| **system != DrmSystem::Other)
+        .or(systems.first())
+        .copied()
+}
+
+pub fn parse_mpd_content(content: &str, base_url: &str) -> Result<Mpd> {
+    let document = Document::parse(content.trim_start_matches('\u{feff}')).map_err(|e| anyhow!("MPD不是有效的XML: {}", e))?;
+    let root = document.root_element();
+    if !root.has_tag_name("MPD") {
+        return Err(anyhow!("不是有效的MPD（根元素为 {}）", root.tag_name().name()));
+    }
+
+    let is_live = root.attribute("type") == Some("dynamic");
+    let duration = root.attribute("mediaPresentationDuration").and_then(parse_iso_duration);
+    let availability_start = root.attribute("availabilityStartTime").and_then(parse_iso_datetime);
+    let mpd_base = resolve_base_url(root, base_url);
+
+    let period = child(root, "Period").ok_or_else(|| anyhow!("MPD中没有 Period"))?;
+    let period_base = resolve_base_url(period, &mpd_base);
+    let period_start = period.attribute("start").and_then(parse_iso_duration).unwrap_or(0.0);
+    let period_duration = period.attribute("duration").and_then(parse_iso_duration).or(duration);
+
+    let mut representations = Vec::new();
+    for adaptation_set in children(period, "AdaptationSet") {
+        let adaptation_base = resolve_base_url(adaptation_set, &period_base);
+
+        for representation in children(adaptation_set, "Representation") {
+            let base_url = resolve_base_url(representation, &adaptation_base);
+            let inherited = |name: &str| {
+                representation
+                    .attribute(name)
+                    .or_else(|| adaptation_set.attribute(name))
+                    .map(str::to_string)
+            };
+
+            let templates: Vec<Node> = [representation, adaptation_set, period]
+                .into_iter()
+                .filter_map(|node| child(node, "SegmentTemplate"))
+                .collect();
+            let segment_list = [representation, adaptation_set, period]
+                .into_iter()
+                .find_map(|node| child(node, "SegmentList"));
+            let segment_base = [representation, adaptation_set, period]
+                .into_iter()
+                .find_map(|node| child(node, "SegmentBase"));
+
+            let addressing = if let Some(template) = parse_segment_template(&templates) {
+                SegmentAddressing::Template(template)
+            } else if let Some(list) = segment_list {
+                parse_segment_list(list, &base_url)
+            } else {
+                SegmentAddressing::Base {
+                    initialization: segment_base
+                        .and_then(|segment_base| child(segment_base, "Initialization"))
+                        .and_then(|initialization| initialization.attribute("range"))
+                        .and_then(parse_range),
+                }
+            };
+
+            representations.push(Representation {
+                id: representation.attribute("id").unwrap_or_default().to_string(),
+                bandwidth: parse_attribute(representation, "bandwidth").unwrap_or(0),
+                width: parse_attribute(representation, "width").or_else(|| parse_attribute(adaptation_set, "width")),
+                height: parse_attribute(representation, "height").or_else(|| parse_attribute(adaptation_set, "height")),
+                codecs: inherited("codecs"),
+                mime_type: inherited("mimeType"),
+                content_type: inherited("contentType"),
+                base_url,
+                addressing,
+                drm: content_protection(&[representation, adaptation_set]),
+                period_start,
+                period_duration,
+            });
+        }
+    }
+
+    if representations.is_empty() {
+        return Err(anyhow!("MPD中没有 Representation"));
+    }
+
+    Ok(Mpd {
+        is_live,
+        duration,
+        availability_start,
+        representations,
+    })
+}
+
+// 替换模板中的 $RepresentationID$、$Number$、$Bandwidth$、$Time$（支持 %0Nd 宽度）和 $$
+pub fn expand_template(template: &str, representation_id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+
+        let identifier = &after[..end];
+        let (name, format) = match identifier.split_once('%') {
+            Some((name, format)) => (name, Some(format)),
+            None => (identifier, None),
+        };
+        let width = format
+            .and_then(|format| format.strip_suffix('d'))
+            .and_then(|width| width.trim_start_matches('0').parse().ok())
+            .unwrap_or(0);
+
+        match name {
+            "" => output.push('$'),
+            "RepresentationID" => output.push_str(representation_id),
+            "Number" => output.push_str(&format!("{:0width$}", number, width = width)),
+            "Bandwidth" => output.push_str(&format!("{:0width$}", bandwidth, width = width)),
+            "Time" => output.push_str(&format!("{:0width$}", time, width = width)),
+            // 不认识的标识符原样保留
+            _ => output.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// ISO 8601 时长，如 PT1H2M3.5S、P1DT12H
+pub fn parse_iso_duration(value: &str) -> Option<f64> {
+    let value = value.trim().strip_prefix('P')?;
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, time),
+        None => (value, ""),
+    };
+
+    let mut seconds = 0.0;
+    for (part, units) in [(date, [('Y', 365.0 * 86400.0), ('M', 30.0 * 86400.0), ('W', 7.0 * 86400.0), ('D', 86400.0)]), (time, [('H', 3600.0), ('M', 60.0), ('S', 1.0), ('S', 1.0)])] {
+        let mut number = String::new();
+        for ch in part.chars() {
+            if ch.is_ascii_digit() || ch == '.' {
+                number.push(ch);
+            } else {
+                let unit = units.iter().find(|(name, _)| *name == ch)?.1;
+                seconds += number.parse::<f64>().ok()? * unit;
+                number.clear();
+            }
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Some(seconds)
+}
+
+// ISO 8601 UTC 时间（如 2024-01-01T00:00:00Z、2024-01-01T08:00:00.5+08:00）转换为 Unix 时间（秒）
+pub fn parse_iso_datetime(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (date, time) = value.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date_parts.next()??, date_parts.next()??, date_parts.next()??);
+
+    // 时区：Z 或 ±HH:MM，省略时按 UTC
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else if let Some(index) = time.rfind(['+', '-']) {
+        let (hours, minutes) = time[index + 1..].split_once(':').unwrap_or((&time[index + 1..], "0"));
+        let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        (&time[..index], if time[index..].starts_with('-') { -offset } else { offset })
+    } else {
+        (time, 0)
+    };
+
+    let mut time_parts = time.splitn(3, ':');
+    let hour: i64 = time_parts.next()?.parse().ok()?;
+    let minute: i64 = time_parts.next()?.parse().ok()?;
+    let second: f64 = time_parts.next().unwrap_or("0").parse().ok()?;
+
+    // 公历日期到 1970-01-01 的天数
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    Some((days * 86400 + hour * 3600 + minute * 60 - offset) as f64 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() {
+        assert_eq!(
+            expand_template("$RepresentationID$/seg-$Number%05d$.m4s", "video_1080", 4000000, 42, 0),
+            "video_1080/seg-00042.m4s"
+        );
+        assert_eq!(expand_template("t$Time$_$Bandwidth$$$.mp4", "v", 800000, 1, 90000), "t90000_800000$.mp4");
+        assert_eq!(expand_template("$Unknown$-$Number$", "v", 0, 7, 0), "$Unknown$-7");
+    }
+
+    #[test]
+    fn test_parse_iso_time() {
+        assert_eq!(parse_iso_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_iso_duration("P1DT12H"), Some(129600.0));
+        assert_eq!(parse_iso_duration("PT0S"), Some(0.0));
+        assert_eq!(parse_iso_duration("1H"), None);
+
+        assert_eq!(parse_iso_datetime("1970-01-01T00:00:00Z"), Some(0.0));
+        assert_eq!(parse_iso_datetime("2024-01-01T00:00:00Z"), Some(1704067200.0));
+        assert_eq!(parse_iso_datetime("2024-01-01T08:00:00.5+08:00"), Some(1704067200.5));
+    }
+
+    #[test]
+    fn test_parse_static_mpd() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT20S">
+  <BaseURL>cdn/</BaseURL>
+  <Period>
+    <AdaptationSet contentType="video" mimeType="video/mp4">
+      <SegmentTemplate timescale="1000" duration="4000" startNumber="1"
+                       media="$RepresentationID$/$Number$.m4s" initialization="$RepresentationID$/init.mp4"/>
+      <Representation id="720p" bandwidth="2000000" width="1280" height="720" codecs="avc1.64001f"/>
+      <Representation id="1080p" bandwidth="4000000" width="1920" height="1080" codecs="avc1.640028"/>
+    </AdaptationSet>
+    <AdaptationSet contentType="audio" mimeType="audio/mp4">
+      <ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011" value="cenc"/>
+      <ContentProtection schemeIdUri="urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"/>
+      <Representation id="audio" bandwidth="128000">
+        <SegmentList duration="4" timescale="1">
+          <Initialization sourceURL="audio.mp4" range="0-799"/>
+          <SegmentURL media="audio.mp4" mediaRange="800-1799"/>
+          <SegmentURL media="audio.mp4" mediaRange="1800-2799"/>
+        </SegmentList>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd = parse_mpd_content(content, "http://example.com/vod/manifest.mpd").unwrap();
+        assert!(!mpd.is_live);
+        assert_eq!(mpd.duration, Some(20.0));
+        assert_eq!(mpd.representations.len(), 3);
+
+        let best = mpd.best_representation().unwrap();
+        assert_eq!(best.id, "1080p");
+        assert_eq!(best.drm, None);
+        assert_eq!(best.init_segment().unwrap().uri, "http://example.com/vod/cdn/1080p/init.mp4");
+
+        let segments = best.media_segments(10, false, None, 0.0);
+        assert_eq!(segments.len(), 5);
+        assert_eq!(segments[0].uri, "http://example.com/vod/cdn/1080p/1.m4s");
+        assert_eq!(segments[4].uri, "http://example.com/vod/cdn/1080p/5.m4s");
+
+        let audio = &mpd.representations[2];
+        assert_eq!(audio.drm, Some(DrmSystem::Widevine));
+        assert_eq!(audio.addressing.name(), "SegmentList");
+        let segments = audio.media_segments(10, false, None, 0.0);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].byte_range, Some(ByteRange { length: 1000, offset: Some(1800) }));
+        assert_eq!(audio.init_segment().unwrap().byte_range, Some(ByteRange { length: 800, offset: Some(0) }));
+    }
+
+    #[test]
+    fn test_live_segment_numbers() {
+        let content = r#"<MPD type="dynamic" availabilityStartTime="2024-01-01T00:00:00Z">
+  <Period start="PT0S">
+    <AdaptationSet mimeType="video/mp4">
+      <Representation id="v" bandwidth="1000000">
+        <SegmentTemplate timescale="90000" media="v_$Time$.ts">
+          <SegmentTimeline>
+            <S t="900000" d="180000" r="2"/>
+            <S d="90000"/>
+            <S d="180000" r="-1"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+      <Representation id="d" bandwidth="500000">
+        <SegmentTemplate duration="2" startNumber="10" media="d_$Number$.ts"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd = parse_mpd_content(content, "http://example.com/live/manifest.mpd").unwrap();
+        assert!(mpd.is_live);
+        let start = mpd.availability_start.unwrap();
+
+        // 时间线：10 秒开始，3 个 2 秒、1 个 1 秒，之后按 2 秒重复到当前时间（第 25 秒）
+        let timeline = &mpd.representations[0];
+        let segments = timeline.media_segments(2, true, mpd.availability_start, start + 25.0);
+        let uris: Vec<&str> = segments.iter().map(|segment| segment.uri.as_str()).collect();
+        assert_eq!(uris, vec!["http://example.com/live/v_1890000.ts", "http://example.com/live/v_2070000.ts"]);
+
+        // 按时长：第 25 秒时最后一个完整发布的是第 11 个片段（序号 10 + 11）
+        let numbered = &mpd.representations[1];
+        let segments = numbered.media_segments(3, true, mpd.availability_start, start + 25.0);
+        let uris: Vec<&str> = segments.iter().map(|segment| segment.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec!["http://example.com/live/d_19.ts", "http://example.com/live/d_20.ts", "http://example.com/live/d_21.ts"]
+        );
+    }
+
+    #[test]
+    fn test_timeline_overflow() {
+        let content = r#"<MPD type="static" mediaPresentationDuration="PT20S">
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <Representation id="huge_r" bandwidth="1000000">
+        <SegmentTemplate media="r_$Time$.m4s">
+          <SegmentTimeline>
+            <S t="0" d="4" r="2"/>
+            <S d="1" r="9223372036854775807"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+      <Representation id="huge_d" bandwidth="1000000">
+        <SegmentTemplate media="d_$Time$.m4s">
+          <SegmentTimeline>
+            <S t="18446744073709551600" d="10"/>
+            <S d="18446744073709551615" r="1"/>
+            <S d="10"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+      <Representation id="huge_number" bandwidth="1000000">
+        <SegmentTemplate media="n_$Number$.m4s" startNumber="18446744073709551615" duration="1" presentationTimeOffset="18446744073709551615"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd = parse_mpd_content(content, "http://example.com/vod/manifest.mpd").unwrap();
+
+        // r 很大但没有溢出时，展开的片段数受上限约束
+        let segments = mpd.representations[0].media_segments(5, false, None, 0.0);
+        assert_eq!(segments.len(), 5);
+        assert_eq!(segments[4].uri, "http://example.com/vod/r_13.m4s");
+
+        // 时间溢出的 S 及其后的条目不再展开
+        let segments = mpd.representations[1].media_segments(5, false, None, 0.0);
+        let uris: Vec<&str> = segments.iter().map(|segment| segment.uri.as_str()).collect();
+        assert_eq!(uris, vec!["http://example.com/vod/d_18446744073709551600.m4s"]);
+
+        let segments = mpd.representations[2].media_segments(5, false, None, 0.0);
+        let uris: Vec<&str> = segments.iter().map(|segment| segment.uri.as_str()).collect();
+        assert_eq!(uris, vec!["http://example.com/vod/n_18446744073709551615.m4s"]);
+    }
+}
@@ -0,0 +1,913 @@
+// MPEG-TS 负载检查：按 188 字节的包解析下载到的数据，确认同步字节、PAT、PMT 和音视频流，
+// 避免把状态码为 200 的 HTML 错误页、认证页等当成正常的流；同时从视频 PES 中取出 SPS 识别分辨率和帧率，
+// 并按 PCR 估算码流码率；需要时统计连续计数器、PSI 间隔和 PCR 等传输流健康指标
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::codec::{self, Codec, MediaInfo, VideoParameters};
+
+// MPEG-TS 包长度和同步字节
+pub const TS_PACKET_SIZE: usize = 188;
+pub const TS_SYNC_BYTE: u8 = 0x47;
+// 重新同步时，同步字节还要在其后这么多个包的位置上重复出现，才认为找到了包起点
+const SYNC_CONFIRM_PACKETS: usize = 2;
+
+const PAT_PID: u16 = 0x0000;
+const TABLE_ID_PAT: u8 = 0x00;
+const TABLE_ID_PMT: u8 = 0x02;
+
+// 保留开头的字节用于识别非 TS 内容
+const HEAD_SIZE: usize = 512;
+// 查找 SPS 时最多缓存的视频基本流数据，以及用于推算帧率的 PTS 个数
+const MAX_VIDEO_BYTES: usize = 1024 * 1024;
+const MAX_PTS: usize = 120;
+
+// PCR 为 27 MHz 时钟，33 位基数 × 300 + 9 位扩展，超过该值回绕
+const PCR_HZ: f64 = 27_000_000.0;
+const PCR_WRAP: u64 = (1 << 33) * 300;
+// 相邻 PCR 的间隔超过该值（或倒退）视为时间戳不连续，不计入码率估算
+const MAX_PCR_GAP: u64 = 27_000_000;
+
+// TR 101 290 中的时间限制（27 MHz）：PAT / PMT 至少每 0.5 秒一次，PMT 中的 PID 至少每 5 秒出现一次，PCR 至少每 100 ms 一次
+const PSI_INTERVAL: u64 = 27_000_000 / 2;
+const PID_INTERVAL: u64 = 27_000_000 * 5;
+const PCR_INTERVAL: u64 = 27_000_000 / 10;
+const NULL_PID: u16 = 0x1FFF;
+
+// PMT 中声明的基本流
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementaryStream {
+    pub pid: u16,
+    pub stream_type: u8,
+    pub codec: Option<Codec>, // 由 stream_type 和描述符识别，不认识的类型为空
+}
+
+impl ElementaryStream {
+    pub fn is_video(&self) -> bool {
+        self.codec.is_some_and(|codec| codec.is_video())
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.codec.is_some_and(|codec| !codec.is_video())
+    }
+}
+
+// 内容检查结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentCheck {
+    Valid,                      // 有效的 MPEG-TS
+    OtherFormat(&'static str),  // FLV、MP4、WebVTT 等其他媒体格式，不按 TS 检查
+    Invalid(String),            // 不是有效的 TS，附带原因
+}
+
+// 传输流健康指标，参照 ETSI TR 101 290 的第一优先级指标和 PCR 相关的第二优先级指标，
+// 时间按 PCR 计算（不是下载时间），没有 PCR 时只统计连续计数器和同步错误
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TsHealth {
+    pub packets: u64,
+    pub sync_errors: u64,           // 1.1 / 1.2：包边界上不是同步字节，需要重新同步
+    pub pat_errors: u64,            // 1.3：PAT 间隔超过 0.5 秒、table_id 错误或被加扰
+    pub continuity_errors: u64,     // 1.4：连续计数器错误，通常是丢包
+    pub pmt_errors: u64,            // 1.5：PMT 间隔超过 0.5 秒或被加扰
+    pub pid_errors: u64,            // 1.6：PMT 中声明的 PID 超过 5 秒没有出现
+    pub continuity_errors_by_pid: Vec<PidErrors>, // 有连续计数器错误的 PID
+    pub pcr_discontinuities: u64,   // PCR 倒退或跳变超过 1 秒，且没有 discontinuity_indicator
+    pub pcr_repetition_errors: u64, // 相邻 PCR 间隔超过 100 ms
+    pub pcr_jitter_ms: Option<f64>, // PCR 与按平均传输码率推算的值之差的最大值，只对恒定码率的流（如组播）有意义
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PidErrors {
+    pub pid: u16,
+    pub errors: u64,
+}
+
+impl TsHealth {
+    // 第一优先级错误总数
+    pub fn priority1_errors(&self) -> u64 {
+        self.sync_errors + self.pat_errors + self.continuity_errors + self.pmt_errors + self.pid_errors
+    }
+}
+
+// 健康指标的统计状态，时间为累计的 PCR 时长（TsAnalyzer::pcr_ticks）
+#[derive(Debug, Default)]
+struct HealthMonitor {
+    continuity: HashMap<u16, (u8, bool)>, // 每个 PID 上一个包的连续计数器，以及是否已经重复过一次
+    continuity_errors: BTreeMap<u16, u64>,
+    last_pat: u64,
+    last_pmt: HashMap<u16, u64>,
+    last_seen: HashMap<u16, u64>, // PMT 中声明的基本流最后出现的时间
+    pat_errors: u64,
+    pmt_errors: u64,
+    pid_errors: u64,
+    pcr_discontinuities: u64,
+    pcr_repetition_errors: u64,
+    max_jitter_ticks: Option<f64>,
+}
+
+impl HealthMonitor {
+    // 有负载的包连续计数器加 1（允许重复一次），只有自适应字段的包不变，discontinuity_indicator 置位时不检查
+    fn check_continuity(&mut self, pid: u16, counter: u8, has_payload: bool, discontinuity: bool) {
+        let previous = self.continuity.get(&pid).copied();
+        let mut duplicate = false;
+        if let (Some((last, duplicated)), false) = (previous, discontinuity) {
+            let ok = if has_payload {
+                duplicate = counter == last && !duplicated;
+                counter == (last + 1) & 0x0F || duplicate
+            } else {
+                counter == last
+            };
+            if !ok {
+                *self.continuity_errors.entry(pid).or_default() += 1;
+            }
+        }
+        self.continuity.insert(pid, (counter, duplicate));
+    }
+
+    fn check_pid_interval(&mut self, pid: u16, now: u64) {
+        if let Some(last) = self.last_seen.get_mut(&pid) {
+            if now - *last > PID_INTERVAL {
+                self.pid_errors += 1;
+            }
+            *last = now;
+        }
+    }
+
+    fn pat(&mut self, now: u64) {
+        if now - self.last_pat > PSI_INTERVAL {
+            self.pat_errors += 1;
+        }
+        self.last_pat = now;
+    }
+
+    fn pmt(&mut self, pid: u16, now: u64) {
+        let last = self.last_pmt.insert(pid, now).unwrap_or(0);
+        if now - last > PSI_INTERVAL {
+            self.pmt_errors += 1;
+        }
+    }
+
+    // 正常的 PCR 间隔：按之前的平均传输码率推算该间隔的时长，与实际 PCR 差值的最大值为抖动
+    fn pcr_interval(&mut self, ticks: u64, bytes: u64, total_bytes: u64, total_ticks: u64) {
+        if ticks > PCR_INTERVAL {
+            self.pcr_repetition_errors += 1;
+        }
+        if total_bytes > 0 {
+            let expected = bytes as f64 * total_ticks as f64 / total_bytes as f64;
+            let jitter = (ticks as f64 - expected).abs();
+            self.max_jitter_ticks = Some(self.max_jitter_ticks.map_or(jitter, |max| max.max(jitter)));
+        }
+    }
+}
+
+// 流式分析器：下载时逐块喂入数据，不需要缓存整个片段
+#[derive(Debug, Default)]
+pub struct TsAnalyzer {
+    head: Vec<u8>,          // 开头的字节
+    pending: Vec<u8>,       // 不足一个包的剩余字节
+    total_bytes: u64,
+    packets: u64,
+    sync_errors: u64,       // 包边界上不是同步字节、需要重新同步的次数
+    resyncing: bool,        // 正在查找下一个包起点
+    pmt_pids: Vec<u16>,     // PAT 中各节目的 PMT PID
+    pat_found: bool,
+    pmt_found: bool,
+    streams: Vec<ElementaryStream>,
+    video_pid: Option<u16>,     // 第一个视频流，用于查找 SPS 和推算帧率
+    video_data: Vec<u8>,        // 从第一个视频 PES 开始缓存的基本流数据
+    video_parameters: Option<VideoParameters>,
+    video_pts: Vec<u64>,
+    pcr_pid: Option<u16>,       // PMT 中声明的 PCR PID
+    last_pcr: Option<(u64, u64)>, // 上一个 PCR 所在的包序号和值
+    pcr_bytes: u64,             // 正常 PCR 间隔内的字节数和时长，用于估算码率
+    pcr_ticks: u64,
+    health: Option<HealthMonitor>, // 只在需要时统计健康指标
+}
+
+impl TsAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 同时统计传输流健康指标
+    pub fn with_health() -> Self {
+        Self {
+            health: Some(HealthMonitor::default()),
+            ..Self::default()
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.total_bytes += data.len() as u64;
+        if self.head.len() < HEAD_SIZE {
+            let take = (HEAD_SIZE - self.head.len()).min(data.len());
+            self.head.extend_from_slice(&data[..take]);
+        }
+
+        self.pending.extend_from_slice(data);
+        let mut offset = 0;
+        while self.pending.len() - offset >= TS_PACKET_SIZE {
+            if !self.resyncing && self.pending[offset] != TS_SYNC_BYTE {
+                // 丢失同步；从包中间开始的数据在第一个包之前的字节不算同步错误
+                if self.packets > 0 {
+                    self.sync_errors += 1;
+                }
+                self.resyncing = true;
+            }
+            if self.resyncing {
+                match find_sync(&self.pending, offset) {
+                    SyncSearch::Found(position) => {
+                        offset = position;
+                        self.resyncing = false;
+                    }
+                    // 数据不够确认，保留候选位置之后的数据，等下一块数据到来
+                    SyncSearch::Pending(position) => {
+                        offset = position;
+                        break;
+                    }
+                    SyncSearch::NotFound => {
+                        offset = self.pending.len();
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            let packet: [u8; TS_PACKET_SIZE] = self.pending[offset..offset + TS_PACKET_SIZE].try_into().unwrap();
+            self.parse_packet(&packet);
+            offset += TS_PACKET_SIZE;
+        }
+        self.pending.drain(..offset);
+    }
+
+    pub fn check(&self) -> ContentCheck {
+        if self.total_bytes == 0 {
+            return ContentCheck::Invalid("没有收到数据".to_string());
+        }
+        if self.head.first() != Some(&TS_SYNC_BYTE) {
+            match identify_other(&self.head) {
+                Some(Ok(format)) => return ContentCheck::OtherFormat(format),
+                Some(Err(reason)) => return ContentCheck::Invalid(reason),
+                // 不认识的二进制数据：从包中间开始转发的 TS 重新同步后能找到 PAT，按 TS 检查，否则不检查
+                None if !self.pat_found || self.sync_errors * 10 > self.packets => {
+                    return ContentCheck::OtherFormat("unknown")
+                }
+                None => {}
+            }
+        }
+        if self.packets == 0 {
+            return ContentCheck::Invalid(format!("数据不足一个 TS 包（{} 字节）", self.total_bytes));
+        }
+        // 偶尔的丢包可以接受，同步错误过多说明不是 188 字节的 TS 包
+        if self.sync_errors * 10 > self.packets {
+            return ContentCheck::Invalid(format!(
+                "同步字节错误过多（{} 个包中 {} 次失去同步）",
+                self.packets, self.sync_errors
+            ));
+        }
+        if !self.pat_found {
+            return ContentCheck::Invalid("没有找到 PAT".to_string());
+        }
+        if !self.pmt_found {
+            return ContentCheck::Invalid("没有找到 PMT".to_string());
+        }
+        if !self.streams.iter().any(|stream| stream.is_video() || stream.is_audio()) {
+            return ContentCheck::Invalid("PMT 中没有音视频流".to_string());
+        }
+        ContentCheck::Valid
+    }
+
+    fn parse_packet(&mut self, packet: &[u8; TS_PACKET_SIZE]) {
+        self.packets += 1;
+
+        let payload_unit_start = packet[1] & 0x40 != 0;
+        let pid = u16::from_be_bytes([packet[1] & 0x1F, packet[2]]);
+        let adaptation_field_control = (packet[3] >> 4) & 0x03;
+        let discontinuity = adaptation_field_control & 0x02 != 0 && packet[4] > 0 && packet[5] & 0x80 != 0;
+        if adaptation_field_control & 0x02 != 0 && Some(pid) == self.pcr_pid {
+            if let Some(pcr) = parse_pcr(packet) {
+                self.record_pcr(pcr, discontinuity);
+            }
+        }
+        if let Some(health) = &mut self.health {
+            if pid != NULL_PID {
+                health.check_continuity(pid, packet[3] & 0x0F, adaptation_field_control & 0x01 != 0, discontinuity);
+                health.check_pid_interval(pid, self.pcr_ticks);
+            }
+            // PSI 不应加扰
+            let scrambled = packet[3] >> 6 != 0;
+            if scrambled && pid == PAT_PID {
+                health.pat_errors += 1;
+            } else if scrambled && self.pmt_pids.contains(&pid) {
+                health.pmt_errors += 1;
+            }
+        }
+        if adaptation_field_control & 0x01 == 0 {
+            return;
+        }
+
+        let mut start = 4;
+        if adaptation_field_control & 0x02 != 0 {
+            start += 1 + packet[4] as usize;
+        }
+        let Some(payload) = packet.get(start..) else {
+            return;
+        };
+
+        if Some(pid) == self.video_pid {
+            self.parse_video(payload_unit_start, payload);
+            return;
+        }
+
+        // 只解析 PSI 表的第一个包，PAT / PMT 通常都在一个包内
+        if !payload_unit_start || (pid != PAT_PID && !self.pmt_pids.contains(&pid)) {
+            return;
+        }
+        // pointer_field 之后才是表的开始
+        let Some(pointer) = payload.first() else {
+            return;
+        };
+        let Some(section) = payload.get(1 + *pointer as usize..) else {
+            return;
+        };
+
+        let now = self.pcr_ticks;
+        match section.first() {
+            Some(&TABLE_ID_PAT) if pid == PAT_PID => {
+                self.parse_pat(section);
+                if let Some(health) = &mut self.health {
+                    health.pat(now);
+                }
+            }
+            Some(&TABLE_ID_PMT) => {
+                self.parse_pmt(section);
+                if let Some(health) = &mut self.health {
+                    health.pmt(pid, now);
+                }
+            }
+            _ if pid == PAT_PID => {
+                if let Some(health) = &mut self.health {
+                    health.pat_errors += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 表头 8 字节，末尾 4 字节是 CRC，返回中间的内容
+    fn section_body(section: &[u8]) -> Option<&[u8]> {
+        if section.len() < 3 {
+            return None;
+        }
+        let section_length = u16::from_be_bytes([section[1] & 0x0F, section[2]]) as usize;
+        let end = 3 + section_length;
+        if section_length < 9 || end > section.len() {
+            return None;
+        }
+        Some(&section[8..end - 4])
+    }
+
+    fn parse_pat(&mut self, section: &[u8]) {
+        let Some(body) = Self::section_body(section) else {
+            return;
+        };
+
+        self.pat_found = true;
+        for entry in body.chunks_exact(4) {
+            let program_number = u16::from_be_bytes([entry[0], entry[1]]);
+            let pid = u16::from_be_bytes([entry[2] & 0x1F, entry[3]]);
+            // 节目号 0 是 NIT
+            if program_number != 0 && !self.pmt_pids.contains(&pid) {
+                self.pmt_pids.push(pid);
+            }
+        }
+    }
+
+    fn parse_pmt(&mut self, section: &[u8]) {
+        let Some(body) = Self::section_body(section) else {
+            return;
+        };
+        if body.len() < 4 {
+            return;
+        }
+
+        self.pmt_found = true;
+        // 只跟踪第一个节目的 PCR
+        if self.pcr_pid.is_none() {
+            self.pcr_pid = Some(u16::from_be_bytes([body[0] & 0x1F, body[1]])).filter(|pid| *pid != 0x1FFF);
+        }
+        let program_info_length = u16::from_be_bytes([body[2] & 0x0F, body[3]]) as usize;
+        let mut rest = body.get(4 + program_info_length..).unwrap_or_default();
+        while rest.len() >= 5 {
+            let es_info_length = u16::from_be_bytes([rest[3] & 0x0F, rest[4]]) as usize;
+            let descriptors = rest.get(5..5 + es_info_length).unwrap_or_default();
+            let stream = ElementaryStream {
+                stream_type: rest[0],
+                pid: u16::from_be_bytes([rest[1] & 0x1F, rest[2]]),
+                codec: Codec::from_stream_type(rest[0], descriptors),
+            };
+            if !self.streams.iter().any(|existing| existing.pid == stream.pid) {
+                if self.video_pid.is_none() && stream.is_video() {
+                    self.video_pid = Some(stream.pid);
+                }
+                if let Some(health) = &mut self.health {
+                    health.last_seen.insert(stream.pid, self.pcr_ticks);
+                }
+                self.streams.push(stream);
+            }
+            rest = rest.get(5 + es_info_length..).unwrap_or_default();
+        }
+    }
+
+    // 累计相邻 PCR 之间的字节数和时间差（两个 PCR 包之间的包都按 188 字节计），
+    // discontinuity_indicator 置位时时间戳允许跳变
+    fn record_pcr(&mut self, pcr: u64, discontinuity: bool) {
+        if let (Some((packet, last)), false) = (self.last_pcr, discontinuity) {
+            let ticks = (pcr + PCR_WRAP - last) % PCR_WRAP;
+            let bytes = (self.packets - packet) * TS_PACKET_SIZE as u64;
+            if ticks > 0 && ticks <= MAX_PCR_GAP {
+                if let Some(health) = &mut self.health {
+                    health.pcr_interval(ticks, bytes, self.pcr_bytes, self.pcr_ticks);
+                }
+                self.pcr_bytes += bytes;
+                self.pcr_ticks += ticks;
+            } else if let Some(health) = &mut self.health {
+                health.pcr_discontinuities += 1;
+            }
+        }
+        self.last_pcr = Some((self.packets, pcr));
+    }
+
+    // 健康指标，没有用 with_health 创建时为空；结束时仍未出现的 PAT / PMT / PID 也计为错误
+    pub fn health(&self) -> Option<TsHealth> {
+        let monitor = self.health.as_ref()?;
+        let now = self.pcr_ticks;
+
+        let mut pat_errors = monitor.pat_errors;
+        if now - monitor.last_pat > PSI_INTERVAL {
+            pat_errors += 1;
+        }
+        let mut pmt_errors = monitor.pmt_errors;
+        for pid in &self.pmt_pids {
+            if now - monitor.last_pmt.get(pid).copied().unwrap_or(0) > PSI_INTERVAL {
+                pmt_errors += 1;
+            }
+        }
+        let pid_errors = monitor.pid_errors + monitor.last_seen.values().filter(|last| now - **last > PID_INTERVAL).count() as u64;
+
+        Some(TsHealth {
+            packets: self.packets,
+            sync_errors: self.sync_errors,
+            pat_errors,
+            continuity_errors: monitor.continuity_errors.values().sum(),
+            pmt_errors,
+            pid_errors,
+            continuity_errors_by_pid: monitor
+                .continuity_errors
+                .iter()
+                .map(|(pid, errors)| PidErrors { pid: *pid, errors: *errors })
+                .collect(),
+            pcr_discontinuities: monitor.pcr_discontinuities,
+            pcr_repetition_errors: monitor.pcr_repetition_errors,
+            pcr_jitter_ms: monitor.max_jitter_ticks.map(|ticks| ticks / PCR_HZ * 1000.0),
+        })
+    }
+
+    // PCR 覆盖的 (字节数, 秒数)，没有两个以上连续的 PCR 时为空
+    pub fn pcr_span(&self) -> Option<(u64, f64)> {
+        (self.pcr_ticks > 0).then(|| (self.pcr_bytes, self.pcr_ticks as f64 / PCR_HZ))
+    }
+
+    fn video_codec(&self) -> Option<Codec> {
+        let pid = self.video_pid?;
+        self.streams.iter().find(|stream| stream.pid == pid)?.codec
+    }
+
+    // 视频 PES：记录 PTS，缓存基本流数据直到解析出 SPS
+    fn parse_video(&mut self, payload_unit_start: bool, payload: &[u8]) {
+        let data = if payload_unit_start {
+            // PES 头：起始码(3) + stream_id(1) + 长度(2) + 标志(2) + 头长度(1)
+            if payload.len() < 9 || payload[..3] != [0x00, 0x00, 0x01] {
+                return;
+            }
+            let header_length = payload[8] as usize;
+            if payload[7] & 0x80 != 0 && payload.len() >= 14 && self.video_pts.len() < MAX_PTS {
+                self.video_pts.push(parse_timestamp(&payload[9..14]));
+            }
+
+            // 上一个 PES 已经完整，先尝试从中找 SPS
+            if self.video_parameters.is_none() && !self.video_data.is_empty() {
+                self.video_parameters = self.video_codec().and_then(|codec| codec::parse_video_parameters(codec, &self.video_data));
+                if self.video_parameters.is_some() {
+                    self.video_data = Vec::new();
+                }
+            }
+            payload.get(9 + header_length..).unwrap_or_default()
+        } else if self.video_data.is_empty() {
+            // 还没遇到第一个 PES 的开始
+            return;
+        } else {
+            payload
+        };
+
+        if self.video_parameters.is_none() && self.video_data.len() < MAX_VIDEO_BYTES {
+            self.video_data.extend_from_slice(data);
+        }
+    }
+
+    // 从 PMT 和视频流得到的编码信息，没有 PMT 时为空
+    pub fn media_info(&self) -> Option<MediaInfo> {
+        if !self.pmt_found {
+            return None;
+        }
+
+        let mut audio_codecs = Vec::new();
+        for codec in self.streams.iter().filter(|stream| stream.is_audio()).filter_map(|stream| stream.codec) {
+            if !audio_codecs.contains(&codec) {
+                audio_codecs.push(codec);
+            }
+        }
+
+        let video_codec = self.video_codec();
+        let parameters = self
+            .video_parameters
+            .or_else(|| codec::parse_video_parameters(video_codec?, &self.video_data));
+        Some(MediaInfo {
+            video_codec,
+            audio_codecs,
+            width: parameters.map(|parameters| parameters.width),
+            height: parameters.map(|parameters| parameters.height),
+            frame_rate: parameters
+                .and_then(|parameters| parameters.frame_rate)
+                .or_else(|| codec::frame_rate_from_pts(&self.video_pts)),
+        })
+    }
+}
+
+enum SyncSearch {
+    Found(usize),
+    Pending(usize),
+    NotFound,
+}
+
+// 从 from 开始查找包起点：负载中恰好为 0x47 的字节在一个包长之后通常不再是同步字节，
+// 要求同步字节按包长重复出现，避免锁定到负载上
+fn find_sync(data: &[u8], from: usize) -> SyncSearch {
+    'candidates: for position in from..data.len() {
+        if data[position] != TS_SYNC_BYTE {
+            continue;
+        }
+        for packet in 1..=SYNC_CONFIRM_PACKETS {
+            match data.get(position + packet * TS_PACKET_SIZE) {
+                Some(&TS_SYNC_BYTE) => {}
+                Some(_) => continue 'candidates,
+                None => return SyncSearch::Pending(position),
+            }
+        }
+        return SyncSearch::Found(position);
+    }
+    SyncSearch::NotFound
+}
+
+// 自适应字段中的 PCR（27 MHz），没有 PCR 时为空
+fn parse_pcr(packet: &[u8; TS_PACKET_SIZE]) -> Option<u64> {
+    let length = packet[4] as usize;
+    if length < 7 || packet[5] & 0x10 == 0 {
+        return None;
+    }
+    let bytes = &packet[6..12];
+    let base = (bytes[0] as u64) << 25
+        | (bytes[1] as u64) << 17
+        | (bytes[2] as u64) << 9
+        | (bytes[3] as u64) << 1
+        | bytes[4] as u64 >> 7;
+    let extension = ((bytes[4] as u64 & 0x01) << 8) | bytes[5] as u64;
+    Some(base * 300 + extension)
+}
+
+// PES 头中 33 位的 PTS / DTS（5 字节，中间夹着标记位）
+fn parse_timestamp(bytes: &[u8]) -> u64 {
+    ((bytes[0] as u64 >> 1) & 0x07) << 30
+        | (bytes[1] as u64) << 22
+        | (bytes[2] as u64 >> 1) << 15
+        | (bytes[3] as u64) << 7
+        | bytes[4] as u64 >> 1
+}
+
+// 开头不是同步字节时识别内容：其他媒体格式返回格式名，HTML、播放列表等文本返回无效的原因，
+// 不认识的二进制数据返回 None
+fn identify_other(head: &[u8]) -> Option<Result<&'static str, String>> {
+    if head.starts_with(b"FLV") {
+        return Some(Ok("FLV"));
+    }
+    if head.len() >= 8 && matches!(&head[4..8], b"ftyp" | b"styp" | b"moof" | b"moov" | b"sidx" | b"mdat" | b"free") {
+        return Some(Ok("MP4"));
+    }
+    // ADTS 和 MPEG 音频帧都以 11 位的帧同步开始，layer 为 0 的是 ADTS
+    if head.starts_with(b"ID3") || (head.len() >= 2 && head[0] == 0xFF && head[1] & 0xF6 == 0xF0) {
+        return Some(Ok("AAC"));
+    }
+    if head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE0 == 0xE0 && head[1] & 0x06 != 0 {
+        return Some(Ok("MP3"));
+    }
+    if head.starts_with(b"OggS") {
+        return Some(Ok("Ogg"));
+    }
+    if head.starts_with(b"\xEF\xBB\xBFWEBVTT") || head.starts_with(b"WEBVTT") {
+        return Some(Ok("WebVTT"));
+    }
+
+    let text = String::from_utf8_lossy(head).trim_start().to_lowercase();
+    if text.starts_with("<!doctype html") || text.starts_with("<html") || text.contains("<body") {
+        return Some(Err("内容是 HTML 页面（可能是错误页或认证页），不是 MPEG-TS".to_string()));
+    }
+    if text.starts_with("#extm3u") {
+        return Some(Err("内容是 M3U8 播放列表，不是 MPEG-TS".to_string()));
+    }
+    if text.starts_with('{') || text.starts_with("<?xml") {
+        return Some(Err("内容是 JSON / XML 文本，不是 MPEG-TS".to_string()));
+    }
+    if std::str::from_utf8(head).is_ok() {
+        return Some(Err("内容是文本，不是 MPEG-TS".to_string()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 构造一个只有负载的 TS 包，PSI 表需要带 pointer_field
+    fn packet(pid: u16, payload_unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![
+            TS_SYNC_BYTE,
+            ((payload_unit_start as u8) << 6) | (pid >> 8) as u8,
+            pid as u8,
+            0x10,
+        ];
+        packet.extend_from_slice(payload);
+        packet.resize(TS_PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    // PSI 表：pointer_field + 表头 + 内容 + CRC（不校验，填 0）
+    fn psi(table_id: u8, body: &[u8]) -> Vec<u8> {
+        let section_length = (5 + body.len() + 4) as u16;
+        let mut data = vec![0x00, table_id, 0xB0 | (section_length >> 8) as u8, section_length as u8, 0x00, 0x01, 0xC1, 0x00, 0x00];
+        data.extend_from_slice(body);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data
+    }
+
+    // PAT 指向 PID 0x1000 的 PMT，PMT 中有给定类型的基本流（PID 从 0x100 开始）
+    fn transport_stream(stream_types: &[u8]) -> Vec<u8> {
+        let mut pmt = vec![0xE1, 0x00, 0xF0, 0x00];
+        for (index, stream_type) in stream_types.iter().enumerate() {
+            let pid = 0x100 + index as u16;
+            pmt.extend_from_slice(&[*stream_type, 0xE0 | (pid >> 8) as u8, pid as u8, 0xF0, 0x00]);
+        }
+
+        let mut data = packet(PAT_PID, true, &psi(TABLE_ID_PAT, &[0x00, 0x01, 0xF0, 0x00]));
+        data.extend(packet(0x1000, true, &psi(TABLE_ID_PMT, &pmt)));
+        for index in 0..stream_types.len() {
+            data.extend(packet(0x100 + index as u16, true, &[0x00, 0x00, 0x01, 0xE0]));
+        }
+        data
+    }
+
+    fn analyze(data: &[u8]) -> ContentCheck {
+        let mut analyzer = TsAnalyzer::new();
+        // 按不对齐的小块喂入，模拟网络分块
+        for chunk in data.chunks(100) {
+            analyzer.feed(chunk);
+        }
+        analyzer.check()
+    }
+
+    #[test]
+    fn test_valid_transport_stream() {
+        let data = transport_stream(&[0x1B, 0x0F]);
+        let mut analyzer = TsAnalyzer::new();
+        analyzer.feed(&data);
+        assert_eq!(analyzer.check(), ContentCheck::Valid);
+        assert_eq!(analyzer.streams.len(), 2);
+        assert!(analyzer.streams[0].is_video());
+        assert!(analyzer.streams[1].is_audio());
+
+        assert_eq!(analyze(&data), ContentCheck::Valid);
+    }
+
+    #[test]
+    fn test_invalid_content() {
+        let html = b"<!DOCTYPE html><html><body>Please log in</body></html>";
+        assert!(matches!(analyze(html), ContentCheck::Invalid(reason) if reason.contains("HTML")));
+        assert!(matches!(analyze(b""), ContentCheck::Invalid(_)));
+        assert_eq!(analyze(b"FLV\x01\x05\x00\x00\x00\x09"), ContentCheck::OtherFormat("FLV"));
+        assert_eq!(analyze(b"\x00\x00\x00\x18stypmsdh"), ContentCheck::OtherFormat("MP4"));
+
+        // 同步字节对，但没有 PSI，或者 PMT 中只有数据流
+        let no_psi = packet(0x100, true, &[0x00, 0x00, 0x01, 0xE0]).repeat(5);
+        assert_eq!(analyze(&no_psi), ContentCheck::Invalid("没有找到 PAT".to_string()));
+        assert_eq!(analyze(&transport_stream(&[0x05])), ContentCheck::Invalid("PMT 中没有音视频流".to_string()));
+
+        // 只有开头是 0x47 的二进制数据
+        let mut garbage = vec![0x47];
+        garbage.extend((0..2000u32).map(|value| (value * 7 % 251) as u8 | 1));
+        assert!(matches!(analyze(&garbage), ContentCheck::Invalid(_)));
+    }
+
+    #[test]
+    fn test_other_binary_formats() {
+        // MP3 电台（MPEG-1 Layer III 帧头）、Ogg 和不认识的二进制数据不按 TS 检查
+        assert_eq!(analyze(b"\xFF\xFB\x90\x64\x00\x00\x00\x00"), ContentCheck::OtherFormat("MP3"));
+        assert_eq!(analyze(b"\xFF\xF1\x50\x80\x02\x1F\xFC"), ContentCheck::OtherFormat("AAC"));
+        assert_eq!(analyze(b"OggS\x00\x02\x00\x00"), ContentCheck::OtherFormat("Ogg"));
+        let binary: Vec<u8> = (0..2000u32).map(|value| (value * 7 % 251) as u8 | 0x80).collect();
+        assert_eq!(analyze(&binary), ContentCheck::OtherFormat("unknown"));
+    }
+
+    #[test]
+    fn test_mid_packet_start() {
+        // 从包中间开始转发的 TS：重新同步后正常检查，开头不完整的包不算同步错误
+        let mut data = packet(0x100, false, &[0xAB; 184])[100..].to_vec();
+        data.extend(transport_stream(&[0x1B, 0x0F]));
+        assert_eq!(analyze(&data), ContentCheck::Valid);
+
+        let mut analyzer = TsAnalyzer::new();
+        analyzer.feed(&data);
+        assert_eq!((analyzer.packets, analyzer.sync_errors), (4, 0));
+    }
+
+    #[test]
+    fn test_resync_ignores_payload_sync_bytes() {
+        // 负载中每 10 个字节有一个 0x47，重新同步时不能锁定到这些字节上
+        let payload: Vec<u8> = (0..184).map(|index| if index % 10 == 5 { TS_SYNC_BYTE } else { 0xAB }).collect();
+
+        let mut data = packet(0x100, false, &payload)[100..].to_vec();
+        data.extend(transport_stream(&[0x1B, 0x0F]));
+        let mut analyzer = TsAnalyzer::with_health();
+        for chunk in data.chunks(100) {
+            analyzer.feed(chunk);
+        }
+        assert_eq!(analyzer.check(), ContentCheck::Valid);
+        assert_eq!((analyzer.packets, analyzer.sync_errors), (4, 0));
+
+        // 流中间插入一段带 0x47 的垃圾数据：只算一次失去同步，之后的包全部找回
+        let mut data = transport_stream(&[0x1B, 0x0F]);
+        data.extend(&payload[..150]);
+        for _ in 0..4 {
+            data.extend(packet(0x100, false, &payload));
+        }
+        let mut analyzer = TsAnalyzer::with_health();
+        for chunk in data.chunks(100) {
+            analyzer.feed(chunk);
+        }
+        assert_eq!((analyzer.packets, analyzer.sync_errors), (8, 1));
+        assert_eq!(analyzer.health().map(|health| health.sync_errors), Some(1));
+    }
+
+    // 带 PTS 的视频 PES 头
+    fn pes(pts: u64, data: &[u8]) -> Vec<u8> {
+        let mut pes = vec![0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x80, 0x05];
+        pes.extend_from_slice(&[
+            0x21 | ((pts >> 29) & 0x0E) as u8,
+            (pts >> 22) as u8,
+            ((pts >> 14) & 0xFE) as u8 | 0x01,
+            (pts >> 7) as u8,
+            ((pts << 1) & 0xFE) as u8 | 0x01,
+        ]);
+        pes.extend_from_slice(data);
+        pes
+    }
+
+    #[test]
+    fn test_media_info() {
+        // MPEG-2 视频 + MP2 音频，序列头中的帧率代码无效，按 PTS 间隔（3600）推算为 25 帧
+        let mut data = transport_stream(&[0x02, 0x03]);
+        data.extend(packet(0x100, true, &pes(90_000, &[0x00, 0x00, 0x01, 0xB3, 0x2D, 0x02, 0x40, 0x20])));
+        for frame in 1..10 {
+            data.extend(packet(0x100, true, &pes(90_000 + frame * 3600, &[0x00, 0x00, 0x01, 0x00])));
+        }
+
+        let mut analyzer = TsAnalyzer::new();
+        for chunk in data.chunks(100) {
+            analyzer.feed(chunk);
+        }
+        assert_eq!(analyzer.check(), ContentCheck::Valid);
+        let info = analyzer.media_info().unwrap();
+        assert_eq!(info.video_codec, Some(Codec::Mpeg2));
+        assert_eq!(info.audio_codecs, vec![Codec::Mp2]);
+        assert_eq!(info.resolution().as_deref(), Some("720x576"));
+        assert_eq!(info.frame_rate, Some(25.0));
+        assert_eq!(info.label(), "576p MPEG-2");
+
+        assert_eq!(TsAnalyzer::new().media_info(), None);
+    }
+
+    // 只有自适应字段、带 PCR 的包
+    fn pcr_packet(pid: u16, pcr: u64) -> Vec<u8> {
+        let (base, extension) = (pcr / 300, pcr % 300);
+        let mut packet = vec![
+            TS_SYNC_BYTE,
+            (pid >> 8) as u8,
+            pid as u8,
+            0x20,
+            183,
+            0x10,
+            (base >> 25) as u8,
+            (base >> 17) as u8,
+            (base >> 9) as u8,
+            (base >> 1) as u8,
+            ((base & 0x01) << 7) as u8 | 0x7E | (extension >> 8) as u8,
+            extension as u8,
+        ];
+        packet.resize(TS_PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    #[test]
+    fn test_pcr_bitrate() {
+        // PMT 的 PCR PID 为 0x100；每 40 ms 一个 PCR，中间 9 个包，即 10 × 188 字节 / 40 ms
+        let mut data = transport_stream(&[0x1B]);
+        let mut pcr = PCR_WRAP - 100_000; // 中途回绕
+        for _ in 0..=25 {
+            data.extend(pcr_packet(0x100, pcr));
+            data.extend(packet(0x101, false, &[]).repeat(9));
+            pcr = (pcr + 1_080_000) % PCR_WRAP;
+        }
+        // 时间戳跳变的间隔不计入
+        data.extend(pcr_packet(0x100, 5 * 27_000_000));
+
+        let mut analyzer = TsAnalyzer::new();
+        analyzer.feed(&data);
+        let (bytes, secs) = analyzer.pcr_span().unwrap();
+        assert_eq!(bytes, 25 * 10 * TS_PACKET_SIZE as u64);
+        assert!((secs - 1.0).abs() < 1e-9);
+
+        assert_eq!(TsAnalyzer::new().pcr_span(), None);
+    }
+
+    fn with_counter(mut packet: Vec<u8>, counter: u8) -> Vec<u8> {
+        packet[3] |= counter & 0x0F;
+        packet
+    }
+
+    #[test]
+    fn test_health() {
+        // 每 40 ms 固定 5 个包：PCR（视频 PID）、视频、音频，以及每 0.4 秒一次的 PAT + PMT（其余时候为空包）
+        let mut data = transport_stream(&[0x1B, 0x0F]);
+        let (mut video, mut audio, mut psi_counter) = (0u8, 0u8, 0u8);
+        for index in 0..30u64 {
+            data.extend(with_counter(pcr_packet(0x100, 27_000_000 + index * 1_080_000), video));
+            video += 1;
+            data.extend(with_counter(packet(0x100, false, &[]), video));
+            audio += 1;
+            match index {
+                // 丢了一个音频包
+                7 => data.extend(packet(NULL_PID, false, &[])),
+                _ => data.extend(with_counter(packet(0x101, false, &[]), audio)),
+            }
+            if index % 10 == 9 {
+                psi_counter += 1;
+                data.extend(with_counter(packet(PAT_PID, true, &psi(TABLE_ID_PAT, &[0x00, 0x01, 0xF0, 0x00])), psi_counter));
+                data.extend(with_counter(packet(0x1000, true, &psi(TABLE_ID_PMT, &[0xE1, 0x00, 0xF0, 0x00])), psi_counter));
+            } else if index == 12 {
+                // 重复一次的视频包不算错误
+                data.extend(with_counter(packet(0x100, false, &[]), video));
+                data.extend(packet(NULL_PID, false, &[]));
+            } else {
+                data.extend(packet(NULL_PID, false, &[]).repeat(2));
+            }
+        }
+
+        let mut analyzer = TsAnalyzer::with_health();
+        for chunk in data.chunks(1000) {
+            analyzer.feed(chunk);
+        }
+        let health = analyzer.health().unwrap();
+        assert_eq!(health.continuity_errors, 1);
+        assert_eq!(health.continuity_errors_by_pid, vec![PidErrors { pid: 0x101, errors: 1 }]);
+        assert_eq!((health.pat_errors, health.pmt_errors, health.pid_errors, health.sync_errors), (0, 0, 0, 0));
+        assert_eq!((health.pcr_discontinuities, health.pcr_repetition_errors), (0, 0));
+        assert!(health.pcr_jitter_ms.unwrap() < 1e-6);
+        assert_eq!(health.priority1_errors(), 1);
+
+        // PCR 跳变：置位 discontinuity_indicator 的不算错误；之后 0.6 秒没有 PAT / PMT，音频 PID 缺失超过 5 秒
+        let mut jump = pcr_packet(0x100, 90 * 27_000_000);
+        jump[5] |= 0x80;
+        data.extend(with_counter(jump, video));
+        for index in 1..=150u64 {
+            data.extend(with_counter(pcr_packet(0x100, 90 * 27_000_000 + index * 1_080_000), video));
+        }
+        data.extend(with_counter(pcr_packet(0x100, 27_000_000), video));
+
+        let mut analyzer = TsAnalyzer::with_health();
+        analyzer.feed(&data);
+        let health = analyzer.health().unwrap();
+        assert_eq!(health.pcr_discontinuities, 1);
+        assert_eq!((health.pat_errors, health.pmt_errors, health.pid_errors), (1, 1, 1));
+        assert_eq!(health.continuity_errors, 1);
+
+        assert_eq!(TsAnalyzer::new().health(), None);
+    }
+}
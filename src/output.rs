@@ -0,0 +1,367 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::batch::BatchResult;
+use crate::channel::Channel;
+use crate::m3u8_parser::MediaType;
+use crate::policy::VerdictStatus;
+use crate::report::{self, Column};
+use crate::speed_test::{BitrateSource, SpeedTestResult};
+
+// 结果输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,   // 人类可读文本（默认）
+    Json,   // 单个 JSON 文档（批量模式为数组）
+    Ndjson, // 每行一个 JSON 对象
+    Csv,    // 逗号分隔报表
+    Tsv,    // 制表符分隔报表
+}
+
+// 批量测试的一条输出记录：频道信息 + 展开的测试结果字段
+#[derive(Serialize)]
+struct BatchRecord<'a> {
+    name: &'a str,
+    group: Option<&'a str>,
+    tvg_id: Option<&'a str>,
+    #[serde(flatten)]
+    result: &'a SpeedTestResult,
+}
+
+impl<'a> From<&'a BatchResult> for BatchRecord<'a> {
+    fn from(item: &'a BatchResult) -> Self {
+        Self {
+            name: &item.channel.name,
+            group: item.channel.group_title.as_deref(),
+            tvg_id: item.channel.tvg_id.as_deref(),
+            result: &item.result,
+        }
+    }
+}
+
+// 输出单个URL的测试结果
+pub fn print_single(result: &SpeedTestResult, format: OutputFormat, verbose: bool) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            if verbose {
+                println!("\n=== 测试结果 ===");
+                println!("URL: {}", result.url);
+                println!("状态: {}", if result.success { "成功" } else { "失败" });
+                println!("延迟: {:.2} ms", result.delay_ms);
+                println!("下载速度: {:.0} kbps", result.speed_kbps);
+                println!("下载大小: {:.2} MB", result.size_mb);
+                println!("测试时长: {:.2} 秒", result.duration_secs);
+                println!("协议类型: {}", result.protocol_type);
+
+                if let Some(details) = &result.details {
+                    println!("详细信息: {}", details);
+                }
+                let timings: Vec<String> = [
+                    ("DNS", result.dns_ms),
+                    ("TCP 连接", result.tcp_connect_ms),
+                    ("TLS 握手", result.tls_handshake_ms),
+                    ("首字节", result.ttfb_ms),
+                    ("首个媒体字节", result.first_media_byte_ms),
+                ]
+                .iter()
+                .filter_map(|(name, value)| value.map(|ms| format!("{} {:.1} ms", name, ms)))
+                .collect();
+                if !timings.is_empty() {
+                    println!("耗时分解: {}", timings.join(", "));
+                }
+                match (result.content_valid, &result.content_error) {
+                    (Some(false), Some(error)) => println!("内容检查: 无效 ({})", error),
+                    (Some(true), _) => println!("内容检查: 有效的 MPEG-TS"),
+                    _ => {}
+                }
+                if let Some(media) = &result.media {
+                    let mut parts = Vec::new();
+                    if let Some(codec) = media.video_codec {
+                        parts.push(format!("视频 {}", codec));
+                    }
+                    if let Some(resolution) = media.resolution() {
+                        parts.push(resolution);
+                    }
+                    if let Some(frame_rate) = media.frame_rate {
+                        parts.push(format!("{} fps", frame_rate));
+                    }
+                    if !media.audio_codecs.is_empty() {
+                        parts.push(format!(
+                            "音频 {}",
+                            media.audio_codecs.iter().map(|codec| codec.to_string()).collect::<Vec<_>>().join("/")
+                        ));
+                    }
+                    println!("码流: {}", parts.join(", "));
+                }
+                if let Some(bitrate) = &result.media_bitrate {
+                    let source = match bitrate.source {
+                        BitrateSource::Segments => "片段大小 / 时长",
+                        BitrateSource::Pcr => "PCR",
+                        BitrateSource::Advertised => "声明码率",
+                    };
+                    println!(
+                        "码流码率: {:.0} kbps（{}）, 余量 {:.2} 倍",
+                        bitrate.kbps,
+                        source,
+                        result.headroom.unwrap_or_default()
+                    );
+                }
+                if let Some(health) = &result.ts_health {
+                    println!(
+                        "TS 健康: {} 个包, 第一优先级错误 {}（同步 {}, PAT {}, 连续计数器 {}, PMT {}, PID {}）",
+                        health.packets,
+                        health.priority1_errors(),
+                        health.sync_errors,
+                        health.pat_errors,
+                        health.continuity_errors,
+                        health.pmt_errors,
+                        health.pid_errors
+                    );
+                    for pid in &health.continuity_errors_by_pid {
+                        println!("  PID 0x{:04X}: 连续计数器错误 {} 次", pid.pid, pid.errors);
+                    }
+                    println!(
+                        "PCR: 不连续 {} 次, 间隔超过 100 ms {} 次, 最大抖动 {}",
+                        health.pcr_discontinuities,
+                        health.pcr_repetition_errors,
+                        health.pcr_jitter_ms.map_or("未知".to_string(), |jitter| format!("{:.3} ms", jitter))
+                    );
+                }
+                if let Some(key) = &result.key {
+                    println!(
+                        "AES-128 密钥: {} (获取耗时 {:.0} ms{})",
+                        key.uri,
+                        key.delay_ms,
+                        if key.decrypted { ", 首个片段解密成功" } else { "" }
+                    );
+                }
+                if let Some(init) = &result.init_segment {
+                    println!(
+                        "初始化片段: {} ({} bytes, 品牌 {})",
+                        init.uri,
+                        init.bytes,
+                        if init.brands.is_empty() { "无".to_string() } else { init.brands.join(",") }
+                    );
+                }
+                if let Some(live) = &result.live {
+                    println!(
+                        "直播刷新: 观察 {:.0} 秒, 刷新 {} 次（无新片段 {} 次）, 新片段 {} 个, 错过 {} 个, 最长发布间隔 {:.1} 秒",
+                        live.window_secs,
+                        live.reloads,
+                        live.stale_reloads,
+                        live.new_segments,
+                        live.missed_segments,
+                        live.max_publish_gap_secs
+                    );
+                    println!(
+                        "实时性: 发布{}, 下载{}实时 ({:.2} 倍)",
+                        if live.on_time { "准时" } else { "延迟" },
+                        if live.keeps_up { "跟得上" } else { "跟不上" },
+                        live.realtime_ratio
+                    );
+                }
+                if let Some(low_latency) = &result.low_latency {
+                    println!(
+                        "低延迟: 观察 {:.0} 秒, 阻塞刷新 {} 次（提前返回 {} 次）, 新部分片段 {} 个（失败 {} 个）",
+                        low_latency.window_secs,
+                        low_latency.blocking_reloads,
+                        low_latency.early_responses,
+                        low_latency.parts_published,
+                        low_latency.parts_failed
+                    );
+                    println!(
+                        "部分片段发布: 平均间隔 {:.2} 秒 (PART-TARGET {} 秒), 阻塞等待平均 {:.2} 秒 / 最长 {:.2} 秒, 下载平均 {:.0} ms, {}{}",
+                        low_latency.avg_part_interval_secs,
+                        low_latency.part_target,
+                        low_latency.avg_reload_wait_secs,
+                        low_latency.max_reload_wait_secs,
+                        low_latency.avg_part_download_ms,
+                        if low_latency.on_time { "准时" } else { "延迟" },
+                        if low_latency.preload_hints { ", 有预加载提示" } else { "" }
+                    );
+                }
+                if let Some(player) = &result.player {
+                    println!(
+                        "播放模拟: 起播 {:.0} ms, 卡顿 {} 次共 {:.2} 秒, 慢于实时的片段 {}/{} 个",
+                        player.startup_ms,
+                        player.rebuffer_count,
+                        player.stall_secs,
+                        player.slow_segments,
+                        player.segments.len()
+                    );
+                    for segment in &player.segments {
+                        if segment.ok {
+                            println!(
+                                "  片段 #{}: 时长 {:.2} 秒, 下载 {:.2} 秒, {} bytes",
+                                segment.sequence, segment.duration, segment.download_secs, segment.bytes
+                            );
+                        } else {
+                            println!("  片段 #{}: 下载失败 ({:.2} 秒)", segment.sequence, segment.download_secs);
+                        }
+                    }
+                }
+                if let Some(variants) = &result.variants {
+                    println!("子播放列表:");
+                    for variant in variants {
+                        let resolution = variant.resolution.as_deref().unwrap_or("未知分辨率");
+                        match &variant.error {
+                            Some(error) => println!("  ✗ {} bps, {}: {} ({})", variant.bandwidth, resolution, error, variant.url),
+                            None => println!(
+                                "  {} {} bps, {}: 片段 {}/{}, {:.0} kbps ({})",
+                                if variant.sustainable { "✓" } else { "△" },
+                                variant.bandwidth,
+                                resolution,
+                                variant.segments_ok,
+                                variant.segments_total,
+                                variant.speed_kbps,
+                                variant.url
+                            ),
+                        }
+                    }
+                    if let Some(selected) = &result.selected_variant {
+                        println!("选择的子播放列表: {}", selected);
+                    }
+                }
+                if let Some(renditions) = &result.renditions {
+                    println!("备选媒体:");
+                    for rendition in renditions {
+                        let kind = match rendition.media_type {
+                            MediaType::Audio => "音轨",
+                            MediaType::Subtitles => "字幕",
+                            _ => "其他",
+                        };
+                        let required = if rendition.required { ", 必需" } else { "" };
+                        match &rendition.error {
+                            Some(error) => println!("  ✗ {} {}{}: {} ({})", kind, rendition.name, required, error, rendition.url),
+                            None => println!(
+                                "  ✓ {} {}{}: 片段 {}/{}, {:.0} kbps ({})",
+                                kind,
+                                rendition.name,
+                                required,
+                                rendition.segments_ok,
+                                rendition.segments_total,
+                                rendition.speed_kbps,
+                                rendition.url
+                            ),
+                        }
+                    }
+                }
+                if let Some(verdict) = &result.verdict {
+                    let status = match verdict.status {
+                        VerdictStatus::Pass => "通过",
+                        VerdictStatus::Degraded => "降级",
+                        VerdictStatus::DrmProtected => "DRM加密",
+                        VerdictStatus::Fail => "不通过",
+                    };
+                    println!("判定: {} ({})", status, verdict.reason);
+                }
+            } else {
+                // 非verbose模式，只输出速率（kbps）
+                if result.success {
+                    println!("{:.0} kbps", result.speed_kbps);
+                } else {
+                    println!("0 kbps");
+                }
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(result)?),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            // 单URL测试没有频道名，用URL代替
+            let item = BatchResult {
+                channel: Channel::new(&result.url, &result.url),
+                result: result.clone(),
+            };
+            write_batch(std::io::stdout().lock(), &[item], format, &[])?;
+        }
+    }
+
+    Ok(())
+}
+
+// 批量测试完成后一次性写出所有结果，columns 只对 CSV/TSV 生效（为空时输出默认列）
+pub fn write_batch<W: Write>(
+    mut writer: W,
+    results: &[BatchResult],
+    format: OutputFormat,
+    columns: &[Column],
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            // 每行 "频道名,URL,速率"
+            for item in results {
+                let speed_kbps = if item.result.success { item.result.speed_kbps } else { 0.0 };
+                writeln!(writer, "{},{},{:.0} kbps", item.channel.name, item.channel.url, speed_kbps)?;
+            }
+        }
+        OutputFormat::Json => {
+            let records: Vec<BatchRecord> = results.iter().map(BatchRecord::from).collect();
+            writeln!(writer, "{}", serde_json::to_string_pretty(&records)?)?;
+        }
+        OutputFormat::Ndjson => {
+            for item in results {
+                writeln!(writer, "{}", serde_json::to_string(&BatchRecord::from(item))?)?;
+            }
+        }
+        OutputFormat::Csv => report::write_report(writer, results, columns, b',')?,
+        OutputFormat::Tsv => report::write_report(writer, results, columns, b'\t')?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn results() -> Vec<BatchResult> {
+        let mut channel = Channel::new("CCTV-1", "http://example.com/1.m3u8");
+        channel.group_title = Some("央视".to_string());
+        vec![
+            BatchResult {
+                channel,
+                result: SpeedTestResult {
+                    url: "http://example.com/1.m3u8".to_string(),
+                    success: true,
+                    speed_kbps: 4096.0,
+                    ..Default::default()
+                },
+            },
+            BatchResult {
+                channel: Channel::new("CCTV-2", "http://example.com/2.ts"),
+                result: SpeedTestResult::failure("http://example.com/2.ts", "HTTP直连", crate::speed_test::ErrorKind::Timeout, "超时".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut buffer = Vec::new();
+        write_batch(&mut buffer, &results(), OutputFormat::Json, &[]).unwrap();
+        let records: Vec<Value> = serde_json::from_slice(&buffer).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["name"], "CCTV-1");
+        assert_eq!(records[0]["group"], "央视");
+        assert_eq!(records[0]["success"], true);
+        assert_eq!(records[0]["speed_kbps"], 4096.0);
+        assert_eq!(records[1]["group"], Value::Null);
+        assert_eq!(records[1]["error_kind"], "timeout");
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        let mut buffer = Vec::new();
+        write_batch(&mut buffer, &results(), OutputFormat::Ndjson, &[]).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        let records: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["url"], "http://example.com/1.m3u8");
+        assert_eq!(records[1]["name"], "CCTV-2");
+        assert_eq!(records[1]["success"], false);
+    }
+}
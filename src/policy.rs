@@ -0,0 +1,396 @@
+use serde::{Deserialize, Serialize};
+
+use crate::speed_test::SpeedTestResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerdictStatus {
+    Pass,
+    Fail,
+    // 主流可用，但必需的备选媒体（如单独的音轨）不可用
+    Degraded,
+    // 需要 DRM 许可证，无法直接播放
+    DrmProtected,
+}
+
+// 判定结果及原因
+#[derive(Debug, Clone, Serialize)]
+pub struct Verdict {
+    pub status: VerdictStatus,
+    pub reason: String,
+}
+
+impl Verdict {
+    fn pass(reason: String) -> Self {
+        Self { status: VerdictStatus::Pass, reason }
+    }
+
+    fn fail(reason: String) -> Self {
+        Self { status: VerdictStatus::Fail, reason }
+    }
+
+    fn degraded(reason: String) -> Self {
+        Self { status: VerdictStatus::Degraded, reason }
+    }
+
+    fn drm_protected(reason: String) -> Self {
+        Self { status: VerdictStatus::DrmProtected, reason }
+    }
+}
+
+// 配置文件中的 [policy] 部分，未设置的项不参与判定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    // 最低速度，默认与 2-speed-check.sh 的保留规则一致
+    pub min_speed_kbps: f64,
+    pub max_delay_ms: Option<f64>,
+    // HLS 片段下载成功比例下限（0.0 ~ 1.0）
+    pub min_segment_success_ratio: Option<f64>,
+    pub min_bytes: Option<u64>,
+    // 设置后，有声明 BANDWIDTH 的 HLS 流改为要求 实测速度 / 声明码率 不低于该值，
+    // 代替 min_speed_kbps；没有声明码率的流仍按 min_speed_kbps 判定
+    pub min_bandwidth_ratio: Option<f64>,
+    // 设置后，能估算出码流码率的流要求 余量（实测速度 / 码流码率）不低于该值，
+    // 优先于 min_bandwidth_ratio 和 min_speed_kbps
+    pub min_headroom: Option<f64>,
+    // 开启 ts_health 时，允许的 TS 第一优先级错误（连续计数器、PAT/PMT、PID、同步）个数上限
+    pub max_ts_errors: Option<u64>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_speed_kbps: 1000.0,
+            max_delay_ms: None,
+            min_segment_success_ratio: None,
+            min_bytes: None,
+            min_bandwidth_ratio: None,
+            min_headroom: None,
+            max_ts_errors: None,
+        }
+    }
+}
+
+pub struct Policy {
+    config: PolicyConfig,
+}
+
+impl Policy {
+    pub fn new(config: PolicyConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn evaluate(&self, result: &SpeedTestResult) -> Verdict {
+        let verdict = self.evaluate_stream(result);
+        if verdict.status != VerdictStatus::Pass {
+            return verdict;
+        }
+
+        let failed = result
+            .renditions
+            .iter()
+            .flatten()
+            .find(|rendition| rendition.required && !rendition.reachable);
+        match failed {
+            Some(rendition) => Verdict::degraded(format!(
+                "{}, 但必需的备选媒体 {} (分组 {}) 不可用",
+                verdict.reason, rendition.name, rendition.group_id
+            )),
+            None => verdict,
+        }
+    }
+
+    fn evaluate_stream(&self, result: &SpeedTestResult) -> Verdict {
+        if let Some(system) = result.drm {
+            return Verdict::drm_protected(format!("{} DRM 加密，无法直接播放", system));
+        }
+
+        if !result.success {
+            return Verdict::fail(format!(
+                "测试失败: {}",
+                result.details.as_deref().unwrap_or("未知原因")
+            ));
+        }
+
+        // 部分片段不是有效的 MPEG-TS（如 HTML 错误页），流不可靠
+        if result.content_valid == Some(false) {
+            return Verdict::fail(format!(
+                "内容无效: {}",
+                result.content_error.as_deref().unwrap_or("不是有效的 MPEG-TS")
+            ));
+        }
+
+        if let Some(min_bytes) = self.config.min_bytes {
+            if result.bytes_downloaded < min_bytes {
+                return Verdict::fail(format!(
+                    "下载字节数 {} 低于下限 {}",
+                    result.bytes_downloaded, min_bytes
+                ));
+            }
+        }
+
+        if let (Some(min_ratio), Some(total), Some(ok)) = (
+            self.config.min_segment_success_ratio,
+            result.segments_total,
+            result.segments_ok,
+        ) {
+            let ratio = if total > 0 { ok as f64 / total as f64 } else { 0.0 };
+            if ratio < min_ratio {
+                return Verdict::fail(format!(
+                    "片段成功率 {}/{} 低于下限 {:.0}%",
+                    ok,
+                    total,
+                    min_ratio * 100.0
+                ));
+            }
+        }
+
+        if let (Some(max_errors), Some(health)) = (self.config.max_ts_errors, &result.ts_health) {
+            let errors = health.priority1_errors();
+            if errors > max_errors {
+                return Verdict::fail(format!(
+                    "TS 第一优先级错误 {} 个超过上限 {}（连续计数器 {}, PAT {}, PMT {}, PID {}, 同步 {}）",
+                    errors,
+                    max_errors,
+                    health.continuity_errors,
+                    health.pat_errors,
+                    health.pmt_errors,
+                    health.pid_errors,
+                    health.sync_errors
+                ));
+            }
+        }
+
+        if let Some(max_delay_ms) = self.config.max_delay_ms {
+            if result.delay_ms > max_delay_ms {
+                return Verdict::fail(format!(
+                    "延迟 {:.0} ms 超过上限 {:.0} ms",
+                    result.delay_ms, max_delay_ms
+                ));
+            }
+        }
+
+        if let (Some(min_headroom), Some(headroom)) = (self.config.min_headroom, result.headroom) {
+            if headroom < min_headroom {
+                return Verdict::fail(format!(
+                    "余量 {:.2} 倍低于下限 {:.2} 倍（实测速度 {:.0} kbps，码流码率 {:.0} kbps）",
+                    headroom,
+                    min_headroom,
+                    result.speed_kbps,
+                    result.speed_kbps / headroom
+                ));
+            }
+            return Verdict::pass(format!("余量 {:.2} 倍", headroom));
+        }
+
+        // speed_kbps 以 1024 bit 为单位，声明的 BANDWIDTH 单位为 bps
+        if let (Some(min_ratio), Some(bandwidth)) = (self.config.min_bandwidth_ratio, result.advertised_bandwidth) {
+            if bandwidth > 0 {
+                let ratio = result.speed_kbps * 1024.0 / bandwidth as f64;
+                if ratio < min_ratio {
+                    return Verdict::fail(format!(
+                        "实测速度为声明码率的 {:.2} 倍，低于下限 {:.2} 倍",
+                        ratio, min_ratio
+                    ));
+                }
+                return Verdict::pass(format!("实测速度为声明码率的 {:.2} 倍", ratio));
+            }
+        }
+
+        if result.speed_kbps < self.config.min_speed_kbps {
+            return Verdict::fail(format!(
+                "速度 {:.0} kbps 低于下限 {:.0} kbps",
+                result.speed_kbps, self.config.min_speed_kbps
+            ));
+        }
+
+        Verdict::pass(format!(
+            "速度 {:.0} kbps 不低于 {:.0} kbps",
+            result.speed_kbps, self.config.min_speed_kbps
+        ))
+    }
+
+    // 给结果附上判定
+    pub fn apply(&self, result: &mut SpeedTestResult) {
+        result.verdict = Some(self.evaluate(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drm::DrmSystem;
+
+    fn hls_result(speed_kbps: f64, segments_ok: usize, bandwidth: Option<u64>) -> SpeedTestResult {
+        SpeedTestResult {
+            url: "http://a.com/live.m3u8".to_string(),
+            success: true,
+            delay_ms: 200.0,
+            speed_kbps,
+            bytes_downloaded: 1_000_000,
+            segments_total: Some(5),
+            segments_ok: Some(segments_ok),
+            advertised_bandwidth: bandwidth,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_policy_speed_threshold() {
+        let policy = Policy::new(PolicyConfig::default());
+
+        assert_eq!(policy.evaluate(&hls_result(1500.0, 5, None)).status, VerdictStatus::Pass);
+        assert_eq!(policy.evaluate(&hls_result(800.0, 5, None)).status, VerdictStatus::Fail);
+
+        let failed = SpeedTestResult::failure("http://a.com", "HTTP直连", crate::speed_test::ErrorKind::Timeout, "请求超时".to_string());
+        let verdict = policy.evaluate(&failed);
+        assert_eq!(verdict.status, VerdictStatus::Fail);
+        assert_eq!(verdict.reason, "测试失败: 请求超时");
+    }
+
+    #[test]
+    fn test_policy_limits() {
+        let policy = Policy::new(PolicyConfig {
+            min_speed_kbps: 0.0,
+            max_delay_ms: Some(100.0),
+            min_segment_success_ratio: Some(0.8),
+            min_bytes: Some(2_000_000),
+            min_bandwidth_ratio: None,
+            min_headroom: None,
+            max_ts_errors: None,
+        });
+
+        let verdict = policy.evaluate(&hls_result(5000.0, 5, None));
+        assert_eq!(verdict.status, VerdictStatus::Fail);
+        assert!(verdict.reason.starts_with("下载字节数"));
+
+        let mut result = hls_result(5000.0, 3, None);
+        result.bytes_downloaded = 3_000_000;
+        assert!(policy.evaluate(&result).reason.starts_with("片段成功率 3/5"));
+
+        result.segments_ok = Some(4);
+        assert!(policy.evaluate(&result).reason.starts_with("延迟 200 ms"));
+    }
+
+    #[test]
+    fn test_invalid_content_fails() {
+        let policy = Policy::new(PolicyConfig::default());
+        let mut result = hls_result(5000.0, 4, None);
+        result.content_valid = Some(true);
+        assert_eq!(policy.evaluate(&result).status, VerdictStatus::Pass);
+
+        result.content_valid = Some(false);
+        result.content_error = Some("没有找到 PAT".to_string());
+        let verdict = policy.evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::Fail);
+        assert_eq!(verdict.reason, "内容无效: 没有找到 PAT");
+    }
+
+    #[test]
+    fn test_bandwidth_ratio_mode() {
+        let policy = Policy::new(PolicyConfig {
+            min_bandwidth_ratio: Some(1.2),
+            ..Default::default()
+        });
+
+        // 4 Mbps 的流测得约 6 Mbps
+        assert_eq!(policy.evaluate(&hls_result(6000.0, 5, Some(4_000_000))).status, VerdictStatus::Pass);
+        // 4 Mbps 的流只测得约 4.1 Mbps，低于 1.2 倍
+        assert_eq!(policy.evaluate(&hls_result(4000.0, 5, Some(4_000_000))).status, VerdictStatus::Fail);
+        // 没有声明码率时回退到 min_speed_kbps
+        assert_eq!(policy.evaluate(&hls_result(4000.0, 5, None)).status, VerdictStatus::Pass);
+    }
+
+    #[test]
+    fn test_headroom_mode() {
+        let policy = Policy::new(PolicyConfig {
+            min_headroom: Some(1.5),
+            min_bandwidth_ratio: Some(1.0),
+            ..Default::default()
+        });
+
+        // 5 Mbps 的速度对 1 Mbps 的码流很宽裕，对 4 Mbps 的码流余量不足，即使达到了声明码率
+        let mut result = hls_result(5000.0, 5, Some(4_000_000));
+        result.headroom = Some(5.0);
+        assert_eq!(policy.evaluate(&result).status, VerdictStatus::Pass);
+        result.headroom = Some(1.25);
+        let verdict = policy.evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::Fail);
+        assert!(verdict.reason.starts_with("余量 1.25 倍低于下限 1.50 倍"));
+
+        // 估算不出码率时回退到 min_bandwidth_ratio / min_speed_kbps
+        result.headroom = None;
+        assert_eq!(policy.evaluate(&result).status, VerdictStatus::Pass);
+        assert_eq!(policy.evaluate(&hls_result(800.0, 5, None)).status, VerdictStatus::Fail);
+    }
+
+    #[test]
+    fn test_ts_error_limit() {
+        use crate::mpeg_ts::TsHealth;
+
+        let policy = Policy::new(PolicyConfig {
+            max_ts_errors: Some(0),
+            ..Default::default()
+        });
+        let mut result = hls_result(5000.0, 5, None);
+        assert_eq!(policy.evaluate(&result).status, VerdictStatus::Pass);
+
+        result.ts_health = Some(TsHealth { packets: 1000, ..Default::default() });
+        assert_eq!(policy.evaluate(&result).status, VerdictStatus::Pass);
+
+        result.ts_health = Some(TsHealth { packets: 1000, continuity_errors: 3, ..Default::default() });
+        let verdict = policy.evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::Fail);
+        assert!(verdict.reason.starts_with("TS 第一优先级错误 3 个超过上限 0"));
+    }
+
+    #[test]
+    fn test_drm_protected() {
+        let policy = Policy::new(PolicyConfig::default());
+        let result = SpeedTestResult::drm_protected("http://a.com/live.m3u8", DrmSystem::Widevine, "测试".to_string());
+
+        let verdict = policy.evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::DrmProtected);
+        assert_eq!(verdict.reason, "Widevine DRM 加密，无法直接播放");
+    }
+
+    #[test]
+    fn test_required_rendition_degrades() {
+        use crate::m3u8_parser::MediaType;
+        use crate::speed_test::RenditionResult;
+
+        let rendition = |media_type, required, reachable| RenditionResult {
+            media_type,
+            group_id: "aud".to_string(),
+            name: "国语".to_string(),
+            language: None,
+            channels: None,
+            url: "http://a.com/audio.m3u8".to_string(),
+            required,
+            reachable,
+            segments_total: 5,
+            segments_ok: if reachable { 5 } else { 0 },
+            speed_kbps: 0.0,
+            error: None,
+        };
+        let policy = Policy::new(PolicyConfig::default());
+
+        let mut result = hls_result(1500.0, 5, None);
+        result.renditions = Some(vec![rendition(MediaType::Audio, true, true), rendition(MediaType::Subtitles, false, false)]);
+        assert_eq!(policy.evaluate(&result).status, VerdictStatus::Pass);
+
+        result.renditions = Some(vec![rendition(MediaType::Audio, true, false)]);
+        let verdict = policy.evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::Degraded);
+        assert!(verdict.reason.ends_with("必需的备选媒体 国语 (分组 aud) 不可用"));
+        // 降级的频道不算通过，不会被导出
+        policy.apply(&mut result);
+        assert!(!result.passed());
+
+        // 主流本身不合格时仍判定为不合格
+        let mut slow = hls_result(800.0, 5, None);
+        slow.renditions = result.renditions.clone();
+        assert_eq!(policy.evaluate(&slow).status, VerdictStatus::Fail);
+    }
+}
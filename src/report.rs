@@ -0,0 +1,288 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::io::Write;
+
+use crate::batch::BatchResult;
+
+// CSV/TSV 报表的列，列名与 JSON 输出的字段名一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    #[value(name = "name")]
+    Name,
+    #[value(name = "group")]
+    Group,
+    #[value(name = "tvg_id")]
+    TvgId,
+    #[value(name = "url")]
+    Url,
+    #[value(name = "success")]
+    Success,
+    #[value(name = "delay_ms")]
+    DelayMs,
+    #[value(name = "speed_kbps")]
+    SpeedKbps,
+    #[value(name = "size_mb")]
+    SizeMb,
+    #[value(name = "duration_secs")]
+    DurationSecs,
+    #[value(name = "protocol_type")]
+    ProtocolType,
+    #[value(name = "error_kind")]
+    ErrorKind,
+    #[value(name = "details")]
+    Details,
+    #[value(name = "bytes_downloaded")]
+    BytesDownloaded,
+    #[value(name = "dns_ms")]
+    DnsMs,
+    #[value(name = "tcp_connect_ms")]
+    TcpConnectMs,
+    #[value(name = "tls_handshake_ms")]
+    TlsHandshakeMs,
+    #[value(name = "ttfb_ms")]
+    TtfbMs,
+    #[value(name = "first_media_byte_ms")]
+    FirstMediaByteMs,
+    #[value(name = "content_valid")]
+    ContentValid,
+    #[value(name = "content_error")]
+    ContentError,
+    #[value(name = "video_codec")]
+    VideoCodec,
+    #[value(name = "audio_codecs")]
+    AudioCodecs,
+    #[value(name = "resolution")]
+    Resolution,
+    #[value(name = "frame_rate")]
+    FrameRate,
+    #[value(name = "media_bitrate_kbps")]
+    MediaBitrateKbps,
+    #[value(name = "bitrate_source")]
+    BitrateSource,
+    #[value(name = "headroom")]
+    Headroom,
+    #[value(name = "ts_p1_errors")]
+    TsP1Errors,
+    #[value(name = "cc_errors")]
+    CcErrors,
+    #[value(name = "pcr_discontinuities")]
+    PcrDiscontinuities,
+    #[value(name = "pcr_jitter_ms")]
+    PcrJitterMs,
+    #[value(name = "segments_total")]
+    SegmentsTotal,
+    #[value(name = "segments_ok")]
+    SegmentsOk,
+    #[value(name = "advertised_bandwidth")]
+    AdvertisedBandwidth,
+    #[value(name = "live_on_time")]
+    LiveOnTime,
+    #[value(name = "live_keeps_up")]
+    LiveKeepsUp,
+    #[value(name = "live_realtime_ratio")]
+    LiveRealtimeRatio,
+    #[value(name = "ll_part_interval_secs")]
+    LlPartIntervalSecs,
+    #[value(name = "ll_on_time")]
+    LlOnTime,
+    #[value(name = "player_startup_ms")]
+    PlayerStartupMs,
+    #[value(name = "player_rebuffer_count")]
+    PlayerRebufferCount,
+    #[value(name = "player_stall_secs")]
+    PlayerStallSecs,
+    #[value(name = "selected_variant")]
+    SelectedVariant,
+    #[value(name = "renditions_failed")]
+    RenditionsFailed,
+    #[value(name = "drm")]
+    Drm,
+    #[value(name = "verdict")]
+    Verdict,
+    #[value(name = "verdict_reason")]
+    VerdictReason,
+}
+
+// 未指定列时输出的默认列
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Name,
+    Column::Group,
+    Column::TvgId,
+    Column::Url,
+    Column::Success,
+    Column::DelayMs,
+    Column::SpeedKbps,
+    Column::SizeMb,
+    Column::DurationSecs,
+    Column::ProtocolType,
+    Column::ErrorKind,
+    Column::Details,
+    Column::Verdict,
+    Column::VerdictReason,
+];
+
+impl Column {
+    // 表头与 --columns 中的列名相同，都取自 #[value(name = ...)]
+    pub fn header(&self) -> String {
+        self.to_possible_value().expect("每一列都有列名").get_name().to_string()
+    }
+
+    fn value(&self, item: &BatchResult) -> String {
+        let result = &item.result;
+        match self {
+            Column::Name => item.channel.name.clone(),
+            Column::Group => item.channel.group_title.clone().unwrap_or_default(),
+            Column::TvgId => item.channel.tvg_id.clone().unwrap_or_default(),
+            Column::Url => result.url.clone(),
+            Column::Success => result.success.to_string(),
+            Column::DelayMs => format!("{:.0}", result.delay_ms),
+            Column::SpeedKbps => format!("{:.0}", result.speed_kbps),
+            Column::SizeMb => format!("{:.2}", result.size_mb),
+            Column::DurationSecs => format!("{:.2}", result.duration_secs),
+            Column::ProtocolType => result.protocol_type.clone(),
+            Column::ErrorKind => result.error_kind.map(enum_name).unwrap_or_default(),
+            Column::Details => result.details.clone().unwrap_or_default(),
+            Column::BytesDownloaded => result.bytes_downloaded.to_string(),
+            Column::DnsMs => milliseconds(result.dns_ms),
+            Column::TcpConnectMs => milliseconds(result.tcp_connect_ms),
+            Column::TlsHandshakeMs => milliseconds(result.tls_handshake_ms),
+            Column::TtfbMs => milliseconds(result.ttfb_ms),
+            Column::FirstMediaByteMs => milliseconds(result.first_media_byte_ms),
+            Column::ContentValid => optional(result.content_valid),
+            Column::ContentError => result.content_error.clone().unwrap_or_default(),
+            Column::VideoCodec => result.media.as_ref().and_then(|media| media.video_codec).map(enum_name).unwrap_or_default(),
+            Column::AudioCodecs => result
+                .media
+                .as_ref()
+                .map(|media| media.audio_codecs.iter().copied().map(enum_name).collect::<Vec<_>>().join(","))
+                .unwrap_or_default(),
+            Column::Resolution => result.media.as_ref().and_then(|media| media.resolution()).unwrap_or_default(),
+            Column::FrameRate => optional(result.media.as_ref().and_then(|media| media.frame_rate)),
+            Column::MediaBitrateKbps => optional(result.media_bitrate.map(|bitrate| format!("{:.0}", bitrate.kbps))),
+            Column::BitrateSource => result.media_bitrate.map(|bitrate| enum_name(bitrate.source)).unwrap_or_default(),
+            Column::Headroom => optional(result.headroom.map(|headroom| format!("{:.2}", headroom))),
+            Column::TsP1Errors => optional(result.ts_health.as_ref().map(|health| health.priority1_errors())),
+            Column::CcErrors => optional(result.ts_health.as_ref().map(|health| health.continuity_errors)),
+            Column::PcrDiscontinuities => optional(result.ts_health.as_ref().map(|health| health.pcr_discontinuities)),
+            Column::PcrJitterMs => optional(
+                result
+                    .ts_health
+                    .as_ref()
+                    .and_then(|health| health.pcr_jitter_ms)
+                    .map(|jitter| format!("{:.3}", jitter)),
+            ),
+            Column::SegmentsTotal => optional(result.segments_total),
+            Column::SegmentsOk => optional(result.segments_ok),
+            Column::AdvertisedBandwidth => optional(result.advertised_bandwidth),
+            Column::LiveOnTime => optional(result.live.as_ref().map(|live| live.on_time)),
+            Column::LiveKeepsUp => optional(result.live.as_ref().map(|live| live.keeps_up)),
+            Column::LiveRealtimeRatio => optional(result.live.as_ref().map(|live| format!("{:.2}", live.realtime_ratio))),
+            Column::LlPartIntervalSecs => {
+                optional(result.low_latency.as_ref().map(|low_latency| format!("{:.2}", low_latency.avg_part_interval_secs)))
+            }
+            Column::LlOnTime => optional(result.low_latency.as_ref().map(|low_latency| low_latency.on_time)),
+            Column::PlayerStartupMs => optional(result.player.as_ref().map(|player| format!("{:.0}", player.startup_ms))),
+            Column::PlayerRebufferCount => optional(result.player.as_ref().map(|player| player.rebuffer_count)),
+            Column::PlayerStallSecs => optional(result.player.as_ref().map(|player| format!("{:.2}", player.stall_secs))),
+            Column::SelectedVariant => result.selected_variant.clone().unwrap_or_default(),
+            Column::RenditionsFailed => optional(
+                result
+                    .renditions
+                    .as_ref()
+                    .map(|renditions| renditions.iter().filter(|rendition| !rendition.reachable).count()),
+            ),
+            Column::Drm => result.drm.map(enum_name).unwrap_or_default(),
+            Column::Verdict => result.verdict.as_ref().map(|verdict| enum_name(verdict.status)).unwrap_or_default(),
+            Column::VerdictReason => result.verdict.as_ref().map(|verdict| verdict.reason.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+// 枚举值使用与 JSON 输出相同的名称
+fn enum_name<T: serde::Serialize>(value: T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(|name| name.to_string()))
+        .unwrap_or_default()
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn milliseconds(value: Option<f64>) -> String {
+    optional(value.map(|ms| format!("{:.1}", ms)))
+}
+
+// 写出 CSV（delimiter 为 b','）或 TSV（delimiter 为 b'\t'）报表，第一行为列名
+pub fn write_report<W: Write>(
+    writer: W,
+    results: &[BatchResult],
+    columns: &[Column],
+    delimiter: u8,
+) -> Result<()> {
+    let columns = if columns.is_empty() { DEFAULT_COLUMNS } else { columns };
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+
+    writer.write_record(columns.iter().map(|column| column.header()))?;
+    for item in results {
+        writer.write_record(columns.iter().map(|column| column.value(item)))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::speed_test::{ErrorKind, SpeedTestResult};
+
+    fn results() -> Vec<BatchResult> {
+        let ok = SpeedTestResult {
+            url: "http://a.com/1.m3u8".to_string(),
+            success: true,
+            delay_ms: 120.4,
+            speed_kbps: 2048.6,
+            protocol_type: "HLS/M3U8".to_string(),
+            ..Default::default()
+        };
+
+        vec![
+            BatchResult { channel: Channel::new("CCTV-5, 体育", "http://a.com/1.m3u8"), result: ok },
+            BatchResult {
+                channel: Channel::new("广东\t卫视", "http://a.com/2"),
+                result: SpeedTestResult::failure("http://a.com/2", "HTTP直连", ErrorKind::HttpStatus, "HTTP错误: 404".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_write_csv_quotes_commas() {
+        let mut buffer = Vec::new();
+        write_report(&mut buffer, &results(), &[Column::Name, Column::SpeedKbps, Column::ErrorKind], b',').unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "name,speed_kbps,error_kind\n\"CCTV-5, 体育\",2049,\n广东\t卫视,0,http_status\n"
+        );
+    }
+
+    #[test]
+    fn test_write_tsv_default_columns() {
+        let mut buffer = Vec::new();
+        write_report(&mut buffer, &results(), &[], b'\t').unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0].split('\t').count(), DEFAULT_COLUMNS.len());
+        assert!(lines[0].starts_with("name\tgroup\ttvg_id\turl\tsuccess"));
+        assert!(lines[1].starts_with("CCTV-5, 体育\t\t\thttp://a.com/1.m3u8\ttrue\t120\t2049"));
+        // 含分隔符的字段被加上引号
+        assert!(lines[2].starts_with("\"广东\t卫视\""));
+    }
+}
@@ -3,49 +3,590 @@ use reqwest::Client;
 use std::time::{Duration, Instant};
 use futures::StreamExt;
 use tokio::time::timeout;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-use crate::m3u8_parser::M3u8Parser;
+use crate::drm::DrmSystem;
+use crate::m3u8_parser::{ByteRange, KeyMethod, M3u8Parser, MasterPlaylist, MediaSegment, ParsedM3u8, VariantStream};
+use crate::mpd_parser::MpdParser;
+use crate::codec::MediaInfo;
+use crate::mpeg_ts::{ContentCheck, TsAnalyzer, TsHealth};
+use crate::policy::{Verdict, VerdictStatus};
+
+mod dash;
+mod encryption;
+mod init_segment;
+mod live;
+mod low_latency;
+mod player;
+mod renditions;
+mod timing;
+mod variants;
+
+pub use encryption::KeyCheck;
+pub use init_segment::InitSegment;
+pub use live::LiveStats;
+pub use low_latency::LowLatencyStats;
+pub use player::PlayerStats;
+pub use renditions::RenditionResult;
+pub use variants::VariantResult;
+use timing::ConnectionTiming;
 
 // HEAD检查结果枚举
 #[derive(Debug)]
 enum HeadCheckResult {
     M3U8,           // HEAD成功，识别为M3U8
+    Dash,           // 识别为MPEG-DASH（HEAD成功且为MPD，或HEAD失败但URL以.mpd结尾）
     DirectUrl,      // HEAD成功，识别为直接URL
     FailedM3U8Suffix, // HEAD失败，但URL以.m3u8结尾
-    FailedNonM3U8,  // HEAD失败，URL不以.m3u8结尾
+    FailedNonM3U8(bool), // HEAD失败，URL不以.m3u8结尾；为 true 时在 DNS 解析或建立连接时就已失败
+}
+
+// 失败原因分类，供 JSON 输出等机器处理使用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Timeout,     // 超时
+    Dns,         // 域名解析失败
+    Connect,     // 连接建立失败
+    HttpStatus,  // HTTP 状态码错误
+    HeadFailed,  // HEAD 请求失败且跳过测试
+    NoSegments,  // M3U8 中没有可用的媒体片段
+    Key,         // HLS 解密密钥不可用或无法正确解密
+    Drm,         // 需要 DRM 许可证，无法直接播放
+    InvalidUrl,  // URL 格式错误
+    Network,     // 传输过程中的其他网络错误
+    Other,
+}
+
+// 测试过程中可归类的错误
+#[derive(Debug)]
+pub enum TestError {
+    HttpStatus(reqwest::StatusCode),
+    NoSegments,
+    Key(String),
+    Drm(DrmSystem),
+}
+
+impl std::fmt::Display for TestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestError::HttpStatus(status) => write!(f, "HTTP错误: {}", status),
+            TestError::NoSegments => write!(f, "未找到有效的媒体片段"),
+            TestError::Key(reason) => write!(f, "密钥不可用: {}", reason),
+            TestError::Drm(system) => write!(f, "需要 {} DRM 许可证", system),
+        }
+    }
+}
+
+impl std::error::Error for TestError {}
+
+// 根据错误链判断失败原因
+pub fn classify_error(error: &anyhow::Error) -> ErrorKind {
+    if let Some(test_error) = error.downcast_ref::<TestError>() {
+        return match test_error {
+            TestError::HttpStatus(_) => ErrorKind::HttpStatus,
+            TestError::NoSegments => ErrorKind::NoSegments,
+            TestError::Key(_) => ErrorKind::Key,
+            TestError::Drm(_) => ErrorKind::Drm,
+        };
+    }
+
+    if error.downcast_ref::<url::ParseError>().is_some() {
+        return ErrorKind::InvalidUrl;
+    }
+
+    if let Some(reqwest_error) = error.downcast_ref::<reqwest::Error>() {
+        if reqwest_error.is_timeout() {
+            return ErrorKind::Timeout;
+        }
+        if reqwest_error.is_builder() {
+            return ErrorKind::InvalidUrl;
+        }
+        if reqwest_error.is_status() {
+            return ErrorKind::HttpStatus;
+        }
+        if reqwest_error.is_connect() {
+            // hyper 的连接错误中 DNS 失败表现为 "dns error"
+            let mut source = std::error::Error::source(reqwest_error);
+            while let Some(err) = source {
+                if err.to_string().contains("dns error") {
+                    return ErrorKind::Dns;
+                }
+                source = err.source();
+            }
+            return ErrorKind::Connect;
+        }
+        return ErrorKind::Network;
+    }
+
+    ErrorKind::Other
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct SpeedTestResult {
     pub url: String,
     pub success: bool,
-    pub delay_ms: f64,
+    pub delay_ms: f64,                       // 直连为下载请求的首字节时间，HLS / DASH 为第一个媒体片段请求的首字节时间
     pub speed_kbps: f64,
     pub size_mb: f64,
     pub duration_secs: f64,
     pub protocol_type: String,
     pub details: Option<String>,
+    pub error_kind: Option<ErrorKind>,
+    pub bytes_downloaded: u64,
+    pub dns_ms: Option<f64>,                 // 单独的探测连接上的 DNS 解析耗时，IP 地址的 URL 为空
+    pub tcp_connect_ms: Option<f64>,         // 探测连接的 TCP 连接耗时
+    pub tls_handshake_ms: Option<f64>,       // 探测连接的 TLS 握手耗时，只有 https
+    pub ttfb_ms: Option<f64>,                // 发出请求到收到响应头（直连为下载请求，HLS / DASH 为第一个媒体片段）
+    pub first_media_byte_ms: Option<f64>,    // 测试开始到收到第一个媒体字节（包括连接耗时探测，HLS / DASH 包括获取播放列表）
+    pub content_valid: Option<bool>,         // 下载的数据是否为有效的 MPEG-TS，FLV、fMP4 等其他格式不检查
+    pub content_error: Option<String>,       // 内容无效的原因
+    pub media: Option<MediaInfo>,            // 从 TS 码流中识别的编码格式、分辨率和帧率
+    pub media_bitrate: Option<MediaBitrate>, // 估算的码流码率
+    pub headroom: Option<f64>,               // 余量：实测速度 / 码流码率，低于 1 时无法流畅播放
+    pub ts_health: Option<TsHealth>,         // 直连：开启 ts_health 时的连续计数器、PCR 等传输流健康指标
+    pub segments_total: Option<usize>,       // HLS / DASH：测试的片段数
+    pub segments_ok: Option<usize>,          // HLS / DASH：下载成功的片段数
+    pub advertised_bandwidth: Option<u64>,   // HLS：主播放列表中声明的 BANDWIDTH（bps）
+    pub key: Option<KeyCheck>,               // HLS：AES-128 密钥的检查结果
+    pub drm: Option<DrmSystem>,              // 检测到的 DRM 系统，有值时 protocol_type 为 DRM-protected
+    pub init_segment: Option<InitSegment>,   // HLS：EXT-X-MAP 初始化片段的检查结果
+    pub live: Option<LiveStats>,             // HLS 直播模式的刷新统计
+    pub low_latency: Option<LowLatencyStats>, // HLS 低延迟模式的部分片段发布统计
+    pub player: Option<PlayerStats>,         // HLS 播放模拟模式的缓冲统计
+    pub variants: Option<Vec<VariantResult>>, // HLS 逐个测试子播放列表时每个子播放列表的结果
+    pub selected_variant: Option<String>,    // 逐个测试子播放列表时，实测能承载的最高码率子播放列表
+    pub renditions: Option<Vec<RenditionResult>>, // HLS 所选子播放列表引用的单独音轨、字幕的测试结果
+    pub verdict: Option<Verdict>,            // 由 Policy 判定，测试本身不设置
+}
+
+// HLS / DASH 流测试的测量结果
+#[derive(Default)]
+struct HlsMeasurement {
+    delay_ms: f64,
+    timing: FirstByteTiming,
+    connection: ConnectionTiming, // 第一个媒体请求之前对其主机的探测连接
+    speed_kbps: f64,
+    bytes: u64,
+    segments_total: usize,
+    segments_ok: usize,
+    advertised_bandwidth: Option<u64>,
+    details: String,
+    key: Option<KeyCheck>,
+    init_segment: Option<InitSegment>,
+    content_valid: Option<bool>,
+    content_error: Option<String>,
+    media: Option<MediaInfo>,
+    media_bitrate: Option<MediaBitrate>, // 由下载的片段估算，没有时用声明的码率
+    live: Option<LiveStats>,
+    low_latency: Option<LowLatencyStats>,
+    player: Option<PlayerStats>,
+    renditions: Option<Vec<RenditionResult>>,
+    variants: Option<Vec<VariantResult>>,
+    selected_variant: Option<String>,
+    // 所选子播放列表及其所在的主播放列表，用于测试备选媒体
+    rendition_source: Option<(MasterPlaylist, VariantStream)>,
+}
+
+// 一轮并发片段下载的统计
+#[derive(Default)]
+struct BurstStats {
+    bytes: u64,
+    segments_ok: usize,
+    elapsed_secs: f64,
+    content: ContentSummary,
+    media: Option<MediaInfo>, // 第一个识别出编码信息的片段
+    bitrate: BitrateSamples,
+    timing: FirstByteTiming,
+}
+
+// 多个片段的内容检查结果
+#[derive(Default)]
+struct ContentSummary {
+    checked: bool,         // 至少有一个片段按 MPEG-TS 检查过
+    error: Option<String>, // 第一个内容无效的片段的原因
+}
+
+impl ContentSummary {
+    fn add(&mut self, download: &SegmentDownload) {
+        self.checked |= download.ts_checked;
+        if self.error.is_none() {
+            self.error.clone_from(&download.content_error);
+        }
+    }
+
+    // 片段内容是否为有效的 MPEG-TS，没有片段按 TS 检查过（fMP4、AAC 等格式或加密）时为空
+    fn valid(&self) -> Option<bool> {
+        self.checked.then_some(self.error.is_none())
+    }
+}
+
+// 一个片段的下载结果
+struct SegmentDownload {
+    bytes: u64,
+    media: Option<MediaInfo>, // 检查了 TS 内容时从中识别的编码信息
+    ts_checked: bool,         // 片段内容按 MPEG-TS 检查过，其他格式不检查
+    content_error: Option<String>, // 内容不是有效的 MPEG-TS 的原因
+    complete: bool,           // 完整下载，没有被读取时长截断
+    pcr_span: Option<(u64, f64)>, // 检查了 TS 内容时 PCR 覆盖的 (字节数, 秒数)
+    ttfb_ms: f64,                 // 发出请求到收到响应头
+    first_byte: Option<Instant>,  // 收到第一个数据字节的时间
+}
+
+// 从流式响应中截取片段的字节范围：206 响应从头开始取 length 字节，
+// 服务器忽略 Range 返回 200 和整个文件时先跳过 offset 之前的数据
+struct RangeWindow {
+    skip: u64,
+    remaining: u64,
+}
+
+impl RangeWindow {
+    fn new(byte_range: Option<ByteRange>, status: reqwest::StatusCode) -> Self {
+        match byte_range {
+            Some(range) if status == reqwest::StatusCode::PARTIAL_CONTENT => Self { skip: 0, remaining: range.length },
+            Some(range) => Self { skip: range.start(), remaining: range.length },
+            None => Self { skip: 0, remaining: u64::MAX },
+        }
+    }
+
+    // 返回数据块中落在字节范围内的部分
+    fn take<'a>(&mut self, chunk: &'a [u8]) -> &'a [u8] {
+        let skipped = self.skip.min(chunk.len() as u64);
+        self.skip -= skipped;
+        let rest = &chunk[skipped as usize..];
+        let taken = self.remaining.min(rest.len() as u64);
+        self.remaining -= taken;
+        &rest[..taken as usize]
+    }
+
+    fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+// 直连下载的测量结果
+struct DirectMeasurement {
+    connection: ConnectionTiming, // 下载请求之前对其主机的探测连接
+    ttfb_ms: f64,
+    first_byte: Option<Instant>, // 收到第一个数据字节的时间
+    speed_kbps: f64,
+    bytes: u64,
+    analyzer: TsAnalyzer,
+}
+
+// 码流码率的估算来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitrateSource {
+    Segments,   // 完整下载的片段大小 / 片段时长（EXTINF 或 DASH 片段时长）
+    Pcr,        // TS 中相邻 PCR 之间的字节数和时间差
+    Advertised, // 声明的 BANDWIDTH（HLS）或 bandwidth（DASH）
+}
+
+// 估算的码流码率，kbps 与 speed_kbps 一样以 1024 bit 为单位
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MediaBitrate {
+    pub kbps: f64,
+    pub source: BitrateSource,
+}
+
+impl MediaBitrate {
+    fn new(bits_per_sec: f64, source: BitrateSource) -> Option<Self> {
+        (bits_per_sec.is_finite() && bits_per_sec > 0.0).then_some(Self { kbps: bits_per_sec / 1024.0, source })
+    }
+}
+
+// PCR 覆盖的时长太短时码率受复用突发影响，不可靠
+const MIN_PCR_SECS: f64 = 1.0;
+
+// 估算码流码率用的样本
+#[derive(Default)]
+struct BitrateSamples {
+    bytes: u64,     // 完整下载的片段的字节数和时长
+    secs: f64,
+    pcr_bytes: u64, // 各片段 PCR 覆盖的字节数和时长
+    pcr_secs: f64,
+}
+
+impl BitrateSamples {
+    fn add(&mut self, duration: f64, download: &SegmentDownload) {
+        if download.complete && duration > 0.0 {
+            self.bytes += download.bytes;
+            self.secs += duration;
+        }
+        self.add_pcr(download.pcr_span);
+    }
+
+    fn add_pcr(&mut self, span: Option<(u64, f64)>) {
+        if let Some((bytes, secs)) = span {
+            self.pcr_bytes += bytes;
+            self.pcr_secs += secs;
+        }
+    }
+
+    // 优先用完整片段的大小和时长，其次用 PCR
+    fn estimate(&self) -> Option<MediaBitrate> {
+        let segments = (self.secs > 0.0)
+            .then(|| MediaBitrate::new(self.bytes as f64 * 8.0 / self.secs, BitrateSource::Segments))
+            .flatten();
+        segments.or_else(|| {
+            (self.pcr_secs >= MIN_PCR_SECS)
+                .then(|| MediaBitrate::new(self.pcr_bytes as f64 * 8.0 / self.pcr_secs, BitrateSource::Pcr))
+                .flatten()
+        })
+    }
+}
+
+// 媒体片段请求的首字节时间：取下载成功的片段中最短的首字节时间和最早收到数据的时间
+#[derive(Debug, Clone, Copy, Default)]
+struct FirstByteTiming {
+    ttfb_ms: Option<f64>,
+    first_byte: Option<Instant>,
+}
+
+impl FirstByteTiming {
+    fn add(&mut self, download: &SegmentDownload) {
+        self.ttfb_ms = Some(self.ttfb_ms.map_or(download.ttfb_ms, |ttfb| ttfb.min(download.ttfb_ms)));
+        self.first_byte = self.first_byte.into_iter().chain(download.first_byte).min();
+    }
+
+    // 作为 HLS / DASH 的延迟，没有下载成功的片段时为 -1
+    fn delay_ms(&self) -> f64 {
+        self.ttfb_ms.map_or(-1.0, f64::floor)
+    }
+
+    // 从测试开始到收到第一个媒体字节的毫秒数
+    fn first_media_byte_ms(&self, test_start: Instant) -> Option<f64> {
+        self.first_byte
+            .map(|instant| instant.saturating_duration_since(test_start).as_secs_f64() * 1000.0)
+    }
+}
+
+impl BurstStats {
+    fn speed_kbps(&self) -> f64 {
+        if self.elapsed_secs > 0.0 {
+            (self.bytes as f64 * 8.0) / self.elapsed_secs / 1024.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl SpeedTestResult {
+    // 构造一个失败的测试结果
+    pub fn failure(url: &str, protocol_type: &str, error_kind: ErrorKind, details: String) -> Self {
+        Self {
+            url: url.to_string(),
+            success: false,
+            delay_ms: -1.0,
+            speed_kbps: 0.0,
+            size_mb: 0.0,
+            duration_secs: 0.0,
+            protocol_type: protocol_type.to_string(),
+            details: Some(details),
+            error_kind: Some(error_kind),
+            ..Default::default()
+        }
+    }
+
+    // 构造一个 DRM 加密的测试结果，这类流不测速，由判定单独归类
+    pub fn drm_protected(url: &str, system: DrmSystem, details: String) -> Self {
+        Self {
+            drm: Some(system),
+            ..Self::failure(url, "DRM-protected", ErrorKind::Drm, details)
+        }
+    }
+
+    // 记录码流码率（下载的内容估算不出时用声明的码率）和余量
+    fn with_bitrate(mut self, measured: Option<MediaBitrate>) -> Self {
+        self.media_bitrate = measured.or_else(|| {
+            self.advertised_bandwidth
+                .and_then(|bandwidth| MediaBitrate::new(bandwidth as f64, BitrateSource::Advertised))
+        });
+        self.headroom = self.media_bitrate.map(|bitrate| self.speed_kbps / bitrate.kbps);
+        self
+    }
+
+    // 是否应保留该频道：有判定时以判定为准，否则以测试是否成功为准
+    pub fn passed(&self) -> bool {
+        match &self.verdict {
+            Some(verdict) => verdict.status == VerdictStatus::Pass,
+            None => self.success,
+        }
+    }
+}
+
+// 配置文件中的 [test] 部分，测速用到的超时和预算（时间单位为秒）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TestConfig {
+    // HTTP客户端整体超时，兜底用（HLS片段下载也使用这个超时）
+    pub client_timeout_secs: f64,
+    pub connect_timeout_secs: f64,
+    // 直连下载的整体请求超时（包含连接+读取）
+    pub request_timeout_secs: f64,
+    // 连接成功后的流式读取时长，直连和HLS片段共用
+    pub read_window_secs: f64,
+    // 单个URL测试的兜底超时，直连和M3U8测试共用
+    pub test_timeout_secs: f64,
+    // HLS 测试下载的片段数
+    pub hls_segments: usize,
+    pub hls_mode: HlsMode,
+    // 直播模式的观察时长，会加到单个URL测试的兜底超时上
+    pub live_window_secs: f64,
+    // 逐个测试主播放列表中的子播放列表（每个子播放列表单独计算兜底超时），可按分辨率和码率筛选
+    pub probe_variants: bool,
+    pub variant_max_height: Option<u64>,
+    pub variant_min_bandwidth: Option<u64>,
+    pub variant_max_bandwidth: Option<u64>,
+    // AES-128 加密的流除了检查密钥，还试解密第一个加密片段
+    pub decrypt_check: bool,
+    // 直连下载时统计 TS 连续计数器、PCR 等健康指标（udpxy 转发的组播流丢包时会卡顿）
+    pub ts_health: bool,
+}
+
+// HLS 测试方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum HlsMode {
+    #[default]
+    Burst, // 并发下载播放列表中的前几个片段，测量突发带宽
+    Live,  // 按目标时长刷新直播播放列表，下载新发布的片段，检查发布和下载是否跟得上实时（点播流按 burst 测试）
+    Player, // 像播放器一样按顺序逐个下载片段，模拟播放缓冲区统计起播时间和卡顿
+    // 低延迟 HLS：用 _HLS_msn / _HLS_part 阻塞刷新跟随部分片段，统计部分片段发布延迟（普通直播流按 live 测试）
+    #[value(name = "low_latency")]
+    LowLatency,
+}
+
+impl Default for TestConfig {
+    fn default() -> Self {
+        Self {
+            client_timeout_secs: 10.0,
+            connect_timeout_secs: 3.0,
+            request_timeout_secs: 6.0,
+            read_window_secs: 3.0,
+            test_timeout_secs: 10.0,
+            hls_segments: 5,
+            hls_mode: HlsMode::Burst,
+            live_window_secs: 30.0,
+            probe_variants: false,
+            variant_max_height: None,
+            variant_min_bandwidth: None,
+            variant_max_bandwidth: None,
+            decrypt_check: false,
+            ts_health: false,
+        }
+    }
+}
+
+impl TestConfig {
+    // 检查配置是否可用，避免 Duration 转换时 panic
+    pub fn validate(&self) -> Result<()> {
+        let durations = [
+            ("client_timeout_secs", self.client_timeout_secs),
+            ("connect_timeout_secs", self.connect_timeout_secs),
+            ("request_timeout_secs", self.request_timeout_secs),
+            ("read_window_secs", self.read_window_secs),
+            ("test_timeout_secs", self.test_timeout_secs),
+            ("live_window_secs", self.live_window_secs),
+        ];
+        for (name, value) in durations {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(anyhow!("测速配置 {} 必须为正数，当前为 {}", name, value));
+            }
+            // 过大的值转换为 Duration 时会溢出
+            if value > MAX_DURATION_SECS {
+                return Err(anyhow!("测速配置 {} 不能超过 {} 秒，当前为 {}", name, MAX_DURATION_SECS, value));
+            }
+        }
+        if self.hls_segments == 0 {
+            return Err(anyhow!("测速配置 hls_segments 必须大于 0"));
+        }
+        Ok(())
+    }
+}
+
+// 测速配置中时间的上限（一天）
+const MAX_DURATION_SECS: f64 = 86400.0;
+
+fn secs(value: f64) -> Duration {
+    Duration::from_secs_f64(value)
+}
+
+// 是否检查片段内容：有 EXT-X-MAP 的片段（fMP4，或 PAT/PMT 放在初始化片段中）和加密的片段不检查
+fn checks_payload(segment: &MediaSegment) -> bool {
+    segment.map.is_none() && segment.key.as_ref().is_none_or(|key| key.method == KeyMethod::None)
+}
+
+// 根据播放列表语义描述HLS流：直播/点播、目标时长、加密、fMP4 以及所选子播放列表的属性
+fn describe_hls(parsed: &ParsedM3u8) -> String {
+    let media = &parsed.media;
+    let mut parts = vec![
+        if media.is_live() { "直播".to_string() } else { format!("点播 {:.0} 秒", media.total_duration()) },
+        format!("目标时长 {} 秒", media.target_duration),
+    ];
+
+    if let Some(key) = media.segments.iter().find_map(|segment| segment.key.as_ref()) {
+        let method = match &key.method {
+            KeyMethod::Aes128 => "AES-128",
+            KeyMethod::SampleAes => "SAMPLE-AES",
+            KeyMethod::Other(method) => method.as_str(),
+            KeyMethod::None => "NONE",
+        };
+        parts.push(format!("加密 {}", method));
+    }
+    if media.segments.iter().any(|segment| segment.map.is_some()) {
+        parts.push("fMP4".to_string());
+    }
+    if let Some(part_target) = media.part_target {
+        let mut low_latency = format!("低延迟 PART-TARGET {} 秒", part_target);
+        if let Some(control) = &media.server_control {
+            if let Some(part_hold_back) = control.part_hold_back.or(control.hold_back) {
+                low_latency += &format!(", 播放延迟 {} 秒", part_hold_back);
+            }
+            if control.can_block_reload {
+                low_latency += ", 支持阻塞刷新";
+            }
+        }
+        parts.push(low_latency);
+    }
+    if let Some(variant) = &parsed.variant {
+        parts.push(variant.describe());
+    }
+
+    parts.join(", ")
 }
 
 pub struct SpeedTester {
     client: Client,
     verbose: bool,
+    config: TestConfig,
     m3u8_parser: M3u8Parser,
+    mpd_parser: MpdParser,
+}
+
+// 按测速配置创建HTTP客户端，SpeedTester 和 fetch 子命令的订阅下载都用它，
+// 所以 client_timeout_secs 同样限制单个订阅源的下载时间
+pub fn build_client(config: &TestConfig) -> Client {
+    Client::builder()
+        .timeout(secs(config.client_timeout_secs))  // 整体超时，兜底用
+        .connect_timeout(secs(config.connect_timeout_secs))  // 连接建立超时
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("Failed to create HTTP client")
 }
 
 impl SpeedTester {
-    pub fn new(verbose: bool) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))  // 默认整体超时10秒，兜底用
-            .connect_timeout(Duration::from_secs(3))  // 默认连接建立超时3秒
-            .danger_accept_invalid_certs(true)
-            .build()
-            .expect("Failed to create HTTP client");
+    pub fn new(verbose: bool, config: TestConfig) -> Self {
+        let client = build_client(&config);
 
         Self {
             client,
             verbose,
+            config,
             m3u8_parser: M3u8Parser::new(verbose),
+            mpd_parser: MpdParser::new(verbose),
         }
     }
 
@@ -62,32 +603,35 @@ impl SpeedTester {
                 }
                 self.test_m3u8_url(url).await
             }
+            HeadCheckResult::Dash => {
+                if self.verbose {
+                    println!("识别为MPEG-DASH格式");
+                }
+                self.test_dash_url(url).await
+            }
             HeadCheckResult::DirectUrl => {
                 if self.verbose {
                     println!("HEAD请求成功，识别为直接URL");
                 }
-                self.test_direct_url(url).await
+                self.test_direct_url(url, true).await
             }
             HeadCheckResult::FailedM3U8Suffix => {
                 if self.verbose {
                     println!("HEAD请求失败，但URL以.m3u8结尾，跳过测试");
                 }
-                Ok(SpeedTestResult {
-                    url: url.to_string(),
-                    success: false,
-                    delay_ms: -1.0,
-                    speed_kbps: 0.0,
-                    size_mb: 0.0,
-                    duration_secs: 0.0,
-                    protocol_type: "HEAD失败".to_string(),
-                    details: Some("HEAD请求失败且URL以.m3u8结尾，跳过测试".to_string()),
-                })
+                Ok(SpeedTestResult::failure(
+                    url,
+                    "HEAD失败",
+                    ErrorKind::HeadFailed,
+                    "HEAD请求失败且URL以.m3u8结尾，跳过测试".to_string(),
+                ))
             }
-            HeadCheckResult::FailedNonM3U8 => {
+            HeadCheckResult::FailedNonM3U8(unreachable) => {
                 if self.verbose {
                     println!("HEAD请求失败，URL不以.m3u8结尾，继续GET测试");
                 }
-                self.test_direct_url(url).await
+                // HEAD 已经在 DNS 解析或建立连接时失败，不再探测连接耗时
+                self.test_direct_url(url, !unreachable).await
             }
         }
     }
@@ -105,13 +649,16 @@ impl SpeedTester {
                 if self.verbose {
                     println!("HEAD请求失败: {}", e);
                 }
+                let unreachable = e.is_connect() || e.is_timeout();
 
                 // HEAD请求失败，检查URL后缀
                 let basename = self.get_url_basename(url);
-                if basename.to_lowercase().ends_with(".m3u8") {
+                if basename.to_lowercase().ends_with(".mpd") {
+                    return Ok(HeadCheckResult::Dash);
+                } else if basename.to_lowercase().ends_with(".m3u8") {
                     return Ok(HeadCheckResult::FailedM3U8Suffix);
                 } else {
-                    return Ok(HeadCheckResult::FailedNonM3U8);
+                    return Ok(HeadCheckResult::FailedNonM3U8(unreachable));
                 }
             }
         };
@@ -124,10 +671,12 @@ impl SpeedTester {
 
             // HEAD请求失败，检查URL后缀
             let basename = self.get_url_basename(url);
-            if basename.to_lowercase().ends_with(".m3u8") {
+            if basename.to_lowercase().ends_with(".mpd") {
+                return Ok(HeadCheckResult::Dash);
+            } else if basename.to_lowercase().ends_with(".m3u8") {
                 return Ok(HeadCheckResult::FailedM3U8Suffix);
             } else {
-                return Ok(HeadCheckResult::FailedNonM3U8);
+                return Ok(HeadCheckResult::FailedNonM3U8(false));
             }
         }
 
@@ -142,15 +691,19 @@ impl SpeedTester {
         let basename = self.get_url_basename(url);
         let is_m3u8_by_content_type = content_type.contains("mpegurl") || content_type.contains("m3u8");
         let is_m3u8_by_suffix = basename.to_lowercase().ends_with(".m3u8");
+        let is_dash = content_type.contains("dash+xml") || basename.to_lowercase().ends_with(".mpd");
 
         if self.verbose {
             println!("Content-Type: {}", content_type);
             println!("URL basename: {}", basename);
             println!("M3U8 by Content-Type: {}", is_m3u8_by_content_type);
             println!("M3U8 by suffix: {}", is_m3u8_by_suffix);
+            println!("DASH: {}", is_dash);
         }
 
-        if is_m3u8_by_content_type || is_m3u8_by_suffix {
+        if is_dash {
+            Ok(HeadCheckResult::Dash)
+        } else if is_m3u8_by_content_type || is_m3u8_by_suffix {
             Ok(HeadCheckResult::M3U8)
         } else {
             Ok(HeadCheckResult::DirectUrl)
@@ -172,7 +725,7 @@ impl SpeedTester {
     }
 
     
-    async fn test_direct_url(&self, url: &str) -> Result<SpeedTestResult> {
+    async fn test_direct_url(&self, url: &str, probe_connection: bool) -> Result<SpeedTestResult> {
         let start_time = Instant::now();
 
         if self.verbose {
@@ -180,66 +733,91 @@ impl SpeedTester {
         }
 
         // 这里的 timeout 是兜底用的，download_and_measure 内部会有更细粒度的超时控制
-        let result = timeout(Duration::from_secs(10), self.download_and_measure(url)).await;
+        let result = timeout(secs(self.config.test_timeout_secs), self.download_and_measure(url, probe_connection)).await;
 
         let duration = start_time.elapsed();
         let duration_secs = duration.as_secs_f64();
 
         match result {
-            Ok(Ok((delay_ms, speed_kbps, size_mb))) => {
+            Ok(Ok(DirectMeasurement { connection, ttfb_ms, first_byte, speed_kbps, bytes, analyzer })) => {
+                // 内容无效不影响测试是否成功，由判定规则根据 content_valid 决定
+                let content = analyzer.check();
+                let valid = content == ContentCheck::Valid;
+                let media = analyzer.media_info().filter(|_| valid);
+                let mut details = match (&content, &media) {
+                    (ContentCheck::Invalid(reason), _) => format!("直接下载测速完成, 内容无效: {}", reason),
+                    (_, Some(media)) => format!("直接下载测速完成, 码流: {}", media.label()),
+                    _ => "直接下载测速完成".to_string(),
+                };
+                let ts_health = analyzer.health().filter(|_| valid);
+                if let Some(health) = &ts_health {
+                    details += &format!(
+                        ", TS 错误: 连续计数 {}, 第一优先级共 {}, PCR 不连续 {}",
+                        health.continuity_errors,
+                        health.priority1_errors(),
+                        health.pcr_discontinuities
+                    );
+                }
+                let (content_valid, content_error) = match content {
+                    ContentCheck::Valid => (Some(true), None),
+                    ContentCheck::Invalid(reason) => (Some(false), Some(reason)),
+                    ContentCheck::OtherFormat(_) => (None, None),
+                };
+                let mut bitrate = BitrateSamples::default();
+                bitrate.add_pcr(analyzer.pcr_span());
                 Ok(SpeedTestResult {
                     url: url.to_string(),
                     success: true,
-                    delay_ms,
+                    delay_ms: ttfb_ms.floor(),
                     speed_kbps,
-                    size_mb,
+                    size_mb: bytes as f64 / (1024.0 * 1024.0),
                     duration_secs,
                     protocol_type: "HTTP直连".to_string(),
-                    details: Some("直接下载测速完成".to_string()),
-                })
+                    details: Some(details),
+                    bytes_downloaded: bytes,
+                    ttfb_ms: Some(ttfb_ms),
+                    // 与 HLS / DASH 一样从测试开始计算
+                    first_media_byte_ms: FirstByteTiming { ttfb_ms: Some(ttfb_ms), first_byte }.first_media_byte_ms(start_time),
+                    content_valid,
+                    content_error,
+                    media,
+                    ts_health,
+                    ..Default::default()
+                }
+                .with_bitrate(bitrate.estimate())
+                .with_connection(connection))
             }
             Ok(Err(e)) => {
                 Ok(SpeedTestResult {
-                    url: url.to_string(),
-                    success: false,
-                    delay_ms: -1.0,
-                    speed_kbps: 0.0,
-                    size_mb: 0.0,
                     duration_secs,
-                    protocol_type: "HTTP直连".to_string(),
-                    details: Some(format!("下载失败: {}", e)),
+                    ..SpeedTestResult::failure(url, "HTTP直连", classify_error(&e), format!("下载失败: {}", e))
                 })
             }
             Err(_) => {
                 Ok(SpeedTestResult {
-                    url: url.to_string(),
-                    success: false,
-                    delay_ms: -1.0,
-                    speed_kbps: 0.0,
-                    size_mb: 0.0,
                     duration_secs,
-                    protocol_type: "HTTP直连".to_string(),
-                    details: Some("请求超时".to_string()),
+                    ..SpeedTestResult::failure(url, "HTTP直连", ErrorKind::Timeout, "请求超时".to_string())
                 })
             }
         }
     }
 
-    async fn download_and_measure(&self, url: &str) -> Result<(f64, f64, f64)> {
+    async fn download_and_measure(&self, url: &str, probe_connection: bool) -> Result<DirectMeasurement> {
+        let connection = if probe_connection { self.media_connection(url).await } else { ConnectionTiming::default() };
         let start_time = Instant::now();
 
         let response = self.client
             .get(url)
-            .timeout(Duration::from_secs(6))  // 整体请求超时6秒（包含连接+读取）
+            .timeout(secs(self.config.request_timeout_secs))  // 整体请求超时（包含连接+读取）
             .send()
             .await?;
 
         if !response.status().is_success() {
-            return Err(anyhow!("HTTP错误: {}", response.status()));
+            return Err(TestError::HttpStatus(response.status()).into());
         }
 
-        // 计算连接延迟（到收到响应头的时间）
-        let delay_ms = start_time.elapsed().as_millis() as f64;
+        // 首字节时间（到收到响应头的时间，连接可能复用 HEAD 请求的连接）
+        let ttfb_ms = start_time.elapsed().as_secs_f64() * 1000.0;
 
         let content_length = response
             .headers()
@@ -247,21 +825,27 @@ impl SpeedTester {
             .and_then(|value| value.to_str().ok())
             .and_then(|value| value.parse::<u64>().ok());
 
+        let mut analyzer = if self.config.ts_health { TsAnalyzer::with_health() } else { TsAnalyzer::new() };
         let mut downloaded_bytes = 0u64;
+        let mut first_byte = None;
         let mut stream = response.bytes_stream();
 
-        // 设置3秒时间限制，专门用于流式下载（从连接成功后开始计算）
+        // 设置读取时间限制，专门用于流式下载（从连接成功后开始计算）
         let read_start = Instant::now();
-        let stream_timeout = Duration::from_secs(3);
+        let stream_timeout = secs(self.config.read_window_secs);
 
         while let Some(chunk) = stream.next().await {
             let chunk = chunk?;
+            if first_byte.is_none() && !chunk.is_empty() {
+                first_byte = Some(Instant::now());
+            }
             downloaded_bytes += chunk.len() as u64;
+            analyzer.feed(&chunk);
 
-            // 检查是否达到3秒时间限制（从连接成功后开始计算）
+            // 检查是否达到读取时间限制（从连接成功后开始计算）
             if read_start.elapsed() > stream_timeout {
                 if self.verbose {
-                    println!("连接成功后达到3秒读取时间限制，停止下载");
+                    println!("连接成功后达到{}秒读取时间限制，停止下载", self.config.read_window_secs);
                 }
                 break;
             }
@@ -283,7 +867,18 @@ impl SpeedTester {
             }
         }
 
-        Ok((delay_ms, speed_kbps, size_mb))
+        if self.verbose {
+            println!("内容检查: {:?}", analyzer.check());
+        }
+
+        Ok(DirectMeasurement {
+            connection,
+            ttfb_ms,
+            first_byte,
+            speed_kbps,
+            bytes: downloaded_bytes,
+            analyzer,
+        })
     }
 
     async fn test_m3u8_url(&self, url: &str) -> Result<SpeedTestResult> {
@@ -294,84 +889,243 @@ impl SpeedTester {
         }
 
         // 这里的 timeout 也是兜底用的，最终会调用 download_and_measure 内部会有更细粒度的超时控制
-        let result = timeout(Duration::from_secs(10), self.test_hls_stream(url)).await;
+        // 直播模式需要额外的观察时间
+        // 播放模拟在 test_timeout_secs 后不再发起新的下载，再给正在进行的下载留一个客户端超时
+        let budget_secs = match self.config.hls_mode {
+            HlsMode::Burst => self.config.test_timeout_secs,
+            HlsMode::Live | HlsMode::LowLatency => self.config.test_timeout_secs + self.config.live_window_secs,
+            HlsMode::Player => self.config.test_timeout_secs + self.config.client_timeout_secs,
+        };
+        let result = if self.config.probe_variants {
+            self.test_hls_variants(url, secs(budget_secs)).await
+        } else {
+            timeout(secs(budget_secs), self.test_hls_stream(url)).await
+        };
+
+        // 备选媒体单独计算超时，不占用主流的测试时间
+        let result = match result {
+            Ok(Ok(mut measurement)) => {
+                if let Some((master, variant)) = measurement.rendition_source.take() {
+                    let renditions = self.test_renditions(&master, &variant).await;
+                    if !renditions.is_empty() {
+                        let reachable = renditions.iter().filter(|rendition| rendition.reachable).count();
+                        measurement.details += &format!(", 备选媒体可用: {}/{}", reachable, renditions.len());
+                        measurement.renditions = Some(renditions);
+                    }
+                }
+                Ok(Ok(measurement))
+            }
+            other => other,
+        };
 
         let duration = start_time.elapsed();
         let duration_secs = duration.as_secs_f64();
 
         match result {
-            Ok(Ok((delay_ms, speed_kbps, size_mb, details))) => {
+            Ok(Ok(measurement)) => {
                 Ok(SpeedTestResult {
                     url: url.to_string(),
                     success: true,
-                    delay_ms,
-                    speed_kbps,
-                    size_mb,
+                    delay_ms: measurement.delay_ms,
+                    speed_kbps: measurement.speed_kbps,
+                    size_mb: measurement.bytes as f64 / (1024.0 * 1024.0),
                     duration_secs,
-                    protocol_type: "HLS/M3U8".to_string(),
-                    details: Some(details),
-                })
+                    // fMP4 / CMAF 流按初始化片段的格式标注
+                    protocol_type: measurement
+                        .init_segment
+                        .as_ref()
+                        .map_or("HLS/M3U8", |init| init.container.protocol_type())
+                        .to_string(),
+                    details: Some(measurement.details),
+                    bytes_downloaded: measurement.bytes,
+                    ttfb_ms: measurement.timing.ttfb_ms,
+                    first_media_byte_ms: measurement.timing.first_media_byte_ms(start_time),
+                    segments_total: Some(measurement.segments_total),
+                    segments_ok: Some(measurement.segments_ok),
+                    advertised_bandwidth: measurement.advertised_bandwidth,
+                    key: measurement.key,
+                    init_segment: measurement.init_segment,
+                    content_valid: measurement.content_valid,
+                    content_error: measurement.content_error,
+                    media: measurement.media,
+                    live: measurement.live,
+                    low_latency: measurement.low_latency,
+                    player: measurement.player,
+                    variants: measurement.variants,
+                    selected_variant: measurement.selected_variant,
+                    renditions: measurement.renditions,
+                    ..Default::default()
+                }
+                .with_bitrate(measurement.media_bitrate)
+                .with_connection(measurement.connection))
             }
             Ok(Err(e)) => {
+                if let Some(TestError::Drm(system)) = e.downcast_ref::<TestError>() {
+                    return Ok(SpeedTestResult {
+                        duration_secs,
+                        ..SpeedTestResult::drm_protected(url, *system, format!("HLS流使用 {} DRM 加密", system))
+                    });
+                }
                 Ok(SpeedTestResult {
-                    url: url.to_string(),
-                    success: false,
-                    delay_ms: -1.0,
-                    speed_kbps: 0.0,
-                    size_mb: 0.0,
                     duration_secs,
-                    protocol_type: "HLS/M3U8".to_string(),
-                    details: Some(format!("HLS测试失败: {}", e)),
+                    ..SpeedTestResult::failure(url, "HLS/M3U8", classify_error(&e), format!("HLS测试失败: {}", e))
                 })
             }
             Err(_) => {
                 Ok(SpeedTestResult {
-                    url: url.to_string(),
-                    success: false,
-                    delay_ms: -1.0,
-                    speed_kbps: 0.0,
-                    size_mb: 0.0,
                     duration_secs,
-                    protocol_type: "HLS/M3U8".to_string(),
-                    details: Some("HLS测试超时".to_string()),
+                    ..SpeedTestResult::failure(url, "HLS/M3U8", ErrorKind::Timeout, "HLS测试超时".to_string())
                 })
             }
         }
     }
 
-    async fn test_hls_stream(&self, url: &str) -> Result<(f64, f64, f64, String)> {
+    async fn test_hls_stream(&self, url: &str) -> Result<HlsMeasurement> {
+        let test_start = Instant::now();
+
         // 解析M3U8文件
-        let segments = self.m3u8_parser.parse_m3u8(url, &self.client).await?;
+        let parsed = self.m3u8_parser.parse_m3u8(url, &self.client).await?;
+        let rendition_source = parsed.master.clone().zip(parsed.variant.clone());
+
+        let mut measurement = self.measure_playlist(parsed, test_start).await?;
+        measurement.rendition_source = rendition_source;
+        Ok(measurement)
+    }
+
+    // 排除 DRM 加密的流并检查 AES-128 密钥和初始化片段后，按 hls_mode 测试解析好的媒体播放列表，密钥不可用时整个流判定为失败
+    async fn measure_playlist(&self, parsed: ParsedM3u8, test_start: Instant) -> Result<HlsMeasurement> {
+        if let Some(system) = parsed.drm_system() {
+            return Err(TestError::Drm(system).into());
+        }
+        // 在初始化片段和媒体片段的请求之前探测片段所在的主机，片段可能来自其他 CDN 主机
+        let first_media = parsed
+            .media
+            .segments
+            .first()
+            .map(|segment| &segment.uri)
+            .or(parsed.media.trailing_parts.first().map(|part| &part.uri));
+        let connection = match first_media {
+            Some(uri) => self.media_connection(uri).await,
+            None => ConnectionTiming::default(),
+        };
+        let key = self.check_key(&parsed.media).await?;
+        let init_segment = match parsed.media.segments.first().and_then(|segment| segment.map.as_ref()) {
+            Some(map) => Some(self.check_init_segment(map).await?),
+            None => None,
+        };
+
+        let mut measurement = self.measure_media(parsed, test_start).await?;
+        if let Some(media) = &measurement.media {
+            measurement.details += &format!(", 码流: {}", media.label());
+        }
+        if let Some(reason) = &measurement.content_error {
+            measurement.details += &format!(", 内容无效: {}", reason);
+        }
+        if let Some(key) = key {
+            measurement.details += if key.decrypted { ", 密钥有效, 首个片段解密成功" } else { ", 密钥有效" };
+            measurement.key = Some(key);
+        }
+        measurement.init_segment = init_segment;
+        measurement.connection = connection;
+        Ok(measurement)
+    }
+
+    async fn measure_media(&self, parsed: ParsedM3u8, test_start: Instant) -> Result<HlsMeasurement> {
+        let stream_info = describe_hls(&parsed);
+
+        if self.verbose {
+            println!("媒体播放列表: {} ({})", parsed.url, stream_info);
+        }
+
+        match self.config.hls_mode {
+            HlsMode::Live if parsed.media.is_live() => return self.measure_live(parsed, stream_info).await,
+            HlsMode::LowLatency if parsed.media.is_live() && parsed.media.is_low_latency() => {
+                return self.measure_low_latency(parsed, stream_info).await;
+            }
+            HlsMode::LowLatency if parsed.media.is_live() => {
+                if self.verbose {
+                    println!("不是低延迟 HLS，改用 live 模式测试");
+                }
+                return self.measure_live(parsed, stream_info).await;
+            }
+            HlsMode::Live | HlsMode::LowLatency => {
+                if self.verbose {
+                    println!("点播播放列表，改用 burst 模式测试");
+                }
+            }
+            HlsMode::Player => return self.measure_player(parsed, stream_info, test_start).await,
+            HlsMode::Burst => {}
+        }
+
+        let segments = parsed.media.segments;
 
         if self.verbose {
             println!("发现 {} 个媒体片段", segments.len());
         }
 
         if segments.is_empty() {
-            return Err(anyhow!("未找到有效的媒体片段"));
+            return Err(TestError::NoSegments.into());
+        }
+
+        // 限制测试的片段数量
+        let test_segments: Vec<MediaSegment> = segments.into_iter().take(self.config.hls_segments).collect();
+        if self.verbose {
+            for segment in &test_segments {
+                println!("  {} ({})", segment.uri, segment.describe());
+            }
         }
 
-        // 限制测试的片段数量（最多5个）
-        let test_segments: Vec<String> = segments.into_iter().take(5).collect();
+        let burst = self.burst_download(&test_segments).await;
+        let speed_kbps = burst.speed_kbps();
 
+        let details = format!(
+            "HLS流测试 - {}, 总片段: {}, 成功: {}, 平均速度: {:.0} kbps",
+            stream_info,
+            test_segments.len(),
+            burst.segments_ok,
+            speed_kbps
+        );
+
+        Ok(HlsMeasurement {
+            delay_ms: burst.timing.delay_ms(),
+            timing: burst.timing,
+            speed_kbps,
+            bytes: burst.bytes,
+            segments_total: test_segments.len(),
+            segments_ok: burst.segments_ok,
+            advertised_bandwidth: parsed.variant.map(|variant| variant.bandwidth).filter(|bandwidth| *bandwidth > 0),
+            details,
+            content_valid: burst.content.valid(),
+            content_error: burst.content.error,
+            media: burst.media,
+            media_bitrate: burst.bitrate.estimate(),
+            ..Default::default()
+        })
+    }
+
+    // 并发下载片段（每个片段受读取时长限制）
+    async fn burst_download(&self, segments: &[MediaSegment]) -> BurstStats {
         let start_time = Instant::now();
 
-        // 并发下载测试片段
-        let tasks: Vec<_> = test_segments
+        let tasks: Vec<_> = segments
             .iter()
-            .map(|segment_url| self.download_segment_speed(segment_url))
+            .map(|segment| self.download_segment_speed(segment))
             .collect();
 
         let results = futures::future::join_all(tasks).await;
 
-        let mut total_size = 0u64;
-        let mut successful_downloads = 0usize;
-
-        for result in results {
+        let mut stats = BurstStats::default();
+        for (segment, result) in segments.iter().zip(results) {
             match result {
-                Ok(size) => {
-                    total_size += size;
-                    successful_downloads += 1;
+                Ok(download) => {
+                    stats.bytes += download.bytes;
+                    stats.segments_ok += 1;
+                    stats.bitrate.add(segment.duration, &download);
+                    stats.timing.add(&download);
+                    stats.content.add(&download);
+                    if stats.media.is_none() {
+                        stats.media = download.media;
+                    }
                 }
                 Err(e) => {
                     if self.verbose {
@@ -381,57 +1135,305 @@ impl SpeedTester {
             }
         }
 
-        let total_time = start_time.elapsed().as_secs_f64();
-        let delay_ms = start_time.elapsed().as_millis() as f64;
+        stats.elapsed_secs = start_time.elapsed().as_secs_f64();
+        stats
+    }
 
-        let size_mb = total_size as f64 / (1024.0 * 1024.0);
-        let speed_kbps = if total_time > 0.0 {
-            (total_size as f64 * 8.0) / total_time / 1024.0
-        } else {
-            0.0
-        };
+    // 片段请求，有字节范围时只请求该范围，响应用 RangeWindow 截取
+    fn segment_request(&self, url: &str, byte_range: Option<ByteRange>) -> reqwest::RequestBuilder {
+        let request = self.client.get(url);
+        match byte_range {
+            Some(range) => request.header(reqwest::header::RANGE, range.header_value()),
+            None => request,
+        }
+    }
 
-        let details = format!(
-            "HLS流测试 - 总片段: {}, 成功: {}, 平均速度: {:.0} kbps",
-            test_segments.len(),
-            successful_downloads,
-            speed_kbps
-        );
+    // 完整下载一个片段（不受读取时长限制），返回下载字节数
+    async fn download_segment(&self, segment: &MediaSegment) -> Result<SegmentDownload> {
+        self.download_resource(&segment.uri, segment.byte_range, checks_payload(segment), None).await
+    }
 
-        Ok((delay_ms, speed_kbps, size_mb, details))
+    // burst 模式下载一个片段，连接成功后最多读取 read_window_secs
+    async fn download_segment_speed(&self, segment: &MediaSegment) -> Result<SegmentDownload> {
+        let read_window = secs(self.config.read_window_secs);
+        self.download_resource(&segment.uri, segment.byte_range, checks_payload(segment), Some(read_window)).await
     }
 
-    async fn download_segment_speed(&self, url: &str) -> Result<u64> {
+    // 下载一个资源（片段或部分片段），check_content 时检查是否为有效的 MPEG-TS，内容无效不算下载失败
+    // read_window 为连接成功后的读取时长限制，None 时完整下载
+    // 服务器不支持 Range、返回整个文件时，跳过字节范围之前的数据，只统计和检查字节范围内的数据
+    async fn download_resource(
+        &self,
+        url: &str,
+        byte_range: Option<ByteRange>,
+        check_content: bool,
+        read_window: Option<Duration>,
+    ) -> Result<SegmentDownload> {
         // 使用默认的 client 超时设置
-        let response = self.client
-            .get(url)
-            .send()
-            .await?;
+        let request_start = Instant::now();
+        let response = self.segment_request(url, byte_range).send().await?;
+        let ttfb_ms = request_start.elapsed().as_secs_f64() * 1000.0;
 
         if !response.status().is_success() {
-            return Err(anyhow!("HTTP错误: {}", response.status()));
+            return Err(TestError::HttpStatus(response.status()).into());
         }
 
+        let mut window = RangeWindow::new(byte_range, response.status());
+        let mut analyzer = TsAnalyzer::new();
         let mut downloaded_bytes = 0u64;
+        let mut first_byte = None;
+        let mut complete = true;
         let mut stream = response.bytes_stream();
 
-        // 设置3秒时间限制，专门用于HLS片段流式下载（从连接成功后开始计算）
+        // 读取时长从连接成功后开始计算
         let read_start = Instant::now();
-        let stream_timeout = Duration::from_secs(3);
-
         while let Some(chunk) = stream.next().await {
             let chunk = chunk?;
-            downloaded_bytes += chunk.len() as u64;
+            let data = window.take(&chunk);
+            if first_byte.is_none() && !data.is_empty() {
+                first_byte = Some(Instant::now());
+            }
+            downloaded_bytes += data.len() as u64;
+            if check_content {
+                analyzer.feed(data);
+            }
+            if window.is_done() {
+                break;
+            }
 
-            // 检查是否达到3秒时间限制（从连接成功后开始计算）
-            if read_start.elapsed() > stream_timeout {
+            if read_window.is_some_and(|read_window| read_start.elapsed() > read_window) {
                 if self.verbose {
-                    println!("HLS片段连接成功后达到3秒读取时间限制，停止下载: {}", url);
+                    println!("HLS片段连接成功后达到{}秒读取时间限制，停止下载: {}", self.config.read_window_secs, url);
                 }
+                complete = false;
                 break;
             }
         }
 
-        Ok(downloaded_bytes)
+        let (ts_checked, media, content_error) =
+            if check_content { self.check_content(&analyzer, url) } else { (false, None, None) };
+        Ok(SegmentDownload {
+            bytes: downloaded_bytes,
+            media,
+            ts_checked,
+            content_error,
+            complete,
+            pcr_span: analyzer.pcr_span(),
+            ttfb_ms,
+            first_byte,
+        })
+    }
+
+    // 检查片段内容，返回 (是否按 MPEG-TS 检查, 识别出的编码信息, 内容无效的原因)，FLV、fMP4 等其他格式不检查
+    fn check_content(&self, analyzer: &TsAnalyzer, url: &str) -> (bool, Option<MediaInfo>, Option<String>) {
+        match analyzer.check() {
+            ContentCheck::Valid => (true, analyzer.media_info(), None),
+            ContentCheck::OtherFormat(format) => {
+                if self.verbose {
+                    println!("片段为 {} 格式，不检查 TS 结构: {}", format, url);
+                }
+                (false, None, None)
+            }
+            ContentCheck::Invalid(reason) => {
+                if self.verbose {
+                    println!("片段内容无效: {}: {}", reason, url);
+                }
+                (true, None, Some(reason))
+            }
+        }
+    }
+
+    // 完整下载一个资源到内存（密钥、初始化片段、用于试解密的片段）
+    // 服务器不支持 Range、返回 200 和整个文件时，从中截取字节范围
+    async fn fetch_bytes(&self, url: &str, byte_range: Option<ByteRange>) -> Result<Vec<u8>> {
+        let response = self.segment_request(url, byte_range).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(TestError::HttpStatus(status).into());
+        }
+
+        let data = response.bytes().await?;
+        match byte_range {
+            Some(range) if status != reqwest::StatusCode::PARTIAL_CONTENT => {
+                let start = (range.start() as usize).min(data.len());
+                let end = start.saturating_add(range.length as usize).min(data.len());
+                Ok(data[start..end].to_vec())
+            }
+            _ => Ok(data.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{Policy, PolicyConfig, VerdictStatus};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 在本机端口上按路径返回固定内容的 HTTP 服务，返回根地址
+    async fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let files = Arc::new(files);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let files = files.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0u8; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match socket.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let mut words = request.split_whitespace();
+                    let (method, path) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+                    let (status, body) = match files.iter().find(|(name, _)| *name == path) {
+                        Some((_, body)) => ("200 OK", body.as_slice()),
+                        None => ("404 Not Found", &b""[..]),
+                    };
+                    let header = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+                    let _ = socket.write_all(header.as_bytes()).await;
+                    if method != "HEAD" {
+                        let _ = socket.write_all(body).await;
+                    }
+                });
+            }
+        });
+        base
+    }
+
+
+    fn download(bytes: u64, complete: bool, pcr_span: Option<(u64, f64)>) -> SegmentDownload {
+        SegmentDownload {
+            bytes,
+            media: None,
+            ts_checked: false,
+            content_error: None,
+            complete,
+            pcr_span,
+            ttfb_ms: 0.0,
+            first_byte: None,
+        }
+    }
+
+    #[test]
+    fn test_bitrate_estimate() {
+        // 两个完整的 4 秒片段共 2 MB，被截断的片段只贡献 PCR
+        let mut samples = BitrateSamples::default();
+        samples.add(4.0, &download(1_000_000, true, Some((1_000_000, 3.9))));
+        samples.add(4.0, &download(1_000_000, true, None));
+        samples.add(4.0, &download(300_000, false, Some((300_000, 1.2))));
+        let bitrate = samples.estimate().unwrap();
+        assert_eq!(bitrate.source, BitrateSource::Segments);
+        assert_eq!(bitrate.kbps, 2_000_000.0 * 8.0 / 8.0 / 1024.0);
+
+        let mut samples = BitrateSamples::default();
+        samples.add(4.0, &download(300_000, false, Some((300_000, 0.5))));
+        assert_eq!(samples.estimate(), None);
+        samples.add_pcr(Some((500_000, 1.0)));
+        assert_eq!(samples.estimate().map(|bitrate| bitrate.source), Some(BitrateSource::Pcr));
+
+        // 估算不出时用声明的码率：4 Mbps 的流测得 5000 kbps
+        let result = SpeedTestResult {
+            speed_kbps: 5000.0,
+            advertised_bandwidth: Some(4_096_000),
+            ..Default::default()
+        }
+        .with_bitrate(None);
+        assert_eq!(result.media_bitrate.map(|bitrate| bitrate.source), Some(BitrateSource::Advertised));
+        assert_eq!(result.headroom, Some(1.25));
+        assert_eq!(SpeedTestResult::default().with_bitrate(None).headroom, None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_config_validate() {
+        assert!(TestConfig::default().validate().is_ok());
+        assert!(TestConfig { read_window_secs: 0.0, ..Default::default() }.validate().is_err());
+        assert!(TestConfig { test_timeout_secs: f64::NAN, ..Default::default() }.validate().is_err());
+        assert!(TestConfig { live_window_secs: 86400.0, ..Default::default() }.validate().is_ok());
+        assert!(TestConfig { client_timeout_secs: 1e20, ..Default::default() }.validate().is_err());
+        assert!(TestConfig { hls_segments: 0, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_range_window() {
+        let range = Some(ByteRange { length: 5, offset: Some(3) });
+
+        // 206：响应就是字节范围本身，多余的数据不计入
+        let mut window = RangeWindow::new(range, reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(window.take(b"abcdefgh"), b"abcde");
+        assert!(window.is_done());
+
+        // 200：服务器忽略了 Range，跳过 offset 之前的数据，跨数据块截取
+        let mut window = RangeWindow::new(range, reqwest::StatusCode::OK);
+        assert_eq!(window.take(b"ab"), b"");
+        assert_eq!(window.take(b"cdef"), b"def");
+        assert!(!window.is_done());
+        assert_eq!(window.take(b"ghijk"), b"gh");
+        assert!(window.is_done());
+
+        let mut window = RangeWindow::new(None, reqwest::StatusCode::OK);
+        assert_eq!(window.take(b"abc"), b"abc");
+        assert!(!window.is_done());
+    }
+
+    #[test]
+    fn test_first_byte_timing() {
+        let start = Instant::now();
+        let mut timing = FirstByteTiming::default();
+        assert_eq!(timing.delay_ms(), -1.0);
+        assert_eq!(timing.first_media_byte_ms(start), None);
+
+        let later = start + Duration::from_millis(300);
+        timing.add(&SegmentDownload { ttfb_ms: 120.7, first_byte: Some(later), ..download(1000, true, None) });
+        timing.add(&SegmentDownload { ttfb_ms: 80.2, first_byte: Some(later + Duration::from_millis(50)), ..download(1000, true, None) });
+        assert_eq!(timing.ttfb_ms, Some(80.2));
+        assert_eq!(timing.delay_ms(), 80.0);
+        assert_eq!(timing.first_media_byte_ms(start), Some(300.0));
+    }
+
+    #[tokio::test]
+    async fn test_segments_not_checked_as_ts() {
+        let tester = SpeedTester::new(false, TestConfig::default());
+
+        // fMP4 片段的 DASH 流：没有片段按 TS 检查，content_valid 为空
+        let mpd = r#"<MPD type="static" mediaPresentationDuration="PT4S"><Period><AdaptationSet mimeType="video/mp4">
+<Representation id="v" bandwidth="400000"><SegmentTemplate media="v$Number$.m4s" startNumber="1" duration="2" timescale="1"/></Representation>
+</AdaptationSet></Period></MPD>"#;
+        let fragment = b"\x00\x00\x00\x18stypmsdh\x00\x00\x00\x00msdhmsix".repeat(20);
+        let base = serve(vec![("/live.mpd", mpd.into()), ("/v1.m4s", fragment.clone()), ("/v2.m4s", fragment)]).await;
+        let result = tester.test_dash_url(&format!("{}/live.mpd", base)).await.unwrap();
+        assert!(result.success);
+        assert_eq!((result.segments_ok, result.content_valid, result.content_error), (Some(2), None, None));
+
+        // 打包音频（ADTS AAC）片段的 HLS 流
+        let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXTINF:2,\na1.aac\n#EXTINF:2,\na2.aac\n#EXT-X-ENDLIST\n";
+        let audio = b"\xFF\xF1\x50\x80\x02\x1F\xFC".repeat(100);
+        let base = serve(vec![("/audio.m3u8", playlist.into()), ("/a1.aac", audio.clone()), ("/a2.aac", audio)]).await;
+        let result = tester.test_m3u8_url(&format!("{}/audio.m3u8", base)).await.unwrap();
+        assert!(result.success);
+        assert_eq!((result.segments_ok, result.content_valid, result.content_error), (Some(2), None, None));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_segments_left_to_policy() {
+        // 和直连下载一样，片段内容无效时测试仍算成功，由判定规则判为不通过
+        let tester = SpeedTester::new(false, TestConfig::default());
+        let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXTINF:2,\n1.ts\n#EXT-X-ENDLIST\n";
+        let html = b"<!DOCTYPE html><html><body>Please log in</body></html>".to_vec();
+        let base = serve(vec![("/index.m3u8", playlist.into()), ("/1.ts", html)]).await;
+        let result = tester.test_m3u8_url(&format!("{}/index.m3u8", base)).await.unwrap();
+        assert!(result.success);
+        assert_eq!((result.segments_ok, result.content_valid), (Some(1), Some(false)));
+        assert!(result.content_error.as_deref().is_some_and(|error| error.contains("HTML")));
+
+        let verdict = Policy::new(PolicyConfig::default()).evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::Fail);
+        assert!(verdict.reason.contains("内容无效"));
+    }
+}
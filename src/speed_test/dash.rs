@@ -0,0 +1,148 @@
+use anyhow::Result;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::timeout;
+
+use super::{
+    classify_error, secs, ConnectionTiming, ErrorKind, HlsMeasurement, SpeedTestResult, SpeedTester, TestError,
+};
+
+impl SpeedTester {
+    pub(super) async fn test_dash_url(&self, url: &str) -> Result<SpeedTestResult> {
+        let start_time = Instant::now();
+
+        if self.verbose {
+            println!("执行MPEG-DASH流测试...");
+        }
+
+        let result = timeout(secs(self.config.test_timeout_secs), self.test_dash_stream(url)).await;
+        let duration_secs = start_time.elapsed().as_secs_f64();
+
+        match result {
+            Ok(Ok(measurement)) => Ok(SpeedTestResult {
+                url: url.to_string(),
+                success: true,
+                delay_ms: measurement.delay_ms,
+                speed_kbps: measurement.speed_kbps,
+                size_mb: measurement.bytes as f64 / (1024.0 * 1024.0),
+                duration_secs,
+                protocol_type: "DASH".to_string(),
+                details: Some(measurement.details),
+                bytes_downloaded: measurement.bytes,
+                ttfb_ms: measurement.timing.ttfb_ms,
+                first_media_byte_ms: measurement.timing.first_media_byte_ms(start_time),
+                segments_total: Some(measurement.segments_total),
+                segments_ok: Some(measurement.segments_ok),
+                advertised_bandwidth: measurement.advertised_bandwidth,
+                init_segment: measurement.init_segment,
+                content_valid: measurement.content_valid,
+                content_error: measurement.content_error,
+                media: measurement.media,
+                ..Default::default()
+            }
+            .with_bitrate(measurement.media_bitrate)
+            .with_connection(measurement.connection)),
+            Ok(Err(e)) => {
+                if let Some(TestError::Drm(system)) = e.downcast_ref::<TestError>() {
+                    return Ok(SpeedTestResult {
+                        duration_secs,
+                        ..SpeedTestResult::drm_protected(url, *system, format!("DASH流使用 {} DRM 加密", system))
+                    });
+                }
+                Ok(SpeedTestResult {
+                    duration_secs,
+                    ..SpeedTestResult::failure(url, "DASH", classify_error(&e), format!("DASH测试失败: {}", e))
+                })
+            }
+            Err(_) => Ok(SpeedTestResult {
+                duration_secs,
+                ..SpeedTestResult::failure(url, "DASH", ErrorKind::Timeout, "DASH测试超时".to_string())
+            }),
+        }
+    }
+
+    // 解析 MPD，选择带宽最高的视频表示，检查初始化片段后并发下载前 hls_segments 个（直播为最新的）媒体片段
+    async fn test_dash_stream(&self, url: &str) -> Result<HlsMeasurement> {
+        let mpd = self.mpd_parser.parse_mpd(url, &self.client).await?;
+        let representation = mpd.best_representation().ok_or(TestError::NoSegments)?;
+
+        if self.verbose {
+            println!(
+                "MPD: {}, 时长 {}, {} 个表示, 选择 {} ({})",
+                if mpd.is_live { "直播" } else { "点播" },
+                mpd.duration.map_or("未知".to_string(), |duration| format!("{:.0} 秒", duration)),
+                mpd.representations.len(),
+                representation.id,
+                representation.describe()
+            );
+        }
+
+        if let Some(system) = representation.drm {
+            return Err(TestError::Drm(system).into());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_secs_f64());
+        let segments = representation.media_segments(self.config.hls_segments, mpd.is_live, mpd.availability_start, now);
+
+        // 在初始化片段和媒体片段的请求之前探测片段所在的主机
+        let init_map = representation.init_segment();
+        let connection = match init_map.as_ref().map(|map| &map.uri).or(segments.first().map(|segment| &segment.uri)) {
+            Some(uri) => self.media_connection(uri).await,
+            None => ConnectionTiming::default(),
+        };
+
+        let init_segment = match init_map {
+            Some(map) => Some(self.check_init_segment(&map).await?),
+            None => None,
+        };
+
+        if self.verbose {
+            for segment in &segments {
+                println!("DASH片段: {}", segment.uri);
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let burst = self.burst_download(&segments).await;
+        let speed_kbps = burst.speed_kbps();
+
+        let mut details = format!(
+            "DASH测试 - {}, {}, 总片段: {}, 成功: {}, 平均速度: {:.0} kbps",
+            if mpd.is_live { "直播" } else { "点播" },
+            representation.describe(),
+            segments.len(),
+            burst.segments_ok,
+            speed_kbps
+        );
+        if let Some(media) = &burst.media {
+            details += &format!(", 码流: {}", media.label());
+        }
+        if let Some(reason) = &burst.content.error {
+            details += &format!(", 内容无效: {}", reason);
+        }
+
+        if self.verbose {
+            println!("{}", details);
+        }
+
+        Ok(HlsMeasurement {
+            delay_ms: burst.timing.delay_ms(),
+            timing: burst.timing,
+            connection,
+            speed_kbps,
+            bytes: burst.bytes,
+            segments_total: segments.len(),
+            segments_ok: burst.segments_ok,
+            advertised_bandwidth: Some(representation.bandwidth).filter(|bandwidth| *bandwidth > 0),
+            details,
+            content_valid: burst.content.valid(),
+            content_error: burst.content.error,
+            media: burst.media,
+            media_bitrate: burst.bitrate.estimate(),
+            init_segment,
+            ..Default::default()
+        })
+    }
+}
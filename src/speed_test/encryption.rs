@@ -0,0 +1,144 @@
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Instant;
+
+use super::{SpeedTester, TestError};
+use crate::m3u8_parser::{KeyMethod, MediaPlaylist};
+use crate::mpeg_ts::{TS_PACKET_SIZE, TS_SYNC_BYTE};
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+// AES-128 密钥的检查结果，密钥不可用时测试直接失败，不会产生这个结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeyCheck {
+    pub uri: String,
+    pub delay_ms: f64,     // 获取密钥的耗时
+    pub decrypted: bool,   // 是否用该密钥成功解密了第一个加密片段
+}
+
+// 媒体序号作为默认 IV：128 位大端整数（RFC 8216 5.2）
+fn sequence_iv(sequence: u64) -> [u8; 16] {
+    (sequence as u128).to_be_bytes()
+}
+
+// 用 AES-128-CBC（PKCS7 填充）解密片段
+fn decrypt_segment(data: &mut [u8], key: &[u8; 16], iv: &[u8; 16]) -> Result<usize> {
+    let plain = Aes128CbcDec::new(key.into(), iv.into())
+        .decrypt_padded_mut::<Pkcs7>(data)
+        .map_err(|_| TestError::Key("片段解密失败，密钥或 IV 不正确".to_string()))?;
+    Ok(plain.len())
+}
+
+// 开头的几个包都以同步字节开始
+fn looks_like_ts(data: &[u8]) -> bool {
+    !data.is_empty() && data.iter().step_by(TS_PACKET_SIZE).take(4).all(|byte| *byte == TS_SYNC_BYTE)
+}
+
+impl SpeedTester {
+    // 检查播放列表中第一个 AES-128 密钥：可访问、状态正常、长度为 16 字节；
+    // 开启 decrypt_check 时再用它解密第一个加密片段
+    pub(super) async fn check_key(&self, media: &MediaPlaylist) -> Result<Option<KeyCheck>> {
+        let Some((index, segment, key)) = media
+            .segments
+            .iter()
+            .enumerate()
+            .find_map(|(index, segment)| {
+                let key = segment.key.as_ref().filter(|key| key.method == KeyMethod::Aes128)?;
+                Some((index, segment, key))
+            })
+        else {
+            return Ok(None);
+        };
+
+        let Some(uri) = &key.uri else {
+            return Err(TestError::Key("EXT-X-KEY 缺少 URI".to_string()).into());
+        };
+
+        if self.verbose {
+            println!("获取 AES-128 密钥: {}", uri);
+        }
+
+        let start_time = Instant::now();
+        let key_bytes = self
+            .fetch_bytes(uri, None)
+            .await
+            .map_err(|e| TestError::Key(format!("获取 {} 失败: {}", uri, e)))?;
+        let delay_ms = start_time.elapsed().as_millis() as f64;
+
+        let key_bytes: [u8; 16] = key_bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| TestError::Key(format!("密钥长度为 {} 字节，应为 16 字节", key_bytes.len())))?;
+
+        let mut check = KeyCheck {
+            uri: uri.clone(),
+            delay_ms,
+            decrypted: false,
+        };
+
+        if !self.config.decrypt_check {
+            return Ok(Some(check));
+        }
+
+        let iv = key.iv.unwrap_or_else(|| sequence_iv(media.media_sequence + index as u64));
+        let mut data = self.fetch_bytes(&segment.uri, segment.byte_range).await?;
+        let length = decrypt_segment(&mut data, &key_bytes, &iv)?;
+
+        // fMP4 片段解密后不是 TS，只要求填充正确
+        if segment.map.is_none() && !looks_like_ts(&data[..length]) {
+            return Err(TestError::Key("解密后不是有效的 MPEG-TS".to_string()).into());
+        }
+
+        if self.verbose {
+            println!("首个加密片段解密成功: {} bytes", length);
+        }
+        check.decrypted = true;
+        Ok(Some(check))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+
+    type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+    #[test]
+    fn test_sequence_iv() {
+        let iv = sequence_iv(0x0102);
+        assert_eq!(iv[..14], [0u8; 14]);
+        assert_eq!(iv[14..], [0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_decrypt_segment() {
+        let key = [7u8; 16];
+        let iv = sequence_iv(42);
+
+        // 3 个 TS 包共 564 字节，PKCS7 填充到 576 字节
+        let plain_length = TS_PACKET_SIZE * 3;
+        let mut data = vec![0u8; 576];
+        for packet in data[..plain_length].chunks_mut(TS_PACKET_SIZE) {
+            packet[0] = TS_SYNC_BYTE;
+        }
+        let plain = data[..plain_length].to_vec();
+        Aes128CbcEnc::new(&key.into(), &iv.into())
+            .encrypt_padded_mut::<Pkcs7>(&mut data, plain_length)
+            .unwrap();
+        let mut wrong = data.clone();
+
+        let length = decrypt_segment(&mut data, &key, &iv).unwrap();
+        assert_eq!(length, plain_length);
+        assert_eq!(data[..length], plain[..]);
+        assert!(looks_like_ts(&data[..length]));
+
+        // 用错误的密钥解密，填充或内容不对
+        if let Ok(length) = decrypt_segment(&mut wrong, &[8u8; 16], &iv) {
+            assert!(!looks_like_ts(&wrong[..length]));
+        }
+        assert!(!looks_like_ts(&plain[1..]));
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+
+use super::SpeedTester;
+use crate::m3u8_parser::Map;
+
+// EXT-X-MAP 初始化片段的封装格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Container {
+    Ts,   // MPEG-TS（EXT-X-MAP 中只放 PAT/PMT）
+    Fmp4, // 普通 fMP4
+    Cmaf, // ftyp 中声明了 CMAF 品牌（cmfc、cmf2 等）的 fMP4
+}
+
+impl Container {
+    pub fn protocol_type(&self) -> &'static str {
+        match self {
+            Container::Ts => "HLS/M3U8",
+            Container::Fmp4 => "HLS/fMP4",
+            Container::Cmaf => "HLS/CMAF",
+        }
+    }
+}
+
+// 初始化片段的检查结果，获取失败或格式不对时测试直接失败
+#[derive(Debug, Clone, Serialize)]
+pub struct InitSegment {
+    pub uri: String,
+    pub bytes: u64,
+    pub container: Container,
+    pub brands: Vec<String>, // ftyp 中的主品牌和兼容品牌
+}
+
+// 遍历顶层 MP4 box，返回 (类型, 内容)，遇到长度不合法的 box 时停止
+fn top_level_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut rest = data;
+
+    while rest.len() >= 8 {
+        let size = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as u64;
+        let box_type = [rest[4], rest[5], rest[6], rest[7]];
+        let (header, size) = match size {
+            // size 为 1 时使用 64 位长度
+            1 if rest.len() >= 16 => (16, u64::from_be_bytes(rest[8..16].try_into().unwrap())),
+            // size 为 0 表示一直到文件结尾
+            0 => (8, rest.len() as u64),
+            size => (8, size),
+        };
+        if size < header || size > rest.len() as u64 {
+            break;
+        }
+
+        boxes.push((box_type, &rest[header as usize..size as usize]));
+        rest = &rest[size as usize..];
+    }
+
+    boxes
+}
+
+// 识别初始化片段：TS 以同步字节开始；fMP4 必须有 moov，按 ftyp 品牌区分 CMAF
+fn identify_init_segment(data: &[u8]) -> Option<(Container, Vec<String>)> {
+    if data.first() == Some(&0x47) {
+        return Some((Container::Ts, Vec::new()));
+    }
+
+    let boxes = top_level_boxes(data);
+    if !boxes.iter().any(|(box_type, _)| box_type == b"moov") {
+        return None;
+    }
+
+    // ftyp: 主品牌(4) + 次版本(4) + 兼容品牌(4 * n)
+    let brands: Vec<String> = boxes
+        .iter()
+        .find(|(box_type, _)| box_type == b"ftyp")
+        .map(|(_, payload)| {
+            payload
+                .chunks_exact(4)
+                .enumerate()
+                .filter(|(index, _)| *index != 1)
+                .map(|(_, brand)| String::from_utf8_lossy(brand).to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let container = if brands.iter().any(|brand| brand.starts_with("cmf")) {
+        Container::Cmaf
+    } else {
+        Container::Fmp4
+    };
+    Some((container, brands))
+}
+
+impl SpeedTester {
+    // 获取并检查 EXT-X-MAP 初始化片段，同一个播放列表只检查一次
+    pub(super) async fn check_init_segment(&self, map: &Map) -> Result<InitSegment> {
+        if self.verbose {
+            println!("获取初始化片段: {}", map.uri);
+        }
+
+        let data = self
+            .fetch_bytes(&map.uri, map.byte_range)
+            .await
+            .with_context(|| format!("获取初始化片段 {} 失败", map.uri))?;
+
+        let (container, brands) = identify_init_segment(&data)
+            .ok_or_else(|| anyhow!("初始化片段 {} 不是有效的 fMP4（缺少 moov）或 MPEG-TS", map.uri))?;
+
+        if self.verbose {
+            println!("初始化片段: {} bytes, {:?}, 品牌 {}", data.len(), container, brands.join(","));
+        }
+
+        Ok(InitSegment {
+            uri: map.uri.clone(),
+            bytes: data.len() as u64,
+            container,
+            brands,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_identify_init_segment() {
+        let mut cmaf = mp4_box(b"ftyp", b"cmfc\0\0\0\0iso6cmfc");
+        cmaf.extend(mp4_box(b"moov", &[0u8; 16]));
+        let (container, brands) = identify_init_segment(&cmaf).unwrap();
+        assert_eq!(container, Container::Cmaf);
+        assert_eq!(brands, vec!["cmfc", "iso6", "cmfc"]);
+
+        let mut fmp4 = mp4_box(b"ftyp", b"iso5\0\0\0\x01iso5dash");
+        fmp4.extend(mp4_box(b"moov", &[]));
+        assert_eq!(identify_init_segment(&fmp4).unwrap().0, Container::Fmp4);
+
+        // 没有 moov，或者 box 长度被截断
+        assert!(identify_init_segment(&mp4_box(b"ftyp", b"iso5\0\0\0\0")).is_none());
+        assert!(identify_init_segment(&fmp4[..fmp4.len() - 4]).is_none());
+        assert!(identify_init_segment(b"<html>").is_none());
+
+        assert_eq!(identify_init_segment(&[0x47, 0x40, 0x00, 0x10]).unwrap().0, Container::Ts);
+    }
+}
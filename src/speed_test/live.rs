@@ -0,0 +1,309 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Instant;
+
+use super::{secs, BitrateSamples, ContentSummary, FirstByteTiming, HlsMeasurement, SpeedTester, TestError};
+use crate::m3u8_parser::{MediaPlaylist, MediaSegment, ParsedM3u8};
+
+// 直播刷新测试的统计结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LiveStats {
+    pub window_secs: f64,          // 实际观察时长
+    pub target_duration: f64,      // 播放列表的 EXT-X-TARGETDURATION
+    pub reloads: usize,            // 播放列表刷新次数
+    pub stale_reloads: usize,      // 没有发现新片段的刷新次数（含刷新失败）
+    pub new_segments: usize,       // 观察期间新发布的片段数
+    pub missed_segments: usize,    // 还没来得及下载就被移出播放列表的片段数
+    pub published_secs: f64,       // 新发布片段的 EXTINF 总时长
+    pub max_publish_gap_secs: f64, // 两次发现新片段之间（或观察开始/结束到发现新片段）的最长间隔
+    pub realtime_ratio: f64,       // 已下载片段的 EXTINF 总时长 / 下载耗时，大于 1 表示下载快于播放
+    pub on_time: bool,             // 服务器是否按时发布新片段
+    pub keeps_up: bool,            // 下载速度是否跟得上实时播放
+}
+
+impl LiveStats {
+    // 新片段的发布间隔不超过两个目标时长（最多错过一次刷新）视为按时发布
+    fn finish(&mut self) {
+        self.on_time = self.new_segments > 0 && self.max_publish_gap_secs <= self.target_duration * 2.0;
+        self.keeps_up = self.realtime_ratio >= 1.0 && self.missed_segments == 0;
+    }
+}
+
+// 跟随媒体序号的刷新记账，时间为从观察开始经过的秒数
+struct LiveTracker {
+    next_sequence: u64,     // 下一个要下载的片段序号
+    last_publish_secs: f64, // 上一次发现新片段的时间
+}
+
+impl LiveTracker {
+    // 从直播边缘（最新的一个片段）开始下载
+    fn new(playlist: &MediaPlaylist) -> Self {
+        Self {
+            next_sequence: playlist.media_sequence + playlist.segments.len().saturating_sub(1) as u64,
+            last_publish_secs: 0.0,
+        }
+    }
+
+    // 记录一次刷新，返回需要下载的新片段；initial 为最初解析的播放列表，不计入发布统计
+    fn reload<'a>(
+        &mut self,
+        stats: &mut LiveStats,
+        playlist: &'a MediaPlaylist,
+        now_secs: f64,
+        initial: bool,
+    ) -> Vec<(u64, &'a MediaSegment)> {
+        if self.next_sequence < playlist.media_sequence {
+            // 片段在下载之前就已经过期
+            stats.missed_segments += (playlist.media_sequence - self.next_sequence) as usize;
+            self.next_sequence = playlist.media_sequence;
+        }
+
+        let new_segments: Vec<_> = playlist
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| (playlist.media_sequence + index as u64, segment))
+            .filter(|(sequence, _)| *sequence >= self.next_sequence)
+            .collect();
+
+        if !initial {
+            if new_segments.is_empty() {
+                stats.stale_reloads += 1;
+            } else {
+                stats.max_publish_gap_secs = stats.max_publish_gap_secs.max(now_secs - self.last_publish_secs);
+                self.last_publish_secs = now_secs;
+                stats.new_segments += new_segments.len();
+                stats.published_secs += new_segments.iter().map(|(_, segment)| segment.duration).sum::<f64>();
+            }
+        }
+
+        if let Some((sequence, _)) = new_segments.last() {
+            self.next_sequence = sequence + 1;
+        }
+        new_segments
+    }
+}
+
+impl SpeedTester {
+    // 直播模式：每个目标时长刷新一次媒体播放列表，跟随媒体序号下载新发布的片段，持续 live_window_secs
+    pub(super) async fn measure_live(&self, parsed: ParsedM3u8, stream_info: String) -> Result<HlsMeasurement> {
+        let window = secs(self.config.live_window_secs);
+        let target_duration = if parsed.media.target_duration > 0.0 { parsed.media.target_duration } else { 1.0 };
+        let start_time = Instant::now();
+
+        let mut stats = LiveStats {
+            target_duration,
+            ..Default::default()
+        };
+        let mut playlist = parsed.media;
+        let mut tracker = LiveTracker::new(&playlist);
+        let mut last_reload = start_time;
+        let mut first_reload = true;
+
+        let mut timing = FirstByteTiming::default();
+        let mut total_bytes = 0u64;
+        let mut download_secs = 0.0;
+        let mut downloaded_media_secs = 0.0;
+        let mut segments_total = 0usize;
+        let mut segments_ok = 0usize;
+        let mut content = ContentSummary::default();
+        let mut media = None;
+        let mut bitrate = BitrateSamples::default();
+
+        loop {
+            let new_segments = tracker.reload(&mut stats, &playlist, start_time.elapsed().as_secs_f64(), first_reload);
+            let published = !new_segments.is_empty();
+
+            for (sequence, segment) in new_segments {
+                segments_total += 1;
+
+                let download_start = Instant::now();
+                match self.download_segment(segment).await {
+                    Ok(download) => {
+                        let bytes = download.bytes;
+                        bitrate.add(segment.duration, &download);
+                        timing.add(&download);
+                        content.add(&download);
+                        if media.is_none() {
+                            media = download.media;
+                        }
+                        let elapsed = download_start.elapsed().as_secs_f64();
+                        total_bytes += bytes;
+                        download_secs += elapsed;
+                        downloaded_media_secs += segment.duration;
+                        segments_ok += 1;
+
+                        if self.verbose {
+                            println!(
+                                "片段 #{} 下载完成: {} bytes, 耗时 {:.2} 秒, 时长 {:.2} 秒",
+                                sequence, bytes, elapsed, segment.duration
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        if self.verbose {
+                            println!("片段 #{} 下载失败: {}", sequence, e);
+                        }
+                    }
+                }
+            }
+
+            if start_time.elapsed() >= window {
+                break;
+            }
+
+            // 有新片段时间隔一个目标时长再刷新，没有时间隔半个目标时长（RFC 8216 6.3.4）
+            let interval = if published { target_duration } else { target_duration / 2.0 };
+            let next_reload = (last_reload + secs(interval)).min(start_time + window);
+            tokio::time::sleep_until(next_reload.into()).await;
+            if start_time.elapsed() >= window {
+                break;
+            }
+
+            last_reload = Instant::now();
+            first_reload = false;
+            stats.reloads += 1;
+
+            match self.reload_media_playlist(&parsed.url).await {
+                Ok(reloaded) => playlist = reloaded,
+                Err(e) => {
+                    // 刷新失败时继续使用旧的播放列表，下一轮会被计为没有新片段
+                    if self.verbose {
+                        println!("刷新播放列表失败: {}", e);
+                    }
+                    playlist.segments.clear();
+                    playlist.media_sequence = tracker.next_sequence;
+                }
+            }
+        }
+
+        stats.window_secs = start_time.elapsed().as_secs_f64();
+        stats.max_publish_gap_secs = stats.max_publish_gap_secs.max(stats.window_secs - tracker.last_publish_secs);
+        stats.realtime_ratio = if download_secs > 0.0 { downloaded_media_secs / download_secs } else { 0.0 };
+        stats.finish();
+
+        if segments_ok == 0 {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let speed_kbps = if download_secs > 0.0 {
+            (total_bytes as f64 * 8.0) / download_secs / 1024.0
+        } else {
+            0.0
+        };
+
+        let details = format!(
+            "HLS直播测试 - {}, 观察 {:.0} 秒, 刷新 {} 次, 新片段 {} 个, 发布{}, 下载{}实时 ({:.2} 倍), 平均速度: {:.0} kbps",
+            stream_info,
+            stats.window_secs,
+            stats.reloads,
+            stats.new_segments,
+            if stats.on_time { "准时" } else { "延迟" },
+            if stats.keeps_up { "跟得上" } else { "跟不上" },
+            stats.realtime_ratio,
+            speed_kbps
+        );
+
+        if self.verbose {
+            println!("{}", details);
+        }
+
+        Ok(HlsMeasurement {
+            delay_ms: timing.delay_ms(),
+            timing,
+            speed_kbps,
+            bytes: total_bytes,
+            segments_total,
+            segments_ok,
+            advertised_bandwidth: parsed.variant.map(|variant| variant.bandwidth).filter(|bandwidth| *bandwidth > 0),
+            details,
+            content_valid: content.valid(),
+            content_error: content.error,
+            media,
+            media_bitrate: bitrate.estimate(),
+            live: Some(stats),
+            ..Default::default()
+        })
+    }
+
+    async fn reload_media_playlist(&self, url: &str) -> Result<MediaPlaylist> {
+        if self.verbose {
+            println!("刷新播放列表: {}", url);
+        }
+        self.m3u8_parser.fetch_media_playlist(url, &self.client).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 媒体序号从 sequence 开始、每个片段 4 秒的直播播放列表
+    fn playlist(sequence: u64, count: usize) -> MediaPlaylist {
+        MediaPlaylist {
+            media_sequence: sequence,
+            target_duration: 4.0,
+            segments: (0..count)
+                .map(|index| MediaSegment {
+                    uri: format!("http://example.com/{}.ts", sequence + index as u64),
+                    duration: 4.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn sequences(segments: &[(u64, &MediaSegment)]) -> Vec<u64> {
+        segments.iter().map(|(sequence, _)| *sequence).collect()
+    }
+
+    #[test]
+    fn test_live_reloads() {
+        let mut stats = LiveStats { target_duration: 4.0, ..Default::default() };
+        let initial = playlist(100, 5);
+        let mut tracker = LiveTracker::new(&initial);
+
+        // 最初只下载直播边缘的片段，不计入发布统计
+        assert_eq!(sequences(&tracker.reload(&mut stats, &initial, 0.0, true)), vec![104]);
+        assert_eq!((stats.new_segments, stats.stale_reloads), (0, 0));
+
+        // 没有新片段的刷新
+        assert!(tracker.reload(&mut stats, &playlist(100, 5), 4.0, false).is_empty());
+        assert_eq!(stats.stale_reloads, 1);
+
+        // 发布了两个新片段
+        assert_eq!(sequences(&tracker.reload(&mut stats, &playlist(102, 5), 6.0, false)), vec![105, 106]);
+        assert_eq!((stats.new_segments, stats.published_secs, stats.max_publish_gap_secs), (2, 8.0, 6.0));
+
+        // 播放列表一下滚动了太多，107、108 在下载之前就被移出
+        assert_eq!(sequences(&tracker.reload(&mut stats, &playlist(109, 3), 20.0, false)), vec![109, 110, 111]);
+        assert_eq!(stats.missed_segments, 2);
+        assert_eq!(stats.max_publish_gap_secs, 14.0);
+        assert_eq!(tracker.next_sequence, 112);
+    }
+
+    #[test]
+    fn test_live_stats_finish() {
+        let mut stats = LiveStats {
+            target_duration: 4.0,
+            new_segments: 5,
+            max_publish_gap_secs: 8.0,
+            realtime_ratio: 1.5,
+            ..Default::default()
+        };
+        stats.finish();
+        assert!(stats.on_time && stats.keeps_up);
+
+        // 发布间隔超过两个目标时长，或有片段来不及下载
+        stats.max_publish_gap_secs = 8.5;
+        stats.missed_segments = 1;
+        stats.finish();
+        assert!(!stats.on_time && !stats.keeps_up);
+
+        // 下载慢于实时，或观察期间没有新片段
+        let mut stats = LiveStats { target_duration: 4.0, realtime_ratio: 0.8, ..Default::default() };
+        stats.finish();
+        assert!(!stats.on_time && !stats.keeps_up);
+    }
+}
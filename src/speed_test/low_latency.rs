@@ -0,0 +1,281 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Instant;
+use url::Url;
+
+use super::{secs, BitrateSamples, ContentSummary, FirstByteTiming, HlsMeasurement, SpeedTester, TestError};
+use crate::m3u8_parser::{MediaPlaylist, ParsedM3u8, PartialSegment};
+
+// 低延迟 HLS 阻塞刷新测试的统计结果，部分片段的发布和完整片段的下载分开统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LowLatencyStats {
+    pub window_secs: f64,             // 实际观察时长
+    pub part_target: f64,             // EXT-X-PART-INF 的 PART-TARGET
+    pub blocking_reloads: usize,      // 带 _HLS_msn / _HLS_part 的阻塞刷新次数
+    pub early_responses: usize,       // 服务器没等到请求的部分片段发布就返回（或刷新失败）的次数
+    pub parts_published: usize,       // 观察期间新发布的部分片段数
+    pub parts_failed: usize,          // 下载失败或标记为 GAP 的部分片段数
+    pub avg_part_interval_secs: f64,  // 部分片段的平均发布间隔（观察时长 / 新部分片段数）
+    pub avg_reload_wait_secs: f64,    // 阻塞刷新的平均等待时间，即等待下一个部分片段发布的时间
+    pub max_reload_wait_secs: f64,
+    pub avg_part_download_ms: f64,    // 部分片段的平均下载耗时
+    pub preload_hints: bool,          // 播放列表是否提供 TYPE=PART 的预加载提示
+    pub on_time: bool,                // 部分片段是否按 PART-TARGET 发布
+}
+
+impl LowLatencyStats {
+    // 平均发布间隔不超过 1.5 个 PART-TARGET，且单次阻塞等待不超过 3 个 PART-TARGET 视为按时发布
+    fn finish(&mut self, reload_waits: &[f64]) {
+        if !reload_waits.is_empty() {
+            self.avg_reload_wait_secs = reload_waits.iter().sum::<f64>() / reload_waits.len() as f64;
+            self.max_reload_wait_secs = reload_waits.iter().copied().fold(0.0, f64::max);
+        }
+        if self.parts_published > 0 {
+            self.avg_part_interval_secs = self.window_secs / self.parts_published as f64;
+        }
+        self.on_time = self.parts_published > 0
+            && self.avg_part_interval_secs <= self.part_target * 1.5
+            && self.max_reload_wait_secs <= self.part_target * 3.0;
+    }
+}
+
+// 在播放列表地址上加上阻塞刷新参数，保留原有的查询参数
+fn blocking_reload_url(url: &str, msn: u64, part: usize) -> Result<String> {
+    let mut url = Url::parse(url)?;
+    url.query_pairs_mut()
+        .append_pair("_HLS_msn", &msn.to_string())
+        .append_pair("_HLS_part", &part.to_string());
+    Ok(url.into())
+}
+
+// 播放列表中的所有部分片段，附带所属片段的媒体序号和在片段中的序号
+fn all_parts(playlist: &MediaPlaylist) -> Vec<(u64, usize, &PartialSegment)> {
+    let completed = playlist
+        .segments
+        .iter()
+        .enumerate()
+        .map(|(index, segment)| (playlist.media_sequence + index as u64, &segment.parts));
+    let trailing = std::iter::once((playlist.media_sequence + playlist.segments.len() as u64, &playlist.trailing_parts));
+
+    completed
+        .chain(trailing)
+        .flat_map(|(sequence, parts)| parts.iter().enumerate().map(move |(index, part)| (sequence, index, part)))
+        .collect()
+}
+
+impl SpeedTester {
+    // 低延迟模式：用 _HLS_msn / _HLS_part 阻塞刷新等待下一个部分片段发布，持续 live_window_secs；
+    // 统计部分片段的发布间隔和阻塞等待时间，并单独下载新完成的完整片段测量吞吐量
+    pub(super) async fn measure_low_latency(&self, parsed: ParsedM3u8, stream_info: String) -> Result<HlsMeasurement> {
+        let part_target = parsed.media.part_target.unwrap_or(1.0);
+        if !parsed.media.server_control.as_ref().is_some_and(|control| control.can_block_reload) {
+            if self.verbose {
+                println!("服务器不支持阻塞刷新（CAN-BLOCK-RELOAD），改用 live 模式测试");
+            }
+            return self.measure_live(parsed, stream_info).await;
+        }
+
+        let window = secs(self.config.live_window_secs);
+        let start_time = Instant::now();
+
+        let mut stats = LowLatencyStats {
+            part_target,
+            preload_hints: parsed.media.preload_hints.iter().any(|hint| hint.hint_type == "PART"),
+            ..Default::default()
+        };
+        if self.verbose {
+            for hint in &parsed.media.preload_hints {
+                println!("预加载提示 ({}): {}", hint.hint_type, hint.uri);
+            }
+        }
+
+        let (mut next_msn, mut next_part) = parsed.media.next_part();
+        // 只下载观察开始后才完成的完整片段
+        let mut next_segment = next_msn;
+
+        let mut reload_waits = Vec::new();
+        let mut part_download_secs = Vec::new();
+        let mut timing = FirstByteTiming::default();
+        let mut total_bytes = 0u64;
+        let mut download_secs = 0.0;
+        let mut segments_total = 0usize;
+        let mut segments_ok = 0usize;
+        let mut content = ContentSummary::default();
+        let mut media = None;
+        let mut bitrate = BitrateSamples::default();
+
+        while start_time.elapsed() < window {
+            let url = blocking_reload_url(&parsed.url, next_msn, next_part)?;
+            if self.verbose {
+                println!("阻塞刷新播放列表: {}", url);
+            }
+
+            let reload_start = Instant::now();
+            stats.blocking_reloads += 1;
+            let playlist = match self.m3u8_parser.fetch_media_playlist(&url, &self.client).await {
+                Ok(playlist) => playlist,
+                Err(e) => {
+                    if self.verbose {
+                        println!("刷新播放列表失败: {}", e);
+                    }
+                    stats.early_responses += 1;
+                    tokio::time::sleep(secs(part_target)).await;
+                    continue;
+                }
+            };
+            let wait = reload_start.elapsed().as_secs_f64();
+
+            let new_parts: Vec<_> = all_parts(&playlist)
+                .into_iter()
+                .filter(|(sequence, index, _)| (*sequence, *index) >= (next_msn, next_part))
+                .collect();
+            if new_parts.is_empty() {
+                // 没有等到请求的部分片段，避免空转
+                stats.early_responses += 1;
+                tokio::time::sleep(secs(part_target / 2.0)).await;
+            } else {
+                reload_waits.push(wait);
+            }
+
+            for (sequence, index, part) in new_parts {
+                stats.parts_published += 1;
+                if part.gap {
+                    stats.parts_failed += 1;
+                    continue;
+                }
+
+                // 部分片段不一定以 PAT / PMT 开始，不检查内容
+                let download_start = Instant::now();
+                match self.download_resource(&part.uri, part.byte_range, false, None).await {
+                    Ok(download) => {
+                        part_download_secs.push(download_start.elapsed().as_secs_f64());
+                        timing.add(&download);
+                        if self.verbose {
+                            println!(
+                                "部分片段 {}.{} 下载完成: {} bytes, 时长 {:.2} 秒, 等待发布 {:.2} 秒",
+                                sequence, index, download.bytes, part.duration, wait
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        stats.parts_failed += 1;
+                        if self.verbose {
+                            println!("部分片段 {}.{} 下载失败: {}", sequence, index, e);
+                        }
+                    }
+                }
+            }
+
+            // 新完成的完整片段用于测量吞吐量
+            for (index, segment) in playlist.segments.iter().enumerate() {
+                let sequence = playlist.media_sequence + index as u64;
+                if sequence < next_segment {
+                    continue;
+                }
+                next_segment = sequence + 1;
+                segments_total += 1;
+
+                let download_start = Instant::now();
+                match self.download_segment(segment).await {
+                    Ok(download) => {
+                        total_bytes += download.bytes;
+                        bitrate.add(segment.duration, &download);
+                        timing.add(&download);
+                        content.add(&download);
+                        if media.is_none() {
+                            media = download.media;
+                        }
+                        download_secs += download_start.elapsed().as_secs_f64();
+                        segments_ok += 1;
+                    }
+                    Err(e) => {
+                        if self.verbose {
+                            println!("片段 #{} 下载失败: {}", sequence, e);
+                        }
+                    }
+                }
+            }
+
+            (next_msn, next_part) = playlist.next_part();
+        }
+
+        stats.window_secs = start_time.elapsed().as_secs_f64();
+        if !part_download_secs.is_empty() {
+            stats.avg_part_download_ms = part_download_secs.iter().sum::<f64>() / part_download_secs.len() as f64 * 1000.0;
+        }
+        stats.finish(&reload_waits);
+
+        if segments_ok == 0 && part_download_secs.is_empty() {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let speed_kbps = if download_secs > 0.0 {
+            (total_bytes as f64 * 8.0) / download_secs / 1024.0
+        } else {
+            0.0
+        };
+
+        let details = format!(
+            "HLS低延迟测试 - {}, 观察 {:.0} 秒, 阻塞刷新 {} 次, 新部分片段 {} 个, 平均发布间隔 {:.2} 秒 (PART-TARGET {} 秒), 发布{}, 完整片段 {}/{}, 平均速度: {:.0} kbps",
+            stream_info,
+            stats.window_secs,
+            stats.blocking_reloads,
+            stats.parts_published,
+            stats.avg_part_interval_secs,
+            stats.part_target,
+            if stats.on_time { "准时" } else { "延迟" },
+            segments_ok,
+            segments_total,
+            speed_kbps
+        );
+
+        if self.verbose {
+            println!("{}", details);
+        }
+
+        Ok(HlsMeasurement {
+            delay_ms: timing.delay_ms(),
+            timing,
+            speed_kbps,
+            bytes: total_bytes,
+            segments_total,
+            segments_ok,
+            advertised_bandwidth: parsed.variant.map(|variant| variant.bandwidth).filter(|bandwidth| *bandwidth > 0),
+            details,
+            content_valid: content.valid(),
+            content_error: content.error,
+            media,
+            media_bitrate: bitrate.estimate(),
+            low_latency: Some(stats),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::m3u8_parser::{parse_playlist, Playlist};
+
+    #[test]
+    fn test_blocking_reload_url() {
+        assert_eq!(
+            blocking_reload_url("http://example.com/ll/index.m3u8?token=abc", 268, 2).unwrap(),
+            "http://example.com/ll/index.m3u8?token=abc&_HLS_msn=268&_HLS_part=2"
+        );
+    }
+
+    #[test]
+    fn test_all_parts() {
+        let content = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-PART-INF:PART-TARGET=1\n#EXT-X-MEDIA-SEQUENCE:10\n\
+#EXT-X-PART:DURATION=1,URI=\"p10.0.mp4\"\n#EXT-X-PART:DURATION=1,URI=\"p10.1.mp4\"\n#EXTINF:2,\ns10.mp4\n\
+#EXT-X-PART:DURATION=1,URI=\"p11.0.mp4\"\n";
+        let Playlist::Media(media) = parse_playlist(content, "http://example.com/ll/index.m3u8").unwrap() else {
+            panic!("应解析为媒体播放列表");
+        };
+
+        let parts: Vec<(u64, usize)> = all_parts(&media).into_iter().map(|(sequence, index, _)| (sequence, index)).collect();
+        assert_eq!(parts, vec![(10, 0), (10, 1), (11, 0)]);
+        assert_eq!(media.next_part(), (11, 1));
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Instant;
+
+use super::{secs, BitrateSamples, ContentSummary, FirstByteTiming, HlsMeasurement, SpeedTester, TestError};
+use crate::m3u8_parser::ParsedM3u8;
+
+// 单个片段的下载耗时与播放时长对比
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SegmentTiming {
+    pub sequence: u64,
+    pub duration: f64,      // EXTINF 时长
+    pub download_secs: f64,
+    pub bytes: u64,
+    pub ok: bool,
+}
+
+impl SegmentTiming {
+    // 下载耗时超过播放时长
+    pub fn slower_than_realtime(&self) -> bool {
+        !self.ok || self.download_secs > self.duration
+    }
+}
+
+// 播放器模拟的统计结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlayerStats {
+    pub startup_ms: f64,         // 从开始请求播放列表到第一个片段下载完成（开始播放）的时间，未能开始播放时为 -1
+    pub rebuffer_count: usize,   // 开始播放后缓冲区耗尽的次数
+    pub stall_secs: f64,         // 开始播放后因缓冲区耗尽而卡住的总时长
+    pub slow_segments: usize,    // 下载耗时超过 EXTINF 时长（或下载失败）的片段数
+    pub segments: Vec<SegmentTiming>,
+}
+
+// 按下载顺序模拟播放缓冲区：第一个片段下载完成后开始播放，
+// 之后每个片段下载期间缓冲区按实时消耗，耗尽即卡顿，直到下一个片段到达
+fn simulate_playback(segments: &[SegmentTiming]) -> (Option<f64>, usize, f64) {
+    let mut clock = 0.0;
+    let mut buffer = 0.0;
+    let mut started_at = None;
+    let mut stalled = false;
+    let mut rebuffer_count = 0;
+    let mut stall_secs = 0.0;
+
+    for segment in segments {
+        if started_at.is_some() {
+            if buffer >= segment.download_secs {
+                buffer -= segment.download_secs;
+            } else {
+                stall_secs += segment.download_secs - buffer;
+                buffer = 0.0;
+                if !stalled {
+                    stalled = true;
+                    rebuffer_count += 1;
+                }
+            }
+        }
+        clock += segment.download_secs;
+
+        if segment.ok {
+            buffer += segment.duration;
+            stalled = false;
+            if started_at.is_none() {
+                started_at = Some(clock);
+            }
+        }
+    }
+
+    (started_at, rebuffer_count, stall_secs)
+}
+
+impl SpeedTester {
+    // 播放器模拟：按播放列表顺序逐个完整下载片段，对比下载耗时和 EXTINF 时长
+    pub(super) async fn measure_player(
+        &self,
+        parsed: ParsedM3u8,
+        stream_info: String,
+        start_time: Instant,
+    ) -> Result<HlsMeasurement> {
+        let media = parsed.media;
+        if media.segments.is_empty() {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let startup_offset = start_time.elapsed().as_secs_f64();
+        let deadline = start_time + secs(self.config.test_timeout_secs);
+        let mut timings = Vec::new();
+        let mut content = ContentSummary::default();
+        let mut media_info = None;
+        let mut bitrate = BitrateSamples::default();
+        let mut timing = FirstByteTiming::default();
+
+        for (index, segment) in media.segments.iter().take(self.config.hls_segments).enumerate() {
+            // 兜底超时前停止发起新的下载，保留已经得到的统计
+            if Instant::now() >= deadline {
+                if self.verbose {
+                    println!("达到测试时间限制，停止下载后续片段");
+                }
+                break;
+            }
+
+            let sequence = media.media_sequence + index as u64;
+            let download_start = Instant::now();
+            let result = self.download_segment(segment).await;
+            let download_secs = download_start.elapsed().as_secs_f64();
+
+            let timing = match result {
+                Ok(download) => {
+                    bitrate.add(segment.duration, &download);
+                    timing.add(&download);
+                    content.add(&download);
+                    if media_info.is_none() {
+                        media_info = download.media;
+                    }
+                    SegmentTiming {
+                        sequence,
+                        duration: segment.duration,
+                        download_secs,
+                        bytes: download.bytes,
+                        ok: true,
+                    }
+                }
+                Err(e) => {
+                    if self.verbose {
+                        println!("片段 #{} 下载失败: {}", sequence, e);
+                    }
+                    SegmentTiming {
+                        sequence,
+                        duration: segment.duration,
+                        download_secs,
+                        bytes: 0,
+                        ok: false,
+                    }
+                }
+            };
+            timings.push(timing);
+        }
+
+        let (started_at, rebuffer_count, stall_secs) = simulate_playback(&timings);
+        let segments_ok = timings.iter().filter(|timing| timing.ok).count();
+        if segments_ok == 0 {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let total_bytes: u64 = timings.iter().map(|timing| timing.bytes).sum();
+        let download_secs: f64 = timings.iter().map(|timing| timing.download_secs).sum();
+        let speed_kbps = if download_secs > 0.0 {
+            (total_bytes as f64 * 8.0) / download_secs / 1024.0
+        } else {
+            0.0
+        };
+
+        let stats = PlayerStats {
+            startup_ms: started_at.map_or(-1.0, |started_at| ((startup_offset + started_at) * 1000.0).round()),
+            rebuffer_count,
+            stall_secs,
+            slow_segments: timings.iter().filter(|timing| timing.slower_than_realtime()).count(),
+            segments: timings,
+        };
+
+        let details = format!(
+            "HLS播放模拟 - {}, 总片段: {}, 成功: {}, 起播 {:.0} ms, 卡顿 {} 次共 {:.2} 秒, 慢于实时的片段 {} 个, 平均速度: {:.0} kbps",
+            stream_info,
+            stats.segments.len(),
+            segments_ok,
+            stats.startup_ms,
+            stats.rebuffer_count,
+            stats.stall_secs,
+            stats.slow_segments,
+            speed_kbps
+        );
+
+        Ok(HlsMeasurement {
+            delay_ms: timing.delay_ms(),
+            timing,
+            speed_kbps,
+            bytes: total_bytes,
+            segments_total: stats.segments.len(),
+            segments_ok,
+            advertised_bandwidth: parsed.variant.map(|variant| variant.bandwidth).filter(|bandwidth| *bandwidth > 0),
+            details,
+            content_valid: content.valid(),
+            content_error: content.error,
+            media: media_info,
+            media_bitrate: bitrate.estimate(),
+            player: Some(stats),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(duration: f64, download_secs: f64, ok: bool) -> SegmentTiming {
+        SegmentTiming {
+            duration,
+            download_secs,
+            ok,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_simulate_smooth_playback() {
+        // 每个 4 秒的片段只需 1 秒下载，缓冲区不断增长
+        let segments = vec![timing(4.0, 1.0, true); 5];
+        let (started_at, rebuffer_count, stall_secs) = simulate_playback(&segments);
+
+        assert_eq!(started_at, Some(1.0));
+        assert_eq!(rebuffer_count, 0);
+        assert_eq!(stall_secs, 0.0);
+    }
+
+    #[test]
+    fn test_simulate_rebuffering() {
+        let segments = vec![
+            timing(4.0, 2.0, true),  // 2 秒起播，缓冲 4 秒
+            timing(4.0, 6.0, true),  // 播完 4 秒后卡住 2 秒
+            timing(4.0, 1.0, true),  // 缓冲 4 -> 3 + 4
+            timing(4.0, 3.0, false), // 下载失败，缓冲 7 -> 4
+            timing(4.0, 5.0, false), // 再次失败，缓冲耗尽后卡住 1 秒
+            timing(4.0, 2.0, false), // 仍在卡顿中，不计为新的卡顿事件
+        ];
+        let (started_at, rebuffer_count, stall_secs) = simulate_playback(&segments);
+
+        assert_eq!(started_at, Some(2.0));
+        assert_eq!(rebuffer_count, 2);
+        assert!((stall_secs - 5.0).abs() < 1e-9);
+
+        let (started_at, rebuffer_count, _) = simulate_playback(&[timing(4.0, 1.0, false)]);
+        assert_eq!(started_at, None);
+        assert_eq!(rebuffer_count, 0);
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use serde::Serialize;
+use tokio::time::timeout;
+
+use super::{secs, SpeedTester, TestError};
+use crate::m3u8_parser::{MasterPlaylist, MediaSegment, MediaType, Rendition, VariantStream};
+
+// 子播放列表引用的备选媒体（单独的音轨、字幕）的测试结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RenditionResult {
+    pub media_type: MediaType,
+    pub group_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    pub channels: Option<String>,
+    pub url: String,
+    pub required: bool,       // 音轨和 FORCED=YES 的字幕为必需，必需的备选媒体不可用时判定为降级
+    pub reachable: bool,
+    pub segments_total: usize,
+    pub segments_ok: usize,
+    pub speed_kbps: f64,
+    pub error: Option<String>,
+}
+
+impl RenditionResult {
+    fn new(rendition: &Rendition, url: &str) -> Self {
+        Self {
+            media_type: rendition.media_type,
+            group_id: rendition.group_id.clone(),
+            name: rendition.name.clone(),
+            language: rendition.language.clone(),
+            channels: rendition.channels.clone(),
+            url: url.to_string(),
+            required: rendition.media_type == MediaType::Audio || rendition.forced,
+            reachable: false,
+            segments_total: 0,
+            segments_ok: 0,
+            speed_kbps: 0.0,
+            error: None,
+        }
+    }
+}
+
+impl SpeedTester {
+    // 测试所选子播放列表通过 AUDIO / SUBTITLES 分组引用的、带 URI 的备选媒体，每个分组测试播放器会选择的一个
+    pub(super) async fn test_renditions(&self, master: &MasterPlaylist, variant: &VariantStream) -> Vec<RenditionResult> {
+        let groups = [
+            (MediaType::Audio, variant.audio.as_deref()),
+            (MediaType::Subtitles, variant.subtitles.as_deref()),
+        ];
+
+        let mut results = Vec::new();
+        for (media_type, group_id) in groups {
+            let Some(rendition) = group_id.and_then(|group_id| master.rendition_for(media_type, group_id)) else {
+                continue;
+            };
+            let Some(url) = &rendition.uri else {
+                continue;
+            };
+
+            if self.verbose {
+                println!("测试备选媒体: {} ({}, 分组 {})", url, rendition.name, rendition.group_id);
+            }
+
+            let mut result = RenditionResult::new(rendition, url);
+            match timeout(secs(self.config.test_timeout_secs), self.probe_rendition(url, &mut result)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => result.error = Some(e.to_string()),
+                Err(_) => result.error = Some("测试超时".to_string()),
+            }
+
+            if self.verbose {
+                match &result.error {
+                    Some(error) => println!("  不可用: {}", error),
+                    None => println!("  片段 {}/{}, 速度 {:.0} kbps", result.segments_ok, result.segments_total, result.speed_kbps),
+                }
+            }
+            results.push(result);
+        }
+
+        results
+    }
+
+    // 与子播放列表一样排除 DRM 加密，并检查密钥和初始化片段
+    async fn probe_rendition(&self, url: &str, result: &mut RenditionResult) -> Result<()> {
+        let media = self.m3u8_parser.fetch_media_playlist(url, &self.client).await?;
+        if let Some(system) = media.drm_system() {
+            return Err(TestError::Drm(system).into());
+        }
+        self.check_key(&media).await?;
+        if let Some(map) = media.segments.first().and_then(|segment| segment.map.as_ref()) {
+            self.check_init_segment(map).await?;
+        }
+        let segments: Vec<MediaSegment> = media.segments.into_iter().take(self.config.hls_segments).collect();
+        if segments.is_empty() {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let burst = self.burst_download(&segments).await;
+        result.segments_total = segments.len();
+        result.segments_ok = burst.segments_ok;
+        result.speed_kbps = burst.speed_kbps();
+        result.reachable = burst.segments_ok > 0;
+
+        if !result.reachable {
+            return Err(TestError::NoSegments.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::m3u8_parser::{parse_playlist, Playlist};
+    use crate::policy::{Policy, PolicyConfig, VerdictStatus};
+    use crate::speed_test::{SpeedTestResult, TestConfig};
+
+    #[tokio::test]
+    async fn test_required_rendition_unreachable() {
+        // 每个分组取 DEFAULT 项；端口 1 拒绝连接，备选媒体都不可用
+        let content = "#EXTM3U\n\
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",LANGUAGE=\"en\",URI=\"http://127.0.0.1:1/en.m3u8\"\n\
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"国语\",DEFAULT=YES,URI=\"http://127.0.0.1:1/zh.m3u8\"\n\
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"sub\",NAME=\"中文字幕\",DEFAULT=YES,URI=\"http://127.0.0.1:1/sub.m3u8\"\n\
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aud\",SUBTITLES=\"sub\"\nvideo.m3u8\n";
+        let Playlist::Master(master) = parse_playlist(content, "http://127.0.0.1:1/master.m3u8").unwrap() else {
+            panic!("应解析为主播放列表");
+        };
+
+        let tester = SpeedTester::new(false, TestConfig::default());
+        let renditions = tester.test_renditions(&master, &master.variants[0]).await;
+        let selected: Vec<(&str, bool, bool)> =
+            renditions.iter().map(|rendition| (rendition.name.as_str(), rendition.required, rendition.reachable)).collect();
+        assert_eq!(selected, vec![("国语", true, false), ("中文字幕", false, false)]);
+        assert!(renditions.iter().all(|rendition| rendition.error.is_some()));
+
+        // 主流合格，但必需的音轨不可用
+        let result = SpeedTestResult {
+            success: true,
+            speed_kbps: 4000.0,
+            renditions: Some(renditions),
+            ..Default::default()
+        };
+        let verdict = Policy::new(PolicyConfig::default()).evaluate(&result);
+        assert_eq!(verdict.status, VerdictStatus::Degraded);
+        assert!(verdict.reason.contains("国语"));
+    }
+}
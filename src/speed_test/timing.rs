@@ -0,0 +1,121 @@
+use anyhow::{Result, anyhow};
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::timeout;
+use url::{Host, Url};
+
+use super::{secs, SpeedTestResult, SpeedTester};
+
+// 单独建立的探测连接上各阶段的耗时（毫秒），是测速请求建连耗时的近似值
+#[derive(Debug, Clone, Default)]
+pub(super) struct ConnectionTiming {
+    dns_ms: Option<f64>,           // 主机名为 IP 地址时没有解析
+    tcp_connect_ms: Option<f64>,
+    tls_handshake_ms: Option<f64>, // 只有 https
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+impl SpeedTester {
+    // 测速请求复用连接池中的连接，无法拆分各阶段耗时，因此在第一个媒体请求之前对其主机单独建立一个连接，
+    // 依次测量 DNS 解析、TCP 连接和 TLS 握手，不发送 HTTP 请求；每个阶段受连接超时限制，
+    // 调用方在测试的兜底超时内调用。
+    // 结果只是近似值：探测连接不是测速请求实际使用的连接，可能解析到不同的地址、TLS 实现和会话复用也不同，
+    // 并且多一次握手，耗时计入测试时间和 first_media_byte_ms
+    pub(super) async fn probe_connection(&self, url: &str) -> Result<ConnectionTiming> {
+        let url = Url::parse(url)?;
+        let port = url.port_or_known_default().ok_or_else(|| anyhow!("无法确定端口: {}", url))?;
+        let limit = secs(self.config.connect_timeout_secs);
+        let mut timing = ConnectionTiming::default();
+
+        let (address, domain) = match url.host() {
+            Some(Host::Domain(domain)) => {
+                let start = Instant::now();
+                let address = timeout(limit, lookup_host((domain, port)))
+                    .await
+                    .map_err(|_| anyhow!("DNS 解析超时"))??
+                    .next()
+                    .ok_or_else(|| anyhow!("DNS 解析没有结果: {}", domain))?;
+                timing.dns_ms = Some(elapsed_ms(start));
+                (address, domain.to_string())
+            }
+            Some(Host::Ipv4(ip)) => (SocketAddr::from((ip, port)), ip.to_string()),
+            Some(Host::Ipv6(ip)) => (SocketAddr::from((ip, port)), ip.to_string()),
+            None => return Err(anyhow!("URL 中没有主机名: {}", url)),
+        };
+
+        let start = Instant::now();
+        let stream = timeout(limit, TcpStream::connect(address))
+            .await
+            .map_err(|_| anyhow!("TCP 连接超时"))??;
+        timing.tcp_connect_ms = Some(elapsed_ms(start));
+
+        if url.scheme() == "https" {
+            // 与测速客户端一样不校验证书
+            let connector = native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true)
+                .build()?;
+            let start = Instant::now();
+            timeout(limit, tokio_native_tls::TlsConnector::from(connector).connect(&domain, stream))
+                .await
+                .map_err(|_| anyhow!("TLS 握手超时"))??;
+            timing.tls_handshake_ms = Some(elapsed_ms(start));
+        }
+
+        Ok(timing)
+    }
+
+    // 探测第一个媒体请求的主机，失败时各阶段耗时为空，不影响测试本身
+    pub(super) async fn media_connection(&self, url: &str) -> ConnectionTiming {
+        match self.probe_connection(url).await {
+            Ok(timing) => timing,
+            Err(e) => {
+                if self.verbose {
+                    println!("连接耗时探测失败: {}", e);
+                }
+                ConnectionTiming::default()
+            }
+        }
+    }
+}
+
+impl SpeedTestResult {
+    pub(super) fn with_connection(self, timing: ConnectionTiming) -> Self {
+        Self {
+            dns_ms: timing.dns_ms,
+            tcp_connect_ms: timing.tcp_connect_ms,
+            tls_handshake_ms: timing.tls_handshake_ms,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speed_test::TestConfig;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_probe_connection() {
+        let tester = SpeedTester::new(false, TestConfig::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // IP 地址不需要解析，http 没有 TLS 握手
+        let timing = tester.probe_connection(&format!("http://127.0.0.1:{}/live.ts", port)).await.unwrap();
+        assert!(timing.dns_ms.is_none() && timing.tls_handshake_ms.is_none());
+        assert!(timing.tcp_connect_ms.is_some());
+
+        // 连接失败时整个探测失败，结果中不填耗时
+        drop(listener);
+        let url = format!("http://127.0.0.1:{}/live.ts", port);
+        assert!(tester.probe_connection(&url).await.is_err());
+        let result = SpeedTestResult::default().with_connection(tester.media_connection(&url).await);
+        assert!(result.tcp_connect_ms.is_none());
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::time::error::Elapsed;
+use tokio::time::timeout_at;
+
+use super::{
+    ConnectionTiming, FirstByteTiming, HlsMeasurement, InitSegment, MediaBitrate, SpeedTester, TestConfig, TestError,
+};
+use crate::codec::MediaInfo;
+use crate::drm::DrmSystem;
+use crate::m3u8_parser::{MasterPlaylist, MediaSegment, VariantStream};
+
+// 单个子播放列表的测试结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VariantResult {
+    pub url: String,
+    pub bandwidth: u64,               // 声明的 BANDWIDTH（bps）
+    pub resolution: Option<String>,
+    pub codecs: Option<String>,
+    pub reachable: bool,              // 子播放列表可获取且至少一个片段下载成功
+    pub delay_ms: f64,
+    pub speed_kbps: f64,
+    pub bytes: u64,
+    pub segments_total: usize,
+    pub segments_ok: usize,
+    pub sustainable: bool,            // 实测速度不低于声明码率
+    pub ttfb_ms: Option<f64>,         // 片段请求中最短的首字节时间
+    pub init_segment: Option<InitSegment>,
+    pub content_valid: Option<bool>,  // 片段是否为有效的 MPEG-TS，不检查内容时为空
+    pub content_error: Option<String>,
+    pub media: Option<MediaInfo>,
+    pub error: Option<String>,
+    // 选为测试结果时复制到 HlsMeasurement
+    #[serde(skip)]
+    timing: FirstByteTiming,
+    #[serde(skip)]
+    connection: ConnectionTiming,
+    #[serde(skip)]
+    media_bitrate: Option<MediaBitrate>,
+}
+
+impl VariantResult {
+    fn new(variant: &VariantStream) -> Self {
+        Self {
+            url: variant.uri.clone(),
+            bandwidth: variant.bandwidth,
+            resolution: variant.resolution.map(|resolution| format!("{}x{}", resolution.width, resolution.height)),
+            codecs: variant.codecs.clone(),
+            delay_ms: -1.0,
+            ..Default::default()
+        }
+    }
+}
+
+// 按分辨率高度和声明码率筛选要测试的子播放列表，没有声明分辨率的不受高度限制
+fn is_selected(config: &TestConfig, variant: &VariantStream) -> bool {
+    let height_ok = match (config.variant_max_height, variant.resolution) {
+        (Some(max_height), Some(resolution)) => resolution.height <= max_height,
+        _ => true,
+    };
+    let min_ok = config.variant_min_bandwidth.is_none_or(|min| variant.bandwidth >= min);
+    let max_ok = config.variant_max_bandwidth.is_none_or(|max| variant.bandwidth <= max);
+
+    height_ok && min_ok && max_ok
+}
+
+// 取实测能够承载的最高码率子播放列表，都不能承载时取实测速度最快的可用子播放列表
+fn choose_variant<'a>(results: impl Iterator<Item = &'a VariantResult> + Clone) -> Option<&'a VariantResult> {
+    results
+        .clone()
+        .filter(|result| result.sustainable)
+        .max_by_key(|result| result.bandwidth)
+        .or_else(|| results.filter(|result| result.reachable).max_by(|a, b| a.speed_kbps.total_cmp(&b.speed_kbps)))
+}
+
+impl SpeedTester {
+    // 解析播放列表和所有子播放列表（或非主播放列表）的测试共用 budget，超出后不再测试剩下的子播放列表
+    pub(super) async fn test_hls_variants(&self, url: &str, budget: Duration) -> Result<Result<HlsMeasurement>, Elapsed> {
+        let test_start = Instant::now();
+        let deadline = tokio::time::Instant::from_std(test_start + budget);
+
+        let mut parsed = match timeout_at(deadline, self.m3u8_parser.parse_m3u8(url, &self.client)).await? {
+            Ok(parsed) => parsed,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        match parsed.master.take() {
+            Some(master) => Ok(self.measure_variants(master, deadline).await),
+            None => {
+                if self.verbose {
+                    println!("不是主播放列表，按单个媒体播放列表测试");
+                }
+                timeout_at(deadline, self.measure_playlist(parsed, test_start)).await
+            }
+        }
+    }
+
+    // 依次测试主播放列表中的每个（符合条件的）子播放列表，按 choose_variant 选择结果
+    async fn measure_variants(&self, master: MasterPlaylist, deadline: tokio::time::Instant) -> Result<HlsMeasurement> {
+        if let Some(system) = master.session_keys.iter().find_map(|key| key.drm_system()) {
+            return Err(TestError::Drm(system).into());
+        }
+
+        let selected: Vec<&VariantStream> = master
+            .variants
+            .iter()
+            .filter(|variant| is_selected(&self.config, variant))
+            .collect();
+
+        if selected.is_empty() {
+            return Err(anyhow!("主播放列表中没有符合条件的子播放列表（共 {} 个）", master.variants.len()));
+        }
+
+        let mut results = Vec::new();
+        let mut drm: Option<DrmSystem> = None;
+        for variant in selected {
+            if self.verbose {
+                println!("测试子播放列表: {} ({})", variant.uri, variant.describe());
+            }
+
+            let mut result = VariantResult::new(variant);
+            if tokio::time::Instant::now() >= deadline {
+                result.error = Some("超出测试时间，未测试".to_string());
+                results.push(result);
+                continue;
+            }
+            match timeout_at(deadline, self.probe_variant(variant, &mut result)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    if let Some(TestError::Drm(system)) = e.downcast_ref::<TestError>() {
+                        drm = Some(*system);
+                    }
+                    result.error = Some(e.to_string());
+                }
+                Err(_) => result.error = Some("测试超时".to_string()),
+            }
+
+            if self.verbose {
+                match &result.error {
+                    Some(error) => println!("  不可用: {}", error),
+                    None => println!(
+                        "  片段 {}/{}, 速度 {:.0} kbps, {}",
+                        result.segments_ok,
+                        result.segments_total,
+                        result.speed_kbps,
+                        if result.sustainable { "能承载声明码率" } else { "达不到声明码率" }
+                    ),
+                }
+            }
+            results.push(result);
+        }
+
+        // 内容无效的子播放列表只在没有其他可用子播放列表时选择
+        let chosen = choose_variant(results.iter().filter(|result| result.content_valid != Some(false)))
+            .or_else(|| choose_variant(results.iter()))
+            .cloned();
+
+        // 没有可用的子播放列表时，有子播放列表使用 DRM 则整个流按 DRM 加密处理
+        let Some(chosen) = chosen else {
+            return Err(drm.map_or(TestError::NoSegments, TestError::Drm).into());
+        };
+        let chosen_variant = master.variants.iter().find(|variant| variant.uri == chosen.url).cloned();
+
+        let reachable = results.iter().filter(|result| result.reachable).count();
+        let sustainable = results.iter().filter(|result| result.sustainable).count();
+        let mut details = format!(
+            "HLS子播放列表测试 - 可用 {}/{}, 能承载声明码率 {} 个, 选择 {} bps{}, 片段 {}/{}, 平均速度: {:.0} kbps",
+            reachable,
+            results.len(),
+            sustainable,
+            chosen.bandwidth,
+            chosen.resolution.as_ref().map(|resolution| format!(" ({})", resolution)).unwrap_or_default(),
+            chosen.segments_ok,
+            chosen.segments_total,
+            chosen.speed_kbps
+        );
+        if let Some(media) = &chosen.media {
+            details += &format!(", 码流: {}", media.label());
+        }
+
+        Ok(HlsMeasurement {
+            delay_ms: chosen.delay_ms,
+            timing: chosen.timing,
+            connection: chosen.connection,
+            speed_kbps: chosen.speed_kbps,
+            bytes: chosen.bytes,
+            segments_total: chosen.segments_total,
+            segments_ok: chosen.segments_ok,
+            advertised_bandwidth: Some(chosen.bandwidth).filter(|bandwidth| *bandwidth > 0),
+            details,
+            selected_variant: Some(chosen.url),
+            init_segment: chosen.init_segment,
+            content_valid: chosen.content_valid,
+            content_error: chosen.content_error,
+            media: chosen.media,
+            media_bitrate: chosen.media_bitrate,
+            variants: Some(results),
+            rendition_source: chosen_variant.map(|variant| (master, variant)),
+            ..Default::default()
+        })
+    }
+
+    async fn probe_variant(&self, variant: &VariantStream, result: &mut VariantResult) -> Result<()> {
+        let media = self.m3u8_parser.fetch_media_playlist(&variant.uri, &self.client).await?;
+        if let Some(system) = media.drm_system() {
+            return Err(TestError::Drm(system).into());
+        }
+        if let Some(segment) = media.segments.first() {
+            result.connection = self.media_connection(&segment.uri).await;
+        }
+        self.check_key(&media).await?;
+        if let Some(map) = media.segments.first().and_then(|segment| segment.map.as_ref()) {
+            result.init_segment = Some(self.check_init_segment(map).await?);
+        }
+
+        let segments: Vec<MediaSegment> = media.segments.into_iter().take(self.config.hls_segments).collect();
+        if segments.is_empty() {
+            return Err(TestError::NoSegments.into());
+        }
+
+        let burst = self.burst_download(&segments).await;
+        result.segments_total = segments.len();
+        result.segments_ok = burst.segments_ok;
+        result.bytes = burst.bytes;
+        result.delay_ms = burst.timing.delay_ms();
+        result.ttfb_ms = burst.timing.ttfb_ms;
+        result.timing = burst.timing;
+        result.speed_kbps = burst.speed_kbps();
+        result.reachable = burst.segments_ok > 0;
+        // speed_kbps 以 1024 bit 为单位，BANDWIDTH 单位为 bps
+        result.sustainable = result.reachable && result.speed_kbps * 1024.0 >= variant.bandwidth as f64;
+        result.content_valid = burst.content.valid();
+        result.content_error = burst.content.error;
+        result.media = burst.media;
+        result.media_bitrate = burst.bitrate.estimate();
+
+        if !result.reachable {
+            return Err(TestError::NoSegments.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::m3u8_parser::Resolution;
+
+    fn variant(bandwidth: u64, height: Option<u64>) -> VariantStream {
+        VariantStream {
+            bandwidth,
+            resolution: height.map(|height| Resolution { width: height * 16 / 9, height }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_variant_selection() {
+        let config = TestConfig {
+            variant_max_height: Some(1080),
+            variant_min_bandwidth: Some(1_000_000),
+            variant_max_bandwidth: Some(8_000_000),
+            ..Default::default()
+        };
+
+        assert!(is_selected(&config, &variant(4_000_000, Some(1080))));
+        assert!(is_selected(&config, &variant(4_000_000, None)));
+        assert!(!is_selected(&config, &variant(12_000_000, Some(1080))));
+        assert!(!is_selected(&config, &variant(6_000_000, Some(2160))));
+        assert!(!is_selected(&config, &variant(500_000, Some(360))));
+        assert!(is_selected(&TestConfig::default(), &variant(500_000, Some(2160))));
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::channel::{self, Channel, ChannelListFormat};
+
+// 单个订阅源的下载结果
+#[derive(Debug, Clone)]
+pub struct SourceReport {
+    pub url: String,
+    pub success: bool,
+    pub format: Option<ChannelListFormat>,
+    pub channel_count: usize,
+    pub error: Option<String>,
+}
+
+pub struct SubscriptionFetcher {
+    client: Client,
+    verbose: bool,
+}
+
+impl SubscriptionFetcher {
+    // client 与测速使用相同的配置创建（见 speed_test::build_client）
+    pub fn new(client: Client, verbose: bool) -> Self {
+        Self { client, verbose }
+    }
+
+    // 下载所有订阅源，合并为一个频道列表
+    pub async fn fetch_all(&self, sources: &[String]) -> (Vec<Channel>, Vec<SourceReport>) {
+        let tasks: Vec<_> = sources
+            .iter()
+            .map(|source| self.fetch_source(source))
+            .collect();
+
+        let results = futures::future::join_all(tasks).await;
+        merge_sources(sources, results)
+    }
+
+    async fn fetch_source(&self, url: &str) -> Result<(ChannelListFormat, Vec<Channel>)> {
+        // 频道列表可能输出到 stdout，日志统一写到 stderr
+        if self.verbose {
+            eprintln!("下载: {}", url);
+        }
+
+        let response = self.client.get(url).send().await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("HTTP错误: {}", response.status()));
+        }
+
+        let content = response.text().await?;
+        let (format, channels) = channel::parse_channel_list(&content);
+
+        if channels.is_empty() {
+            return Err(anyhow!("未解析到有效频道"));
+        }
+
+        Ok((format, channels))
+    }
+}
+
+// 按订阅源顺序合并频道列表，并生成每个订阅源的下载报告
+fn merge_sources(
+    sources: &[String],
+    results: Vec<Result<(ChannelListFormat, Vec<Channel>)>>,
+) -> (Vec<Channel>, Vec<SourceReport>) {
+    let mut all_channels = Vec::new();
+    let mut reports = Vec::with_capacity(sources.len());
+
+    for (source, result) in sources.iter().zip(results) {
+        match result {
+            Ok((format, channels)) => {
+                reports.push(SourceReport {
+                    url: source.clone(),
+                    success: true,
+                    format: Some(format),
+                    channel_count: channels.len(),
+                    error: None,
+                });
+                all_channels.extend(channels);
+            }
+            Err(e) => {
+                reports.push(SourceReport {
+                    url: source.clone(),
+                    success: false,
+                    format: None,
+                    channel_count: 0,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    (all_channels, reports)
+}
+
+// 读取订阅源列表文件
+pub fn load_subscription_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("无法读取订阅源文件 {}: {}", path.display(), e))?;
+
+    Ok(parse_subscription_list(&content))
+}
+
+// 每行一个地址，忽略空行和 # 注释，重复的地址只保留第一个
+fn parse_subscription_list(content: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|line| seen.insert(*line))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subscription_list() {
+        let content = "# 订阅源\n\nhttp://a.com/iptv.m3u\n  http://b.com/list.txt  \n# http://c.com/disabled.m3u\nhttp://a.com/iptv.m3u\n";
+        let sources = parse_subscription_list(content);
+        assert_eq!(sources, vec!["http://a.com/iptv.m3u", "http://b.com/list.txt"]);
+    }
+
+    #[test]
+    fn test_merge_sources() {
+        let sources = vec!["http://a.com/iptv.m3u".to_string(), "http://b.com/dead.txt".to_string(), "http://c.com/list.txt".to_string()];
+        let results = vec![
+            Ok(channel::parse_channel_list("#EXTM3U\n#EXTINF:-1 group-title=\"央视\",CCTV-1\nhttp://a.com/1.m3u8\n")),
+            Err(anyhow!("HTTP错误: 404 Not Found")),
+            Ok(channel::parse_channel_list("卫视,#genre#\n湖南卫视,http://c.com/hunan.m3u8\nCCTV-1,http://c.com/1.m3u8\n")),
+        ];
+        let (channels, reports) = merge_sources(&sources, results);
+
+        // 按订阅源顺序合并，不同来源的同名频道都保留
+        let urls: Vec<&str> = channels.iter().map(|channel| channel.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://a.com/1.m3u8", "http://c.com/hunan.m3u8", "http://c.com/1.m3u8"]);
+        assert_eq!(channels[0].group_title.as_deref(), Some("央视"));
+
+        let summary: Vec<(bool, Option<ChannelListFormat>, usize)> =
+            reports.iter().map(|report| (report.success, report.format, report.channel_count)).collect();
+        assert_eq!(
+            summary,
+            vec![(true, Some(ChannelListFormat::M3u), 1), (false, None, 0), (true, Some(ChannelListFormat::Txt), 2)]
+        );
+        assert_eq!(reports[1].error.as_deref(), Some("HTTP错误: 404 Not Found"));
+    }
+}